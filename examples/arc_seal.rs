@@ -48,7 +48,8 @@ async fn main() {
     let dkim_result = resolver.verify_dkim(&authenticated_message).await;
 
     // Build Authenticated-Results header
-    let auth_results = AuthenticationResults::new("mx.mydomain.org")
+    let auth_results = AuthenticationResults::try_new("mx.mydomain.org")
+        .unwrap()
         .with_dkim_results(&dkim_result, "sender@example.org")
         .with_arc_result(&arc_result, "127.0.0.1".parse().unwrap());
 
@@ -63,6 +63,18 @@ pub struct Seal {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Results {
     pub(crate) i: u32,
+    pub(crate) authserv_id: String,
+    pub(crate) results: Vec<MethodResult>,
+}
+
+/// A single `method=result` entry of an ARC-Authentication-Results header,
+/// such as `dkim=pass header.d=example.org` (RFC 8601, Section 2.2).
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct MethodResult {
+    pub(crate) method: String,
+    pub(crate) result: String,
+    pub(crate) reason: Option<String>,
+    pub(crate) properties: Vec<(String, String)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -80,13 +92,113 @@ pub struct Set<'x> {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
-pub(crate) enum ChainValidation {
+pub enum ChainValidation {
     #[default]
     None,
     Fail,
     Pass,
 }
 
+impl Signature {
+    /// Returns the instance number (`i=`) of this ARC-Message-Signature.
+    pub fn instance(&self) -> u32 {
+        self.i
+    }
+
+    /// Returns the names of the signed headers (`h=`).
+    pub fn signed_headers(&self) -> &[String] {
+        &self.h
+    }
+}
+
+impl Seal {
+    /// Returns the instance number (`i=`) of this ARC-Seal.
+    pub fn instance(&self) -> u32 {
+        self.i
+    }
+
+    /// Returns the chain validation status (`cv=`) recorded by the sealer.
+    pub fn chain_validation(&self) -> &ChainValidation {
+        &self.cv
+    }
+}
+
+impl Results {
+    /// Returns the instance number (`i=`) of this ARC-Authentication-Results.
+    pub fn instance(&self) -> u32 {
+        self.i
+    }
+
+    /// Returns the identifier of the server that performed the checks.
+    pub fn authserv_id(&self) -> &str {
+        &self.authserv_id
+    }
+
+    /// Returns the results of all methods, in the order they were recorded.
+    pub fn method_results(&self) -> &[MethodResult] {
+        &self.results
+    }
+
+    /// Returns the first result recorded for `method` (for example `spf`,
+    /// `dkim` or `dmarc`), compared case-insensitively.
+    pub fn method_result(&self, method: &str) -> Option<&MethodResult> {
+        self.results
+            .iter()
+            .find(|r| r.method.eq_ignore_ascii_case(method))
+    }
+}
+
+impl MethodResult {
+    /// Returns the lowercase name of the authentication method.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the lowercase result, such as `pass` or `fail`.
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    /// Returns the `reason=` given for the result, if any.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// Returns the `ptype.property=value` pairs describing the result,
+    /// with lowercase property names.
+    pub fn properties(&self) -> &[(String, String)] {
+        &self.properties
+    }
+
+    /// Returns the value of a property such as `header.d` or `smtp.mailfrom`,
+    /// compared case-insensitively.
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl<'x> Set<'x> {
+    /// Returns the instance number shared by the headers of this set.
+    pub fn instance(&self) -> u32 {
+        self.seal.header.i
+    }
+
+    pub fn signature(&self) -> &Header<'x, &'x Signature> {
+        &self.signature
+    }
+
+    pub fn seal(&self) -> &Header<'x, &'x Seal> {
+        &self.seal
+    }
+
+    pub fn results(&self) -> &Header<'x, &'x Results> {
+        &self.results
+    }
+}
+
 impl VerifySignature for Signature {
     fn signature(&self) -> &[u8] {
         &self.b
@@ -132,6 +244,18 @@ impl<'x> ArcOutput<'x> {
     pub fn can_be_sealed(&self) -> bool {
         self.set.is_empty() || self.set.last().unwrap().seal.header.cv != ChainValidation::Fail
     }
+
+    /// Returns the ARC-Authentication-Results of the highest instance when
+    /// the chain validated, which record the verdicts reached by the last
+    /// intermediary before it forwarded the message. Returns `None` if the
+    /// message has no ARC chain or the chain did not validate.
+    pub fn sealed_auth_results(&self) -> Option<&'x Results> {
+        if self.result == DkimResult::Pass {
+            self.set.last().map(|set| set.results.header)
+        } else {
+            None
+        }
+    }
 }
 
 impl<'x> Default for ArcOutput<'x> {
@@ -139,6 +263,7 @@ impl<'x> Default for ArcOutput<'x> {
         Self {
             result: DkimResult::None,
             set: Vec::new(),
+            key_record: None,
         }
     }
 }
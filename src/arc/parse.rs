@@ -16,7 +16,7 @@ use crate::{
     Error,
 };
 
-use super::{ChainValidation, Results, Seal, Signature};
+use super::{ChainValidation, MethodResult, Results, Seal, Signature};
 
 use crate::common::parse::*;
 
@@ -160,7 +160,12 @@ impl Seal {
 impl Results {
     #[allow(clippy::while_let_on_iterator)]
     pub fn parse(header: &'_ [u8]) -> crate::Result<Self> {
-        let mut results = Results { i: 0 };
+        let mut results = Results {
+            i: 0,
+            authserv_id: String::new(),
+            results: Vec::new(),
+        };
+        let value = header;
         let mut header = header.iter();
 
         while let Some(key) = header.key() {
@@ -173,10 +178,108 @@ impl Results {
             }
         }
 
-        if (1..=50).contains(&results.i) {
-            Ok(results)
+        if !(1..=50).contains(&results.i) {
+            return Err(Error::ArcInvalidInstance(results.i));
+        }
+
+        // The instance tag is followed by the authserv-id and a list of
+        // method results (RFC 8617, Section 4.1.1)
+        let mut segments = result_segments(value)
+            .into_iter()
+            .filter(|words| !words.is_empty())
+            .skip_while(|words| !words[0].to_ascii_lowercase().starts_with("i="))
+            .skip(1);
+        if let Some(words) = segments.next() {
+            results.authserv_id = words[0].clone();
+        }
+        for words in segments {
+            let Some((method, result)) = words[0].split_once('=') else {
+                continue;
+            };
+            let mut method_result = MethodResult {
+                method: method
+                    .split_once('/')
+                    .map_or(method, |(method, _)| method)
+                    .to_ascii_lowercase(),
+                result: result.to_ascii_lowercase(),
+                ..Default::default()
+            };
+            for word in &words[1..] {
+                if let Some((name, value)) = word.split_once('=') {
+                    if name.eq_ignore_ascii_case("reason") {
+                        method_result.reason = Some(value.to_string());
+                    } else {
+                        method_result
+                            .properties
+                            .push((name.to_ascii_lowercase(), value.to_string()));
+                    }
+                }
+            }
+            results.results.push(method_result);
+        }
+
+        Ok(results)
+    }
+}
+
+/// Splits an Authentication-Results value into its `;` separated segments,
+/// each one a list of words. Comments are removed, quoted strings are
+/// unquoted and whitespace around `=` is ignored.
+fn result_segments(value: &[u8]) -> Vec<Vec<String>> {
+    let mut segments = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word = Vec::new();
+    let mut comment_depth = 0;
+    let mut in_quote = false;
+    let mut iter = value.iter();
+
+    let flush = |word: &mut Vec<u8>, words: &mut Vec<String>| {
+        if !word.is_empty() {
+            let value = String::from_utf8_lossy(word).into_owned();
+            match words.last_mut() {
+                Some(last) if last.ends_with('=') || value.starts_with('=') => {
+                    last.push_str(&value)
+                }
+                _ => words.push(value),
+            }
+            word.clear();
+        }
+    };
+
+    while let Some(&ch) = iter.next() {
+        if in_quote {
+            match ch {
+                b'\\' => word.extend(iter.next()),
+                b'"' => in_quote = false,
+                _ => word.push(ch),
+            }
+        } else if comment_depth > 0 {
+            match ch {
+                b'(' => comment_depth += 1,
+                b')' => comment_depth -= 1,
+                b'\\' => {
+                    iter.next();
+                }
+                _ => (),
+            }
         } else {
-            Err(Error::ArcInvalidInstance(results.i))
+            match ch {
+                b'"' => in_quote = true,
+                b'(' => {
+                    flush(&mut word, &mut words);
+                    comment_depth = 1;
+                }
+                b';' => {
+                    flush(&mut word, &mut words);
+                    segments.push(std::mem::take(&mut words));
+                }
+                b' ' | b'\t' | b'\r' | b'\n' => flush(&mut word, &mut words),
+                _ => word.push(ch),
+            }
         }
     }
+    flush(&mut word, &mut words);
+    segments.push(words);
+
+    segments
 }
@@ -63,8 +63,9 @@ impl<T: SigningKey<Hasher = Sha256>> ArcSealer<T, Done> {
         }
 
         // Canonicalize body
+        let canonical_body = set.signature.cb.canonical_body(message.raw_body(), 0);
         if set.signature.l > 0 {
-            set.signature.l = (message.raw_message.len() - message.body_offset) as u64;
+            set.signature.l = canonical_body.len();
         }
         let ha = HashAlgorithm::from(set.signature.a);
         if let Some((_, _, _, bh)) = message
@@ -75,15 +76,7 @@ impl<T: SigningKey<Hasher = Sha256>> ArcSealer<T, Done> {
             // Use cached hash
             set.signature.bh = base64_encode(bh)?;
         } else {
-            let hash = self.key.hash(
-                set.signature.cb.canonical_body(
-                    message
-                        .raw_message
-                        .get(message.body_offset..)
-                        .unwrap_or_default(),
-                    u64::MAX,
-                ),
-            );
+            let hash = self.key.hash(canonical_body);
             set.signature.bh = base64_encode(hash.as_ref())?;
         }
 
@@ -208,7 +201,7 @@ mod test {
             parse::TxtRecordParser,
             verify::DomainKey,
         },
-        dkim::DkimSigner,
+        dkim::{Canonicalization, DkimSigner},
         AuthenticatedMessage, AuthenticationResults, DkimResult, Resolver,
     };
 
@@ -305,6 +298,57 @@ mod test {
         //println!("{}", raw_message);
     }
 
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn arc_seal_body_length() {
+        let message = concat!(
+            "From: queso@manchego.org\r\n",
+            "To: affumicata@scamorza.org\r\n",
+            "Subject: Say cheese\r\n",
+            "\r\n",
+            "We need to settle which one of us\n",
+            "is tastier.\n"
+        );
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "rsa._domainkey.manchego.org.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+
+        // l= counts the canonicalized body, bare LFs included
+        let auth_message =
+            AuthenticatedMessage::parse_with_opts(message.as_bytes(), false).unwrap();
+        let auth_results = AuthenticationResults::try_new("manchego.org").unwrap();
+        let arc = ArcSealer::from_key(pk_rsa)
+            .domain("manchego.org")
+            .selector("rsa")
+            .headers(["From", "To", "Subject"])
+            .body_canonicalization(Canonicalization::Simple)
+            .body_length(true)
+            .seal(&auth_message, &auth_results, &Default::default())
+            .unwrap();
+        assert_eq!(
+            arc.signature.l,
+            message.split_once("\r\n\r\n").unwrap().1.len() as u64 + 2
+        );
+
+        let raw_message = format!("{}{}{message}", arc.to_header(), auth_results.to_header());
+        let auth_message =
+            AuthenticatedMessage::parse_with_opts(raw_message.as_bytes(), false).unwrap();
+        assert_eq!(
+            resolver.verify_arc(&auth_message).await.result(),
+            &DkimResult::Pass
+        );
+    }
+
     async fn arc_verify_and_seal(
         resolver: &Resolver,
         raw_message: &str,
@@ -320,7 +364,9 @@ mod test {
             "ARC validation failed: {:?}",
             arc_result.result()
         );
-        let auth_results = AuthenticationResults::new(d).with_dkim_results(&dkim_result, d);
+        let auth_results = AuthenticationResults::try_new(d)
+            .unwrap()
+            .with_dkim_results(&dkim_result, d);
         let arc = ArcSealer::from_key(pk)
             .domain(d)
             .selector(s)
@@ -8,15 +8,14 @@
  * except according to those terms.
  */
 
-use std::time::SystemTime;
-
 use crate::{
     common::{
         crypto::HashAlgorithm,
         headers::Header,
+        metrics::Metrics,
         verify::{DomainKey, VerifySignature},
     },
-    dkim::{verify::Verifier, Canonicalization},
+    dkim::{verify::Verifier, Canonicalization, DkimOptions},
     ArcOutput, AuthenticatedMessage, DkimResult, Error, Resolver,
 };
 
@@ -25,89 +24,84 @@ use super::{ChainValidation, Set};
 impl Resolver {
     /// Verifies ARC headers of an RFC5322 message.
     pub async fn verify_arc<'x>(&self, message: &'x AuthenticatedMessage<'x>) -> ArcOutput<'x> {
-        let arc_headers = message.ams_headers.len();
-        if arc_headers == 0 {
+        self.verify_arc_with_opts(message, &DkimOptions::default())
+            .await
+    }
+
+    /// Verifies ARC headers of an RFC5322 message using the given
+    /// [`DkimOptions`]. Only [`DkimOptions::key_records`] applies to ARC.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(sets = message.ams_headers.len()))
+    )]
+    pub async fn verify_arc_with_opts<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        opts: &DkimOptions,
+    ) -> ArcOutput<'x> {
+        let mut metrics = Metrics::new();
+        let output = self.verify_arc_(message, opts, &mut metrics).await;
+        metrics.finish("arc", output.result.as_keyword());
+        output
+    }
+
+    async fn verify_arc_<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        opts: &DkimOptions,
+        metrics: &mut Metrics,
+    ) -> ArcOutput<'x> {
+        if message.ams_headers.is_empty() {
             return ArcOutput::default();
-        } else if arc_headers > 50 {
-            return ArcOutput::default().with_result(DkimResult::Fail(Error::ArcChainTooLong));
-        } else if (arc_headers != message.as_headers.len())
-            || (arc_headers != message.aar_headers.len())
-        {
-            return ArcOutput::default().with_result(DkimResult::Fail(Error::ArcBrokenChain));
         }
 
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-
+        // Group ARC headers in sets
+        let (set, err) = message.arc_sets_();
         let mut output = ArcOutput {
             result: DkimResult::None,
-            set: Vec::with_capacity(message.aar_headers.len() / 3),
+            set,
+            key_record: None,
         };
+        match err {
+            Some(
+                err @ (Error::ArcChainTooLong
+                | Error::ArcBrokenChain
+                | Error::ArcInvalidInstance(_)),
+            ) => return output.with_result(DkimResult::Fail(err)),
+            Some(err) => return output.with_result(DkimResult::Neutral(err)),
+            None => (),
+        }
 
-        // Group ARC headers in sets
-        for (pos, ((seal_, signature_), results_)) in message
-            .as_headers
-            .iter()
-            .zip(message.ams_headers.iter())
-            .zip(message.aar_headers.iter())
-            .enumerate()
-        {
-            let seal = match &seal_.header {
-                Ok(seal) => seal,
-                Err(err) => return output.with_result(DkimResult::Neutral(err.clone())),
-            };
-            let signature = match &signature_.header {
-                Ok(signature) => signature,
-                Err(err) => return output.with_result(DkimResult::Neutral(err.clone())),
-            };
-            let results = match &results_.header {
-                Ok(results) => results,
-                Err(err) => return output.with_result(DkimResult::Neutral(err.clone())),
-            };
-
-            if output.result == DkimResult::None {
-                if (seal.i as usize != (pos + 1))
-                    || (signature.i as usize != (pos + 1))
-                    || (results.i as usize != (pos + 1))
-                {
-                    output.result = DkimResult::Fail(Error::ArcInvalidInstance((pos + 1) as u32));
-                } else if (pos == 0 && seal.cv != ChainValidation::None)
-                    || (pos > 0 && seal.cv != ChainValidation::Pass)
-                {
-                    output.result = DkimResult::Fail(Error::ArcInvalidCV);
-                } else if pos == arc_headers - 1 {
-                    // Validate last signature in the chain
-                    if signature.x == 0 || (signature.x > signature.t && signature.x > now) {
-                        // Validate body hash
-                        let ha = HashAlgorithm::from(signature.a);
-                        let bh = &message
-                            .body_hashes
-                            .iter()
-                            .find(|(c, h, l, _)| {
-                                c == &signature.cb && h == &ha && l == &signature.l
-                            })
-                            .unwrap()
-                            .3;
-                        if bh != &signature.bh {
-                            output.result = DkimResult::Neutral(Error::FailedBodyHashMatch);
-                        }
-                    } else {
-                        output.result = DkimResult::Neutral(Error::SignatureExpired);
-                    }
-                }
+        // Only the first set may have cv=none
+        for (pos, set) in output.set.iter().enumerate() {
+            let cv = &set.seal.header.cv;
+            if (pos == 0 && cv != &ChainValidation::None)
+                || (pos > 0 && cv != &ChainValidation::Pass)
+            {
+                return output.with_result(DkimResult::Fail(Error::ArcInvalidCV));
             }
-
-            output.set.push(Set {
-                signature: Header::new(signature_.name, signature_.value, signature),
-                seal: Header::new(seal_.name, seal_.value, seal),
-                results: Header::new(results_.name, results_.value, results),
-            });
         }
 
-        if output.result != DkimResult::None {
-            return output;
+        // Validate last signature in the chain
+        let now = self.unix_time();
+        let signature = output.set.last().unwrap().signature.header;
+        if signature.x == 0 || (signature.x > signature.t && signature.x > now) {
+            // Validate body hash
+            let ha = HashAlgorithm::from(signature.a);
+            let bh = &message
+                .body_hashes
+                .iter()
+                .find(|(c, h, l, _)| c == &signature.cb && h == &ha && l == &signature.l)
+                .unwrap()
+                .3;
+            if bh != &signature.bh {
+                return output.with_result(DkimResult::Neutral(Error::body_hash_mismatch(
+                    &signature.bh,
+                    bh,
+                )));
+            }
+        } else {
+            return output.with_result(DkimResult::Neutral(Error::SignatureExpired));
         }
 
         // Validate ARC Set
@@ -120,12 +114,16 @@ impl Resolver {
         let mut headers = message.signed_headers(&signature.h, header.name, &dkim_hdr_value);
 
         // Obtain record
+        metrics.dns_lookup();
         let record = match self.txt_lookup::<DomainKey>(signature.domain_key()).await {
             Ok(record) => record,
             Err(err) => {
                 return output.with_result(err.into());
             }
         };
+        if opts.key_records {
+            output.key_record = record.record.clone();
+        }
 
         // Verify signature
         if let Err(err) = record.verify(&mut headers, *signature, signature.ch) {
@@ -137,6 +135,7 @@ impl Resolver {
             // Obtain record
             let header = &set.seal;
             let seal = &header.header;
+            metrics.dns_lookup();
             let record = match self.txt_lookup::<DomainKey>(seal.domain_key()).await {
                 Ok(record) => record,
                 Err(err) => {
@@ -174,6 +173,64 @@ impl Resolver {
     }
 }
 
+impl<'x> AuthenticatedMessage<'x> {
+    /// Groups the ARC-Seal, ARC-Message-Signature and ARC-Authentication-Results
+    /// headers into sets ordered by instance number. An error is returned if
+    /// any ARC header is malformed, a set is incomplete or the instance numbers
+    /// are not consecutive starting at 1. Signatures are not verified.
+    pub fn arc_sets(&'x self) -> crate::Result<Vec<Set<'x>>> {
+        match self.arc_sets_() {
+            (sets, None) => Ok(sets),
+            (_, Some(err)) => Err(err),
+        }
+    }
+
+    /// Groups the ARC headers in sets, returning the sets grouped so far
+    /// along with the first error found. Sets are still grouped after an
+    /// instance number mismatch, but not past a malformed header.
+    pub(crate) fn arc_sets_(&'x self) -> (Vec<Set<'x>>, Option<Error>) {
+        let arc_headers = self.ams_headers.len();
+        if arc_headers > 50 {
+            return (Vec::new(), Some(Error::ArcChainTooLong));
+        } else if (arc_headers != self.as_headers.len()) || (arc_headers != self.aar_headers.len())
+        {
+            return (Vec::new(), Some(Error::ArcBrokenChain));
+        }
+
+        let mut sets = Vec::with_capacity(arc_headers);
+        let mut error = None;
+        for (pos, ((seal_, signature_), results_)) in self
+            .as_headers
+            .iter()
+            .zip(self.ams_headers.iter())
+            .zip(self.aar_headers.iter())
+            .enumerate()
+        {
+            let (seal, signature, results) =
+                match (&seal_.header, &signature_.header, &results_.header) {
+                    (Ok(seal), Ok(signature), Ok(results)) => (seal, signature, results),
+                    (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+                        return (sets, Some(err.clone()))
+                    }
+                };
+            let instance = (pos + 1) as u32;
+            if error.is_none()
+                && (seal.i != instance || signature.i != instance || results.i != instance)
+            {
+                error = Some(Error::ArcInvalidInstance(instance));
+            }
+
+            sets.push(Set {
+                signature: Header::new(signature_.name, signature_.value, signature),
+                seal: Header::new(seal_.name, seal_.value, seal),
+                results: Header::new(results_.name, results_.value, results),
+            });
+        }
+
+        (sets, error)
+    }
+}
+
 #[cfg(test)]
 #[allow(unused)]
 mod test {
@@ -184,8 +241,14 @@ mod test {
     };
 
     use crate::{
-        common::{parse::TxtRecordParser, verify::DomainKey},
-        AuthenticatedMessage, DkimResult, Resolver,
+        arc::{ChainValidation, Results},
+        common::{
+            parse::TxtRecordParser,
+            resolver::DnsAnswers,
+            verify::{DomainKey, VerifySignature},
+        },
+        dkim::DkimOptions,
+        AuthenticatedMessage, DkimResult, Error, Resolver,
     };
 
     #[tokio::test]
@@ -209,12 +272,138 @@ mod test {
 
             let arc = resolver.verify_arc(&message).await;
             assert_eq!(arc.result(), &DkimResult::Pass);
+            let sealed = arc.sealed_auth_results().unwrap();
+            assert_eq!(sealed.instance(), arc.sets().len() as u32);
+            assert_eq!(sealed.method_result("dkim").unwrap().result(), "pass");
 
             let dkim = resolver.verify_dkim(&message).await;
             assert!(dkim.iter().any(|o| o.result() == &DkimResult::Pass));
+
+            // The key of the latest ARC-Message-Signature is kept when enabled
+            assert_eq!(arc.key_record(), None);
+            let key_name = arc.sets().last().unwrap().signature().header.domain_key();
+            let key_record = dns_records.split('\n').find_map(|r| {
+                r.split_once(' ')
+                    .filter(|(name, _)| format!("{name}.") == key_name)
+                    .map(|(_, value)| value)
+            });
+            let arc = resolver
+                .verify_arc_with_opts(&message, &DkimOptions::new().key_records(true))
+                .await;
+            assert_eq!(arc.result(), &DkimResult::Pass);
+            assert!(key_record.is_some());
+            assert_eq!(arc.key_record(), key_record);
+
+            let sets = message.arc_sets().unwrap();
+            assert_eq!(sets.len(), arc.sets().len());
+            for (pos, set) in sets.iter().enumerate() {
+                assert_eq!(set.instance() as usize, pos + 1);
+                assert_eq!(set.results().header.instance(), set.instance());
+            }
         }
     }
 
+    #[test]
+    fn arc_sets() {
+        let arc_set = |i: u32, cv: &str| {
+            format!(
+                concat!(
+                    "ARC-Seal: i={}; a=rsa-sha256; t=1; cv={}; d=example.org; s=s; b=dGVzdA==\r\n",
+                    "ARC-Message-Signature: i={}; a=rsa-sha256; c=relaxed/relaxed; d=example.org; ",
+                    "s=s; h=From; bh=dGVzdA==; b=dGVzdA==\r\n",
+                    "ARC-Authentication-Results: i={}; example.org; spf=pass\r\n",
+                ),
+                i, cv, i, i
+            )
+        };
+        let body = "From: jdoe@example.org\r\n\r\nHi!\r\n";
+
+        // Sets are returned in instance order
+        let raw_message = format!("{}{}{body}", arc_set(2, "pass"), arc_set(1, "none"));
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let sets = message.arc_sets().unwrap();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].instance(), 1);
+        assert_eq!(
+            sets[0].seal().header.chain_validation(),
+            &ChainValidation::None
+        );
+        assert_eq!(sets[0].signature().header.signed_headers(), ["From"]);
+        assert_eq!(sets[1].instance(), 2);
+        assert_eq!(sets[1].results().value, b" i=2; example.org; spf=pass\r\n");
+
+        // Gap in the instance numbering
+        let raw_message = format!("{}{}{body}", arc_set(1, "none"), arc_set(3, "pass"));
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        assert_eq!(message.arc_sets(), Err(Error::ArcInvalidInstance(2)));
+        let (sets, err) = message.arc_sets_();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(err, Some(Error::ArcInvalidInstance(2)));
+
+        // Incomplete set
+        let raw_message = format!(
+            "{}ARC-Seal: i=2; a=rsa-sha256; t=1; cv=pass; d=example.org; s=s; b=dGVzdA==\r\n{body}",
+            arc_set(1, "none")
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        assert_eq!(message.arc_sets(), Err(Error::ArcBrokenChain));
+    }
+
+    #[tokio::test]
+    async fn arc_sealed_auth_results() {
+        let results = Results::parse(
+            concat!(
+                " i=2; mx.example.org 1;\r\n",
+                "\tspf=pass (sender SPF authorized) smtp.mailfrom=jdoe@example.org;\r\n",
+                "\tDKIM/1 = Fail reason=\"bad; signature\" header.d=example.org",
+                " header.s=\"default\" (comment (nested));\r\n",
+                "\tdmarc=pass header.from=example.org; none\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(results.instance(), 2);
+        assert_eq!(results.authserv_id(), "mx.example.org");
+        assert_eq!(
+            results
+                .method_results()
+                .iter()
+                .map(|r| (r.method(), r.result()))
+                .collect::<Vec<_>>(),
+            [("spf", "pass"), ("dkim", "fail"), ("dmarc", "pass")]
+        );
+        let spf = results.method_result("SPF").unwrap();
+        assert_eq!(spf.property("smtp.mailfrom"), Some("jdoe@example.org"));
+        assert_eq!(spf.reason(), None);
+        let dkim = results.method_result("dkim").unwrap();
+        assert_eq!(dkim.reason(), Some("bad; signature"));
+        assert_eq!(
+            dkim.properties(),
+            [
+                ("header.d".to_string(), "example.org".to_string()),
+                ("header.s".to_string(), "default".to_string())
+            ]
+        );
+        assert!(results.method_result("iprev").is_none());
+
+        // Results are only trusted from a validated chain
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("arc");
+        test_file.push("001.txt");
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (_, raw_message) = test.split_once("\n\n").unwrap();
+        let raw_message = raw_message.replace('\n', "\r\n");
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let arc = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(DnsAnswers::new())
+            .verify_arc(&message)
+            .await;
+        assert_ne!(arc.result(), &DkimResult::Pass);
+        assert!(arc.sealed_auth_results().is_none());
+    }
+
     fn new_resolver(dns_records: &str) -> Resolver {
         let resolver = Resolver::new_system_conf().unwrap();
         for (key, value) in dns_records
@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+/// Returns the position of the `@` separating the local part of an
+/// addr-spec from its domain. Any `@` within a quoted local part or escaped
+/// with a backslash is skipped (RFC 5321, Section 4.1.2).
+pub(crate) fn domain_separator(address: &[u8]) -> Option<usize> {
+    let mut separator = None;
+    let mut in_quote = false;
+    let mut escaped = false;
+
+    for (pos, &ch) in address.iter().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            b'\\' => escaped = true,
+            b'"' => in_quote = !in_quote,
+            b'@' if !in_quote => separator = Some(pos),
+            _ => (),
+        }
+    }
+
+    separator
+}
+
+/// Splits an addr-spec into its local part and domain.
+pub(crate) fn split_address(address: &str) -> Option<(&str, &str)> {
+    domain_separator(address.as_bytes()).map(|pos| (&address[..pos], &address[pos + 1..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::split_address;
+
+    #[test]
+    fn split_addr_spec() {
+        for (address, expected) in [
+            ("jdoe@example.org", Some(("jdoe", "example.org"))),
+            (
+                "\"weird@local\"@example.org",
+                Some(("\"weird@local\"", "example.org")),
+            ),
+            (
+                "\"john doe\"@example.org",
+                Some(("\"john doe\"", "example.org")),
+            ),
+            (
+                "\"a\\\"@b\"@example.org",
+                Some(("\"a\\\"@b\"", "example.org")),
+            ),
+            ("a\\@b@example.org", Some(("a\\@b", "example.org"))),
+            ("@example.org", Some(("", "example.org"))),
+            ("jdoe@", Some(("jdoe", ""))),
+            ("example.org", None),
+            ("\"jdoe@example.org\"", None),
+        ] {
+            assert_eq!(split_address(address), expected, "{address}");
+        }
+    }
+}
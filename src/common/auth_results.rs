@@ -24,6 +24,7 @@ use crate::{
 use super::headers::{HeaderWriter, Writer};
 
 impl<'x> AuthenticationResults<'x> {
+    #[deprecated(note = "use `AuthenticationResults::try_new`, which validates the authserv-id")]
     pub fn new(hostname: &'x str) -> Self {
         AuthenticationResults {
             hostname,
@@ -31,6 +32,21 @@ impl<'x> AuthenticationResults<'x> {
         }
     }
 
+    /// Creates a new `Authentication-Results` header after validating that
+    /// `hostname`, used as the authserv-id, is a syntactically valid hostname.
+    /// Returns [`Error::InvalidAuthservId`] if it is empty, too long or
+    /// contains characters not allowed in a hostname.
+    pub fn try_new(hostname: &'x str) -> crate::Result<Self> {
+        if is_valid_hostname(hostname) {
+            Ok(AuthenticationResults {
+                hostname,
+                auth_results: String::with_capacity(64),
+            })
+        } else {
+            Err(Error::InvalidAuthservId)
+        }
+    }
+
     pub fn with_dkim_results(mut self, dkim: &[DkimOutput], header_from: &str) -> Self {
         for dkim in dkim {
             self.set_dkim_result(dkim, header_from);
@@ -123,12 +139,14 @@ impl<'x> AuthenticationResults<'x> {
 
     pub fn with_dmarc_result(mut self, dmarc: &DmarcOutput) -> Self {
         self.auth_results.push_str(";\r\n\tdmarc=");
-        if dmarc.spf_result == DmarcResult::Pass || dmarc.dkim_result == DmarcResult::Pass {
+        if dmarc.is_pass() {
             DmarcResult::Pass.as_auth_result(&mut self.auth_results);
-        } else if dmarc.spf_result != DmarcResult::None {
+        } else if dmarc.spf_result != DmarcResult::None && !dmarc.dkim_required {
             dmarc.spf_result.as_auth_result(&mut self.auth_results);
         } else if dmarc.dkim_result != DmarcResult::None {
             dmarc.dkim_result.as_auth_result(&mut self.auth_results);
+        } else if dmarc.spf_result != DmarcResult::None {
+            DmarcResult::Fail(Error::NotAligned).as_auth_result(&mut self.auth_results);
         } else {
             DmarcResult::None.as_auth_result(&mut self.auth_results);
         }
@@ -186,6 +204,7 @@ impl ReceivedSpf {
         hostname: &str,
     ) -> Self {
         let mut received_spf = String::with_capacity(64);
+        let ip_addr = ip_addr.to_canonical();
         let mail_from = if !mail_from.is_empty() {
             Cow::from(mail_from)
         } else {
@@ -207,6 +226,7 @@ impl ReceivedSpf {
 
 impl SpfResult {
     fn as_spf_result(&self, header: &mut String, hostname: &str, mail_from: &str, ip_addr: IpAddr) {
+        let ip_addr = ip_addr.to_canonical();
         match &self {
             SpfResult::Pass => write!(
                 header,
@@ -247,72 +267,43 @@ pub trait AsAuthResult {
 
 impl AsAuthResult for DmarcResult {
     fn as_auth_result(&self, header: &mut String) {
-        match &self {
-            DmarcResult::Pass => header.push_str("pass"),
-            DmarcResult::Fail(err) => {
-                header.push_str("fail");
-                err.as_auth_result(header);
-            }
-            DmarcResult::PermError(err) => {
-                header.push_str("permerror");
-                err.as_auth_result(header);
-            }
-            DmarcResult::TempError(err) => {
-                header.push_str("temperror");
-                err.as_auth_result(header);
+        header.push_str(self.as_keyword());
+        match self {
+            DmarcResult::Fail(err) | DmarcResult::PermError(err) | DmarcResult::TempError(err) => {
+                err.as_auth_result(header)
             }
-            DmarcResult::None => header.push_str("none"),
+            DmarcResult::Pass | DmarcResult::None => (),
         }
     }
 }
 
 impl AsAuthResult for IprevResult {
     fn as_auth_result(&self, header: &mut String) {
-        match &self {
-            IprevResult::Pass => header.push_str("pass"),
-            IprevResult::Fail(err) => {
-                header.push_str("fail");
-                err.as_auth_result(header);
-            }
-            IprevResult::PermError(err) => {
-                header.push_str("permerror");
-                err.as_auth_result(header);
+        header.push_str(self.as_keyword());
+        match self {
+            IprevResult::Fail(err) | IprevResult::PermError(err) | IprevResult::TempError(err) => {
+                err.as_auth_result(header)
             }
-            IprevResult::TempError(err) => {
-                header.push_str("temperror");
-                err.as_auth_result(header);
-            }
-            IprevResult::None => header.push_str("none"),
+            IprevResult::Pass | IprevResult::None => (),
         }
     }
 }
 
 impl AsAuthResult for DkimResult {
     fn as_auth_result(&self, header: &mut String) {
-        match &self {
-            DkimResult::Pass => header.push_str("pass"),
-            DkimResult::Neutral(err) => {
-                header.push_str("neutral");
-                err.as_auth_result(header);
-            }
-            DkimResult::Fail(err) => {
-                header.push_str("fail");
-                err.as_auth_result(header);
-            }
-            DkimResult::PermError(err) => {
-                header.push_str("permerror");
-                err.as_auth_result(header);
-            }
-            DkimResult::TempError(err) => {
-                header.push_str("temperror");
-                err.as_auth_result(header);
-            }
-            DkimResult::None => header.push_str("none"),
+        header.push_str(self.as_keyword());
+        match self {
+            DkimResult::Neutral(err)
+            | DkimResult::Fail(err)
+            | DkimResult::PermError(err)
+            | DkimResult::TempError(err) => err.as_auth_result(header),
+            DkimResult::Pass | DkimResult::None => (),
         }
     }
 }
 
 impl AsAuthResult for Error {
+    #[allow(deprecated)]
     fn as_auth_result(&self, header: &mut String) {
         header.push_str(" (");
         header.push_str(match self {
@@ -326,12 +317,14 @@ impl AsAuthResult for Error {
             Error::UnsupportedAlgorithm => "unsupported algorithm",
             Error::UnsupportedCanonicalization => "unsupported canonicalization",
             Error::UnsupportedKeyType => "unsupported key type",
-            Error::FailedBodyHashMatch => "body hash did not verify",
+            Error::FailedBodyHashMatch | Error::BodyHashMismatch { .. } => {
+                "body hash did not verify"
+            }
             Error::FailedVerification => "verification failed",
             Error::FailedAuidMatch => "auid does not match",
             Error::RevokedPublicKey => "revoked public key",
             Error::IncompatibleAlgorithms => "incompatible record/signature algorithms",
-            Error::SignatureExpired => "signature error",
+            Error::SignatureExpired | Error::SignatureTimeInFuture => "signature error",
             Error::DnsError(_) => "dns error",
             Error::DnsRecordNotFound(_) => "dns record not found",
             Error::ArcInvalidInstance(i) => {
@@ -345,11 +338,35 @@ impl AsAuthResult for Error {
             Error::NotAligned => "policy not aligned",
             Error::InvalidRecordType => "invalid dns record type",
             Error::SignatureLength => "signature length ignored due to security risk",
+            Error::DuplicateTag => "duplicate tag",
+            Error::AlgorithmMismatch => "signature algorithm does not match key type",
+            Error::SignatureLimitExceeded => "too many signatures",
+            Error::AlgorithmNotAllowed => "algorithm not allowed",
+            Error::UnsupportedQueryMethod => "unsupported query method",
+            Error::MultipleFromHeaders => "multiple from headers",
+            Error::InvalidBodyHashLength => "invalid body hash length",
+            Error::BodyLengthExceeded => "signed body length exceeds body",
+            Error::InvalidAuthservId => "invalid authserv-id",
         });
         header.push(')');
     }
 }
 
+fn is_valid_hostname(hostname: &str) -> bool {
+    let hostname = hostname.strip_suffix('.').unwrap_or(hostname);
+    !hostname.is_empty()
+        && hostname.len() <= 253
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == b'-')
+        })
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
@@ -360,7 +377,7 @@ mod test {
 
     #[test]
     fn authentication_results() {
-        let mut auth_results = AuthenticationResults::new("mydomain.org");
+        let mut auth_results = AuthenticationResults::try_new("mydomain.org").unwrap();
 
         for (expected_auth_results, dkim) in [
             (
@@ -375,6 +392,10 @@ mod test {
                         .into(),
                     report: None,
                     is_atps: false,
+                    expired_at_verification: false,
+                    warnings: vec![],
+                    canonical_form: None,
+                    key_record: None,
                 },
             ),
             (
@@ -393,6 +414,10 @@ mod test {
                         .into(),
                     report: None,
                     is_atps: false,
+                    expired_at_verification: false,
+                    warnings: vec![],
+                    canonical_form: None,
+                    key_record: None,
                 },
             ),
             (
@@ -411,6 +436,10 @@ mod test {
                         .into(),
                     report: None,
                     is_atps: true,
+                    expired_at_verification: false,
+                    warnings: vec![],
+                    canonical_form: None,
+                    key_record: None,
                 },
             ),
         ] {
@@ -479,6 +508,22 @@ mod test {
                 "example.org",
                 "",
             ),
+            (
+                concat!(
+                    "spf=pass (mx.domain.org: domain of jdoe@example.org designates 192.0.2.1 ",
+                    "as permitted sender) smtp.mailfrom=jdoe@example.org"
+                ),
+                concat!(
+                    "pass (mx.domain.org: domain of jdoe@example.org designates 192.0.2.1 as ",
+                    "permitted sender)\r\n\treceiver=mx.domain.org; client-ip=192.0.2.1; ",
+                    "envelope-from=\"jdoe@example.org\"; helo=example.org;"
+                ),
+                SpfResult::Pass,
+                "::ffff:192.0.2.1".parse().unwrap(),
+                "mx.domain.org",
+                "example.org",
+                "jdoe@example.org",
+            ),
         ] {
             auth_results.hostname = receiver;
             auth_results = auth_results.with_spf_mailfrom_result(
@@ -487,6 +532,9 @@ mod test {
                     domain: "".to_string(),
                     report: None,
                     explanation: None,
+                    mail_from: "".to_string(),
+                    helo: "".to_string(),
+                    helo_result: None,
                 },
                 ip_addr,
                 mail_from,
@@ -498,6 +546,9 @@ mod test {
                     domain: "".to_string(),
                     report: None,
                     explanation: None,
+                    mail_from: "".to_string(),
+                    helo: "".to_string(),
+                    helo_result: None,
                 },
                 ip_addr,
                 helo,
@@ -520,6 +571,7 @@ mod test {
                     domain: "example.org".to_string(),
                     policy: Policy::None,
                     record: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -530,6 +582,7 @@ mod test {
                     domain: "example.com".to_string(),
                     policy: Policy::Quarantine,
                     record: None,
+                    ..Default::default()
                 },
             ),
         ] {
@@ -548,7 +601,10 @@ mod test {
             ),
             (
                 "arc=neutral (body hash did not verify) smtp.remote-ip=1:2:3::a",
-                DkimResult::Neutral(Error::FailedBodyHashMatch),
+                DkimResult::Neutral(Error::BodyHashMismatch {
+                    expected: "YQ==".to_string(),
+                    computed: "Yg==".to_string(),
+                }),
                 "1:2:3::a".parse().unwrap(),
             ),
         ] {
@@ -556,6 +612,7 @@ mod test {
                 &ArcOutput {
                     result: arc,
                     set: vec![],
+                    key_record: None,
                 },
                 remote_ip,
             );
@@ -590,4 +647,104 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn result_keywords() {
+        for (result, keyword) in [
+            (SpfResult::Pass, "pass"),
+            (SpfResult::Fail, "fail"),
+            (SpfResult::SoftFail, "softfail"),
+            (SpfResult::Neutral, "neutral"),
+            (SpfResult::TempError, "temperror"),
+            (SpfResult::PermError, "permerror"),
+            (SpfResult::None, "none"),
+        ] {
+            assert_eq!(result.as_keyword(), keyword);
+            assert_eq!(result.to_string(), keyword);
+        }
+
+        for (result, keyword, display) in [
+            (DkimResult::Pass, "pass", "pass"),
+            (
+                DkimResult::Neutral(Error::SignatureExpired),
+                "neutral",
+                "neutral; Signature expired",
+            ),
+            (
+                DkimResult::Fail(Error::BodyHashMismatch {
+                    expected: "YQ==".to_string(),
+                    computed: "Yg==".to_string(),
+                }),
+                "fail",
+                "fail; Calculated body hash Yg== does not match signature hash YQ==",
+            ),
+            (
+                DkimResult::TempError(Error::DnsError("timeout".to_string())),
+                "temperror",
+                "temperror; DNS resolution error: timeout",
+            ),
+            (
+                DkimResult::PermError(Error::ParseError),
+                "permerror",
+                "permerror; Parse error",
+            ),
+            (DkimResult::None, "none", "none"),
+        ] {
+            assert_eq!(result.as_keyword(), keyword);
+            assert_eq!(result.to_string(), display);
+        }
+
+        for (result, keyword) in [
+            (DmarcResult::Pass, "pass"),
+            (DmarcResult::Fail(Error::NotAligned), "fail"),
+            (DmarcResult::TempError(Error::NotAligned), "temperror"),
+            (DmarcResult::PermError(Error::NotAligned), "permerror"),
+            (DmarcResult::None, "none"),
+        ] {
+            assert_eq!(result.as_keyword(), keyword);
+            assert!(result.to_string().starts_with(keyword));
+        }
+
+        for (result, keyword) in [
+            (IprevResult::Pass, "pass"),
+            (IprevResult::Fail(Error::NotAligned), "fail"),
+            (IprevResult::TempError(Error::NotAligned), "temperror"),
+            (IprevResult::PermError(Error::NotAligned), "permerror"),
+            (IprevResult::None, "none"),
+        ] {
+            assert_eq!(result.as_keyword(), keyword);
+            assert!(result.to_string().starts_with(keyword));
+        }
+    }
+
+    #[test]
+    fn authserv_id() {
+        for hostname in [
+            "mx.example.org",
+            "mx.example.org.",
+            "localhost",
+            "mx-1.example.org",
+        ] {
+            assert_eq!(
+                AuthenticationResults::try_new(hostname).map(|ar| ar.hostname),
+                Ok(hostname),
+            );
+        }
+
+        for hostname in [
+            "",
+            ".",
+            "mx example.org",
+            "mx.example.org; dkim=pass",
+            "mx..example.org",
+            "-mx.example.org",
+            "mx.example.org\r\n",
+        ] {
+            assert_eq!(
+                AuthenticationResults::try_new(hostname),
+                Err(Error::InvalidAuthservId),
+                "{hostname:?}"
+            );
+        }
+    }
 }
@@ -44,7 +44,7 @@ impl Base32Writer {
 
     pub fn with_capacity(capacity: usize) -> Self {
         Base32Writer {
-            result: String::with_capacity((capacity + 3) / 4 * 5),
+            result: String::with_capacity(capacity.div_ceil(4) * 5),
             last_byte: 0,
             pos: 0,
         }
@@ -83,7 +83,7 @@ impl Base32Writer {
     }
 
     pub fn finalize(mut self) -> String {
-        if self.pos % 5 != 0 {
+        if !self.pos.is_multiple_of(5) {
             self.push_byte(0, true);
         }
 
@@ -153,10 +153,50 @@ impl Iterator for Base32Reader<'_> {
 mod tests {
     use crate::common::{
         base32::{Base32Reader, Base32Writer},
-        crypto::{HashContext, HashImpl, Sha1},
+        crypto::{HashAlgorithm, HashContext, HashImpl, Sha1},
         headers::Writer,
     };
 
+    #[test]
+    fn base32_encode() {
+        // Unpadded output for every remainder length (RFC 4648, Section 6)
+        for (len, expected_result) in [
+            (1, "AE"),
+            (2, "AEBA"),
+            (3, "AEBAG"),
+            (4, "AEBAGBA"),
+            (5, "AEBAGBAF"),
+            (6, "AEBAGBAFAY"),
+            (7, "AEBAGBAFAYDQ"),
+            (8, "AEBAGBAFAYDQQ"),
+            (9, "AEBAGBAFAYDQQCI"),
+            (10, "AEBAGBAFAYDQQCIK"),
+        ] {
+            let bytes = (1..=len).collect::<Vec<u8>>();
+            assert_eq!(Base32Writer::encode(&bytes), expected_result);
+            assert_eq!(
+                Base32Reader::new(expected_result.as_bytes()).collect::<Vec<_>>(),
+                bytes
+            );
+        }
+        assert_eq!(Base32Writer::encode(b""), "");
+
+        // SHA-256 hashes, as used by atpsh=sha256
+        for (test, expected_result) in [
+            (
+                "one.example.net",
+                "SQWHEPKQYG5KRIOG6F7LPEDTTNOIF7DQUSVCO2PCHSH3QUGXAKHA",
+            ),
+            (
+                "two.example.net",
+                "XZWXC3N7U7P4XMXEYDUYZY474B3B4QWONK3SZZTIFFABRUUIFZ6A",
+            ),
+        ] {
+            let hash = HashAlgorithm::Sha256.hash(test.as_bytes());
+            assert_eq!(Base32Writer::encode(hash.as_ref()), expected_result);
+        }
+    }
+
     #[test]
     fn base32_hash() {
         for (test, expected_result) in [
@@ -0,0 +1,43 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::time::{Instant, SystemTime};
+
+/// Time source used by the [`Resolver`](crate::Resolver) for signature
+/// timestamp and expiration checks, the SPF `%{t}` macro and DNS cache
+/// expiration. Processing timeouts, such as the SPF evaluation time limit,
+/// always use the system clock.
+pub trait Clock: Send + Sync {
+    /// Returns the current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// Returns the current monotonic time, used to expire cached DNS records.
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Returns the current time in seconds since the Unix epoch.
+    fn unix_time(&self) -> u64 {
+        self.now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// [`Clock`] backed by the system clock, used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
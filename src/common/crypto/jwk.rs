@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use mail_parser::decoders::base64::base64_decode;
+use serde::Deserialize;
+
+use crate::{common::headers::Writable, Error, Result};
+
+use super::{Algorithm, Ed25519Key, RsaKey, Sha256, SigningKey};
+
+/// Private key read from a JSON Web Key (RFC 7517). RSA keys (`kty=RSA`)
+/// sign using `rsa-sha256` and OKP keys on the Ed25519 curve
+/// (`kty=OKP, crv=Ed25519`, RFC 8037) sign using `ed25519-sha256`.
+pub enum JwkKey {
+    Rsa(RsaKey<Sha256>),
+    Ed25519(Ed25519Key),
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    crv: Option<String>,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    d: Option<String>,
+    p: Option<String>,
+    q: Option<String>,
+    dp: Option<String>,
+    dq: Option<String>,
+    qi: Option<String>,
+    x: Option<String>,
+}
+
+/// Big-endian encoded components of an RSA private key.
+pub(crate) struct RsaComponents {
+    pub n: Vec<u8>,
+    pub e: Vec<u8>,
+    pub d: Vec<u8>,
+    pub p: Vec<u8>,
+    pub q: Vec<u8>,
+    // The CRT parameters are recomputed by the RustCrypto backend.
+    #[cfg_attr(feature = "rust-crypto", allow(dead_code))]
+    pub dp: Vec<u8>,
+    #[cfg_attr(feature = "rust-crypto", allow(dead_code))]
+    pub dq: Vec<u8>,
+    #[cfg_attr(feature = "rust-crypto", allow(dead_code))]
+    pub qi: Vec<u8>,
+}
+
+impl JwkKey {
+    /// Parses a private key from its JWK JSON representation.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let jwk: Jwk = serde_json::from_str(json)
+            .map_err(|err| Error::CryptoError(format!("Invalid JWK: {err}")))?;
+
+        match jwk.kty.as_str() {
+            "RSA" => {
+                jwk.expect_alg("RS256")?;
+                RsaKey::from_components(&RsaComponents {
+                    n: param("n", &jwk.n)?,
+                    e: param("e", &jwk.e)?,
+                    d: param("d", &jwk.d)?,
+                    p: param("p", &jwk.p)?,
+                    q: param("q", &jwk.q)?,
+                    dp: param("dp", &jwk.dp)?,
+                    dq: param("dq", &jwk.dq)?,
+                    qi: param("qi", &jwk.qi)?,
+                })
+                .map(JwkKey::Rsa)
+            }
+            "OKP" if jwk.crv.as_deref() == Some("Ed25519") => {
+                jwk.expect_alg("EdDSA")?;
+                Ed25519Key::from_seed_and_public_key(&param("d", &jwk.d)?, &param("x", &jwk.x)?)
+                    .map(JwkKey::Ed25519)
+            }
+            "OKP" => Err(Error::CryptoError(format!(
+                "Unsupported JWK curve {:?}",
+                jwk.crv.unwrap_or_default()
+            ))),
+            kty => Err(Error::CryptoError(format!(
+                "Unsupported JWK key type {kty:?}"
+            ))),
+        }
+    }
+}
+
+impl Jwk {
+    fn expect_alg(&self, expected: &str) -> Result<()> {
+        match &self.alg {
+            Some(alg) if alg != expected => Err(Error::CryptoError(format!(
+                "Unsupported JWK algorithm {alg:?}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn param(name: &str, value: &Option<String>) -> Result<Vec<u8>> {
+    value
+        .as_deref()
+        .and_then(base64url_decode)
+        .ok_or_else(|| Error::CryptoError(format!("Missing or invalid JWK parameter {name:?}")))
+}
+
+fn base64url_decode(value: &str) -> Option<Vec<u8>> {
+    let mut encoded = String::with_capacity(value.len() + 2);
+    for ch in value.chars() {
+        match ch {
+            '-' => encoded.push('+'),
+            '_' => encoded.push('/'),
+            'A'..='Z' | 'a'..='z' | '0'..='9' => encoded.push(ch),
+            _ => return None,
+        }
+    }
+    while !encoded.len().is_multiple_of(4) {
+        encoded.push('=');
+    }
+    base64_decode(encoded.as_bytes())
+}
+
+impl SigningKey for JwkKey {
+    type Hasher = Sha256;
+
+    fn sign(&self, input: impl Writable) -> Result<Vec<u8>> {
+        match self {
+            JwkKey::Rsa(key) => SigningKey::sign(key, input),
+            JwkKey::Ed25519(key) => SigningKey::sign(key, input),
+        }
+    }
+
+    #[cfg(feature = "rust-crypto")]
+    fn sign_with_rng(
+        &self,
+        input: impl Writable,
+        rng: &mut impl rsa::rand_core::CryptoRngCore,
+    ) -> Result<Vec<u8>> {
+        match self {
+            JwkKey::Rsa(key) => SigningKey::sign_with_rng(key, input, rng),
+            JwkKey::Ed25519(key) => SigningKey::sign_with_rng(key, input, rng),
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            JwkKey::Rsa(key) => SigningKey::algorithm(key),
+            JwkKey::Ed25519(key) => SigningKey::algorithm(key),
+        }
+    }
+}
@@ -1,10 +1,17 @@
 #[cfg(feature = "sha1")]
 use sha1::{digest::Output, Digest};
 
+use std::future::Future;
+
 use crate::{dkim::Canonicalization, Result};
 
 use super::headers::{Writable, Writer};
 
+#[cfg(feature = "jwk")]
+mod jwk;
+#[cfg(feature = "jwk")]
+pub use jwk::JwkKey;
+
 #[cfg(feature = "rust-crypto")]
 mod rust_crypto;
 #[cfg(feature = "rust-crypto")]
@@ -24,6 +31,19 @@ pub trait SigningKey {
 
     fn sign(&self, input: impl Writable) -> Result<Vec<u8>>;
 
+    /// Signs the input using `rng` for any randomized step of the signing
+    /// operation, such as RSA blinding. Keys that require no randomness
+    /// ignore `rng` and produce the same output as [`SigningKey::sign`].
+    #[cfg(feature = "rust-crypto")]
+    fn sign_with_rng(
+        &self,
+        input: impl Writable,
+        rng: &mut impl rsa::rand_core::CryptoRngCore,
+    ) -> Result<Vec<u8>> {
+        let _ = rng;
+        self.sign(input)
+    }
+
     fn hash(&self, data: impl Writable) -> HashOutput {
         let mut hasher = <Self::Hasher as HashImpl>::hasher();
         data.write(&mut hasher);
@@ -33,6 +53,34 @@ pub trait SigningKey {
     fn algorithm(&self) -> Algorithm;
 }
 
+/// Signing key that may be held outside of the process, such as in a KMS
+/// or HSM. Canonicalization and body hashing are performed locally and
+/// only the final signing operation is delegated.
+///
+/// The data to sign is the canonicalized header data, and implementations
+/// must apply the hashing required by the algorithm. For `ed25519-sha256`
+/// this means signing the SHA-256 digest of the data (RFC 8463).
+/// All [`SigningKey`] implementations are also signers.
+pub trait Signer {
+    type Hasher: HashImpl;
+
+    fn sign(&self, data: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send;
+
+    fn algorithm(&self) -> Algorithm;
+}
+
+impl<T: SigningKey> Signer for T {
+    type Hasher = T::Hasher;
+
+    fn sign(&self, data: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send {
+        std::future::ready(SigningKey::sign(self, data))
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        SigningKey::algorithm(self)
+    }
+}
+
 pub trait VerifyingKey {
     fn verify<'a>(
         &self,
@@ -43,7 +91,9 @@ pub trait VerifyingKey {
     ) -> Result<()>;
 }
 
-pub(crate) enum VerifyingKeyType {
+/// Public key type published in the `k=` tag of a key record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyingKeyType {
     Rsa,
     Ed25519,
 }
@@ -152,5 +202,16 @@ pub enum Algorithm {
     Ed25519Sha256,
 }
 
+impl Algorithm {
+    /// Returns the `a=` tag value identifying this algorithm.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::RsaSha1 => "rsa-sha1",
+            Algorithm::RsaSha256 => "rsa-sha256",
+            Algorithm::Ed25519Sha256 => "ed25519-sha256",
+        }
+    }
+}
+
 pub(crate) const R_HASH_SHA1: u64 = 0x01;
 pub(crate) const R_HASH_SHA256: u64 = 0x02;
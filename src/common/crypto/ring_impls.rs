@@ -14,6 +14,11 @@ use crate::{
     Error, Result,
 };
 
+#[cfg(feature = "jwk")]
+use super::jwk::RsaComponents;
+#[cfg(feature = "jwk")]
+use ring::rsa::{KeyPairComponents, PublicKeyComponents};
+
 use super::{Algorithm, HashContext, HashImpl, HashOutput, Sha1, Sha256, SigningKey, VerifyingKey};
 
 #[derive(Debug)]
@@ -69,6 +74,28 @@ impl<T: HashImpl> RsaKey<T> {
         })
     }
 
+    /// Creates a new RSA private key from its big-endian encoded components.
+    #[cfg(feature = "jwk")]
+    pub(crate) fn from_components(components: &RsaComponents) -> Result<Self> {
+        Ok(Self {
+            inner: RsaKeyPair::from_components(&KeyPairComponents {
+                public_key: PublicKeyComponents {
+                    n: components.n.as_slice(),
+                    e: components.e.as_slice(),
+                },
+                d: components.d.as_slice(),
+                p: components.p.as_slice(),
+                q: components.q.as_slice(),
+                dP: components.dp.as_slice(),
+                dQ: components.dq.as_slice(),
+                qInv: components.qi.as_slice(),
+            })
+            .map_err(|err| Error::CryptoError(err.to_string()))?,
+            rng: SystemRandom::new(),
+            padding: PhantomData,
+        })
+    }
+
     /// Returns the public key of the RSA key pair.
     pub fn public_key(&self) -> Vec<u8> {
         self.inner.public().as_ref().to_vec()
@@ -2,7 +2,11 @@ use std::array::TryFromSliceError;
 use std::marker::PhantomData;
 
 use ed25519_dalek::Signer;
-use rsa::{pkcs1::DecodeRsaPrivateKey, Pkcs1v15Sign, RsaPrivateKey};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey,
+    rand_core::{CryptoRngCore, OsRng},
+    Pkcs1v15Sign, RsaPrivateKey,
+};
 use sha2::digest::Digest;
 
 use crate::{
@@ -11,6 +15,11 @@ use crate::{
     Error, Result,
 };
 
+#[cfg(feature = "jwk")]
+use super::jwk::RsaComponents;
+#[cfg(feature = "jwk")]
+use rsa::BigUint;
+
 use super::{Algorithm, HashContext, HashImpl, HashOutput, Sha1, Sha256, SigningKey, VerifyingKey};
 
 #[derive(Debug)]
@@ -41,15 +50,40 @@ impl<T: HashImpl> RsaKey<T> {
             padding: PhantomData,
         })
     }
+
+    /// Creates a new RSA private key from its big-endian encoded components.
+    #[cfg(feature = "jwk")]
+    pub(crate) fn from_components(components: &RsaComponents) -> Result<Self> {
+        let inner = RsaPrivateKey::from_components(
+            BigUint::from_bytes_be(&components.n),
+            BigUint::from_bytes_be(&components.e),
+            BigUint::from_bytes_be(&components.d),
+            vec![
+                BigUint::from_bytes_be(&components.p),
+                BigUint::from_bytes_be(&components.q),
+            ],
+        )
+        .map_err(|err| Error::CryptoError(err.to_string()))?;
+
+        Ok(RsaKey {
+            inner,
+            padding: PhantomData,
+        })
+    }
 }
 
 impl SigningKey for RsaKey<Sha1> {
     type Hasher = Sha1;
 
     fn sign(&self, input: impl Writable) -> Result<Vec<u8>> {
+        self.sign_with_rng(input, &mut OsRng)
+    }
+
+    fn sign_with_rng(&self, input: impl Writable, rng: &mut impl CryptoRngCore) -> Result<Vec<u8>> {
         let hash = self.hash(input);
         self.inner
-            .sign(
+            .sign_with_rng(
+                rng,
                 Pkcs1v15Sign::new::<<Self::Hasher as HashImpl>::Context>(),
                 hash.as_ref(),
             )
@@ -65,9 +99,14 @@ impl SigningKey for RsaKey<Sha256> {
     type Hasher = Sha256;
 
     fn sign(&self, input: impl Writable) -> Result<Vec<u8>> {
+        self.sign_with_rng(input, &mut OsRng)
+    }
+
+    fn sign_with_rng(&self, input: impl Writable, rng: &mut impl CryptoRngCore) -> Result<Vec<u8>> {
         let hash = self.hash(input);
         self.inner
-            .sign(
+            .sign_with_rng(
+                rng,
                 Pkcs1v15Sign::new::<<Self::Hasher as HashImpl>::Context>(),
                 hash.as_ref(),
             )
@@ -94,6 +133,19 @@ impl Ed25519Key {
             ),
         })
     }
+
+    /// Creates an Ed25519 private key from its seed, checking that it
+    /// matches the public key.
+    pub fn from_seed_and_public_key(seed: &[u8], public_key: &[u8]) -> Result<Self> {
+        let key = Self::from_bytes(seed)?;
+        if key.inner.verifying_key().as_bytes() == public_key {
+            Ok(key)
+        } else {
+            Err(Error::CryptoError(
+                "Public key does not match private key".to_string(),
+            ))
+        }
+    }
 }
 
 impl SigningKey for Ed25519Key {
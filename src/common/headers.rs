@@ -44,8 +44,8 @@ pub(crate) struct HeaderParser<'x> {
     iter: Peekable<Enumerate<Iter<'x, u8>>>,
     start_pos: usize,
     pub num_received: usize,
-    pub has_message_id: bool,
-    pub has_date: bool,
+    pub message_id: Option<&'x [u8]>,
+    pub date: Option<&'x [u8]>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -72,8 +72,8 @@ impl<'x> HeaderParser<'x> {
             iter: message.iter().enumerate().peekable(),
             start_pos: 0,
             num_received: 0,
-            has_message_id: false,
-            has_date: false,
+            message_id: None,
+            date: None,
         }
     }
 
@@ -91,6 +91,11 @@ impl<'x> HeaderIterator<'x> {
         }
     }
 
+    /// Offset just past the last header returned by the iterator.
+    pub(crate) fn header_end(&self) -> usize {
+        self.start_pos
+    }
+
     pub fn seek_start(&mut self) {
         while let Some((_, ch)) = self.iter.peek() {
             if !ch.is_ascii_whitespace() {
@@ -138,7 +143,7 @@ impl<'x> Iterator for HeaderIterator<'x> {
                         } else if self
                             .iter
                             .peek()
-                            .map_or(true, |(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
+                            .is_none_or(|(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
                         {
                             // Invalid header, return anyway.
                             let header_name = self
@@ -155,7 +160,7 @@ impl<'x> Iterator for HeaderIterator<'x> {
                 && self
                     .iter
                     .peek()
-                    .map_or(true, |(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
+                    .is_none_or(|(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
             {
                 let header_name = self
                     .message
@@ -225,7 +230,7 @@ impl<'x> Iterator for HeaderParser<'x> {
                         } else if self
                             .iter
                             .peek()
-                            .map_or(true, |(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
+                            .is_none_or(|(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
                         {
                             // Invalid header, return anyway.
                             let header_name = self
@@ -267,7 +272,7 @@ impl<'x> Iterator for HeaderParser<'x> {
                 && self
                     .iter
                     .peek()
-                    .map_or(true, |(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
+                    .is_none_or(|(_, next_byte)| ![b' ', b'\t'].contains(next_byte))
             {
                 let header_name = self
                     .message
@@ -312,11 +317,11 @@ impl<'x> Iterator for HeaderParser<'x> {
                             .unwrap_or_default()
                             .eq_ignore_ascii_case(b"id") =>
                     {
-                        self.has_message_id = true;
+                        self.message_id.get_or_insert(header_value);
                         AuthenticatedHeader::Other(header_name)
                     }
                     DATE => {
-                        self.has_date = true;
+                        self.date.get_or_insert(header_value);
                         AuthenticatedHeader::Other(header_name)
                     }
                     _ => AuthenticatedHeader::Other(header_name),
@@ -368,6 +373,12 @@ impl Writer for Vec<u8> {
     }
 }
 
+impl<W: Writer + ?Sized> Writer for &mut W {
+    fn write(&mut self, buf: &[u8]) {
+        (**self).write(buf);
+    }
+}
+
 const FROM: u64 = (b'f' as u64) | (b'r' as u64) << 8 | (b'o' as u64) << 16 | (b'm' as u64) << 24;
 const DKIM: u64 = (b'd' as u64)
     | (b'k' as u64) << 8
@@ -532,8 +543,8 @@ mod test {
                 AuthenticatedHeader::Other(b"Message-Id"),
             ]
         );
-        assert!(parser.has_date);
-        assert!(parser.has_message_id);
+        assert_eq!(parser.date, Some(&b" date\n"[..]));
+        assert_eq!(parser.message_id, Some(&b" myid\n"[..]));
         assert_eq!(parser.num_received, 3);
     }
 
@@ -22,11 +22,15 @@ pub struct LruItem<V> {
 
 pub trait DnsCache<K, V>: Sized {
     fn with_capacity(capacity: usize) -> Self;
-    fn get<Q>(&self, name: &Q) -> Option<V>
+    fn get<Q>(&self, name: &Q, now: Instant) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn insert(&self, name: K, value: V, valid_until: Instant, now: Instant) -> V;
+    fn valid_until<Q>(&self, name: &Q) -> Option<Instant>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
-    fn insert(&self, name: K, value: V, valid_until: Instant) -> V;
 }
 
 impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
@@ -37,14 +41,14 @@ impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
         ))
     }
 
-    fn get<Q>(&self, name: &Q) -> Option<V>
+    fn get<Q>(&self, name: &Q, now: Instant) -> Option<V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let mut cache = self.lock();
         let entry = cache.get_mut(name)?;
-        if entry.valid_until >= Instant::now() {
+        if entry.valid_until >= now {
             entry.item.clone().into()
         } else {
             cache.remove(name);
@@ -52,14 +56,25 @@ impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
         }
     }
 
-    fn insert(&self, name: K, item: V, valid_until: Instant) -> V {
-        self.lock().insert(
-            name,
-            LruItem {
-                item: item.clone(),
-                valid_until,
-            },
-        );
+    fn insert(&self, name: K, item: V, valid_until: Instant, now: Instant) -> V {
+        // Records with a TTL of 0 are not cached
+        if valid_until > now {
+            self.lock().insert(
+                name,
+                LruItem {
+                    item: item.clone(),
+                    valid_until,
+                },
+            );
+        }
         item
     }
+
+    fn valid_until<Q>(&self, name: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().get_mut(name).map(|entry| entry.valid_until)
+    }
 }
@@ -8,6 +8,8 @@
  * except according to those terms.
  */
 
+use std::{borrow::Cow, ops::Range};
+
 use mail_parser::{parsers::MessageStream, Address, HeaderValue};
 
 use crate::{arc, common::crypto::HashAlgorithm, dkim, AuthenticatedMessage};
@@ -20,122 +22,180 @@ impl<'x> AuthenticatedMessage<'x> {
     }
 
     pub fn parse_with_opts(raw_message: &'x [u8], strict: bool) -> Option<Self> {
+        Self::parse_with_lenient_tags(raw_message, strict, false)
+    }
+
+    /// Parses a message like [`AuthenticatedMessage::parse_with_opts`].
+    /// When `lenient_tags` is enabled, DKIM-Signature tag names that are not
+    /// lowercase are normalized rather than treated as unknown tags, see
+    /// [`dkim::Signature::parse_with_opts`].
+    pub fn parse_with_lenient_tags(
+        raw_message: &'x [u8],
+        strict: bool,
+        lenient_tags: bool,
+    ) -> Option<Self> {
+        let mut message = Self::parse_headers(raw_message, strict, lenient_tags)?;
+        message.body = raw_message.get(message.body_offset..).unwrap_or_default();
+        message.hash_body();
+        message.into()
+    }
+
+    /// Builds a message from a header block and a body stored separately,
+    /// avoiding the need to concatenate them. The header block should end
+    /// with a CRLF and may include the empty line separating the body.
+    /// Since no contiguous buffer exists, [`AuthenticatedMessage::raw_message`]
+    /// returns only the header block.
+    pub fn from_parts(headers: &'x [u8], body: &'x [u8]) -> Option<Self> {
+        Self::from_parts_with_opts(headers, body, true)
+    }
+
+    pub fn from_parts_with_opts(headers: &'x [u8], body: &'x [u8], strict: bool) -> Option<Self> {
+        let mut message = Self::parse_headers(headers, strict, false)?;
+        message.body_offset = headers.len();
+        message.body = body;
+        message.hash_body();
+        message.into()
+    }
+
+    fn hash_body(&mut self) {
+        for (cb, ha, l, bh) in &mut self.body_hashes {
+            *bh = ha.hash(cb.canonical_body(self.body, *l)).as_ref().to_vec();
+            if *l > 0 && !self.body_len.iter().any(|(c, _)| c == cb) {
+                self.body_len
+                    .push((*cb, cb.canonical_body(self.body, 0).len()));
+            }
+        }
+    }
+
+    /// Parses the message headers, leaving the body hashes empty.
+    pub(crate) fn parse_headers(
+        raw_message: &'x [u8],
+        strict: bool,
+        lenient_tags: bool,
+    ) -> Option<Self> {
         let mut message = AuthenticatedMessage {
             headers: Vec::new(),
             from: Vec::new(),
             raw_message,
             body_offset: 0,
+            body: &[],
+            body_len: Vec::new(),
             body_hashes: Vec::new(),
             dkim_headers: Vec::new(),
             ams_headers: Vec::new(),
             as_headers: Vec::new(),
             aar_headers: Vec::new(),
             received_headers_count: 0,
+            from_headers_count: 0,
             date_header_present: false,
             message_id_header_present: false,
+            date: None,
+            message_id: None,
         };
 
         let mut headers = HeaderParser::new(raw_message);
         let mut has_arc_errors = false;
 
         for (header, value) in &mut headers {
-            let name =
-                match header {
-                    AuthenticatedHeader::Ds(name) => {
-                        let signature = match dkim::Signature::parse(value) {
-                            Ok(signature) if signature.l == 0 || !strict => {
-                                let ha = HashAlgorithm::from(signature.a);
-                                if !message.body_hashes.iter().any(|(c, h, l, _)| {
-                                    c == &signature.cb && h == &ha && l == &signature.l
-                                }) {
-                                    message.body_hashes.push((
-                                        signature.cb,
-                                        ha,
-                                        signature.l,
-                                        Vec::new(),
-                                    ));
-                                }
-                                Ok(signature)
+            let name = match header {
+                AuthenticatedHeader::Ds(name) => {
+                    let signature = match dkim::Signature::parse_with_opts(value, lenient_tags) {
+                        Ok(signature) if signature.l == 0 || !strict => {
+                            let ha = HashAlgorithm::from(signature.a);
+                            if !message.body_hashes.iter().any(|(c, h, l, _)| {
+                                c == &signature.cb && h == &ha && l == &signature.l
+                            }) {
+                                message.body_hashes.push((
+                                    signature.cb,
+                                    ha,
+                                    signature.l,
+                                    Vec::new(),
+                                ));
                             }
-                            Ok(_) => Err(crate::Error::SignatureLength),
-                            Err(err) => Err(err),
-                        };
-
-                        message
-                            .dkim_headers
-                            .push(Header::new(name, value, signature));
-                        name
-                    }
-                    AuthenticatedHeader::Aar(name) => {
-                        let results = arc::Results::parse(value);
-                        if !has_arc_errors {
-                            has_arc_errors = results.is_err();
+                            Ok(signature)
                         }
-                        message.aar_headers.push(Header::new(name, value, results));
-                        name
+                        Ok(_) => Err(crate::Error::SignatureLength),
+                        Err(err) => Err(err),
+                    };
+
+                    message
+                        .dkim_headers
+                        .push(Header::new(name, value, signature));
+                    name
+                }
+                AuthenticatedHeader::Aar(name) => {
+                    let results = arc::Results::parse(value);
+                    if !has_arc_errors {
+                        has_arc_errors = results.is_err();
                     }
-                    AuthenticatedHeader::Ams(name) => {
-                        let signature = match arc::Signature::parse(value) {
-                            Ok(signature) if signature.l == 0 || !strict => {
-                                let ha = HashAlgorithm::from(signature.a);
-                                if !message.body_hashes.iter().any(|(c, h, l, _)| {
-                                    c == &signature.cb && h == &ha && l == &signature.l
-                                }) {
-                                    message.body_hashes.push((
-                                        signature.cb,
-                                        ha,
-                                        signature.l,
-                                        Vec::new(),
-                                    ));
-                                }
-                                Ok(signature)
+                    message.aar_headers.push(Header::new(name, value, results));
+                    name
+                }
+                AuthenticatedHeader::Ams(name) => {
+                    let signature = match arc::Signature::parse(value) {
+                        Ok(signature) if signature.l == 0 || !strict => {
+                            let ha = HashAlgorithm::from(signature.a);
+                            if !message.body_hashes.iter().any(|(c, h, l, _)| {
+                                c == &signature.cb && h == &ha && l == &signature.l
+                            }) {
+                                message.body_hashes.push((
+                                    signature.cb,
+                                    ha,
+                                    signature.l,
+                                    Vec::new(),
+                                ));
                             }
-                            Ok(_) => {
-                                has_arc_errors = true;
-                                Err(crate::Error::SignatureLength)
-                            }
-                            Err(err) => {
-                                has_arc_errors = true;
-                                Err(err)
-                            }
-                        };
+                            Ok(signature)
+                        }
+                        Ok(_) => {
+                            has_arc_errors = true;
+                            Err(crate::Error::SignatureLength)
+                        }
+                        Err(err) => {
+                            has_arc_errors = true;
+                            Err(err)
+                        }
+                    };
 
-                        message
-                            .ams_headers
-                            .push(Header::new(name, value, signature));
-                        name
+                    message
+                        .ams_headers
+                        .push(Header::new(name, value, signature));
+                    name
+                }
+                AuthenticatedHeader::As(name) => {
+                    let seal = arc::Seal::parse(value);
+                    if !has_arc_errors {
+                        has_arc_errors = seal.is_err();
                     }
-                    AuthenticatedHeader::As(name) => {
-                        let seal = arc::Seal::parse(value);
-                        if !has_arc_errors {
-                            has_arc_errors = seal.is_err();
+                    message.as_headers.push(Header::new(name, value, seal));
+                    name
+                }
+                AuthenticatedHeader::From(name) => {
+                    message.from_headers_count += 1;
+                    match MessageStream::new(value).parse_address() {
+                        HeaderValue::Address(Address::List(list)) => {
+                            message.from.extend(
+                                list.into_iter()
+                                    .filter_map(|a| a.address.map(|a| a.to_lowercase())),
+                            );
                         }
-                        message.as_headers.push(Header::new(name, value, seal));
-                        name
-                    }
-                    AuthenticatedHeader::From(name) => {
-                        match MessageStream::new(value).parse_address() {
-                            HeaderValue::Address(Address::List(list)) => {
-                                message.from.extend(
-                                    list.into_iter()
-                                        .filter_map(|a| a.address.map(|a| a.to_lowercase())),
-                                );
-                            }
-                            HeaderValue::Address(Address::Group(group_list)) => message
+                        HeaderValue::Address(Address::Group(group_list)) => {
+                            message
                                 .from
                                 .extend(group_list.into_iter().flat_map(|group| {
                                     group
                                         .addresses
                                         .into_iter()
                                         .filter_map(|a| a.address.map(|a| a.to_lowercase()))
-                                })),
-                            _ => (),
+                                }))
                         }
-
-                        name
+                        _ => (),
                     }
-                    AuthenticatedHeader::Other(name) => name,
-                };
+
+                    name
+                }
+                AuthenticatedHeader::Other(name) => name,
+            };
 
             message.headers.push((name, value));
         }
@@ -146,8 +206,10 @@ impl<'x> AuthenticatedMessage<'x> {
 
         // Update header counts
         message.received_headers_count = headers.num_received;
-        message.message_id_header_present = headers.has_message_id;
-        message.date_header_present = headers.has_date;
+        message.message_id_header_present = headers.message_id.is_some();
+        message.date_header_present = headers.date.is_some();
+        message.message_id = headers.message_id.map(|value| value.trim_ascii());
+        message.date = headers.date.map(|value| value.trim_ascii());
 
         // Obtain message body
         if let Some(offset) = headers.body_offset() {
@@ -155,12 +217,6 @@ impl<'x> AuthenticatedMessage<'x> {
         } else {
             message.body_offset = raw_message.len();
         }
-        let body = raw_message.get(message.body_offset..).unwrap_or_default();
-
-        // Calculate body hashes
-        for (cb, ha, l, bh) in &mut message.body_hashes {
-            *bh = ha.hash(cb.canonical_body(body, *l)).as_ref().to_vec();
-        }
 
         // Sort ARC headers
         if !message.as_headers.is_empty() && !has_arc_errors {
@@ -190,10 +246,82 @@ impl<'x> AuthenticatedMessage<'x> {
         message.into()
     }
 
+    /// Converts bare LF and lone CR line endings to CRLF, as most MTAs do
+    /// on receipt. DKIM canonicalization is defined over CRLF, so messages
+    /// stored with LF-only endings should be normalized before parsing.
+    /// Note that verification will only succeed if the signer made the same
+    /// assumption, i.e. it signed the message with CRLF line endings.
+    pub fn normalize_line_endings(raw_message: &[u8]) -> Cow<'_, [u8]> {
+        let needs_normalization = raw_message.iter().enumerate().any(|(pos, &ch)| match ch {
+            b'\r' => raw_message.get(pos + 1) != Some(&b'\n'),
+            b'\n' => pos == 0 || raw_message[pos - 1] != b'\r',
+            _ => false,
+        });
+        if !needs_normalization {
+            return Cow::Borrowed(raw_message);
+        }
+
+        let mut normalized = Vec::with_capacity(raw_message.len() + (raw_message.len() / 32));
+        let mut iter = raw_message.iter().peekable();
+        while let Some(&ch) = iter.next() {
+            match ch {
+                b'\r' => {
+                    if iter.peek().is_some_and(|&&ch| ch == b'\n') {
+                        iter.next();
+                    }
+                    normalized.extend_from_slice(b"\r\n");
+                }
+                b'\n' => {
+                    normalized.extend_from_slice(b"\r\n");
+                }
+                _ => normalized.push(ch),
+            }
+        }
+
+        Cow::Owned(normalized)
+    }
+
+    /// Returns the byte ranges of the DKIM-Signature headers within
+    /// [`AuthenticatedMessage::raw_headers`], including the header name
+    /// and the trailing line break.
+    pub fn dkim_signature_ranges(&self) -> Vec<Range<usize>> {
+        let base = self.raw_message.as_ptr() as usize;
+        self.dkim_headers
+            .iter()
+            .map(|header| {
+                let start = header.name.as_ptr() as usize - base;
+                let end = header.value.as_ptr() as usize - base + header.value.len();
+                start..end
+            })
+            .collect()
+    }
+
+    /// Returns a copy of the message with all DKIM-Signature headers removed,
+    /// leaving the remaining headers and the body untouched.
+    pub fn strip_dkim_signatures(&self) -> Vec<u8> {
+        let headers = self.raw_headers();
+        let body = self.raw_body();
+        let mut message = Vec::with_capacity(headers.len() + body.len());
+        let mut offset = 0;
+        for range in self.dkim_signature_ranges() {
+            message.extend_from_slice(&headers[offset..range.start]);
+            offset = range.end;
+        }
+        message.extend_from_slice(&headers[offset..]);
+        message.extend_from_slice(body);
+        message
+    }
+
     pub fn received_headers_count(&self) -> usize {
         self.received_headers_count
     }
 
+    /// Returns `true` if the message contains more than one From header,
+    /// which RFC 5322 forbids and is commonly used for spoofing.
+    pub fn has_multiple_from_headers(&self) -> bool {
+        self.from_headers_count > 1
+    }
+
     pub fn has_message_id_header(&self) -> bool {
         self.message_id_header_present
     }
@@ -202,6 +330,18 @@ impl<'x> AuthenticatedMessage<'x> {
         self.date_header_present
     }
 
+    /// Returns the value of the first Message-ID header, without the
+    /// surrounding whitespace, or `None` if the message has no Message-ID.
+    pub fn message_id(&self) -> Option<&'x [u8]> {
+        self.message_id
+    }
+
+    /// Returns the value of the first Date header, without the surrounding
+    /// whitespace, or `None` if the message has no Date.
+    pub fn date(&self) -> Option<&'x [u8]> {
+        self.date
+    }
+
     pub fn raw_message(&self) -> &[u8] {
         self.raw_message
     }
@@ -215,7 +355,7 @@ impl<'x> AuthenticatedMessage<'x> {
     }
 
     pub fn raw_body(&self) -> &[u8] {
-        self.raw_message.get(self.body_offset..).unwrap_or_default()
+        self.body
     }
 
     pub fn body_offset(&self) -> usize {
@@ -226,7 +366,152 @@ impl<'x> AuthenticatedMessage<'x> {
         &self.from
     }
 
+    /// Returns the first RFC5322.From address, or only its domain when the
+    /// address has no usable local part.
     pub fn from(&self) -> &str {
-        self.from.first().map_or("", |f| f.as_str())
+        self.from
+            .first()
+            .map_or("", |f| f.strip_prefix('@').unwrap_or(f))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{dkim::DkimVerifier, AuthenticatedMessage, Error};
+
+    #[test]
+    fn parse_from_addresses() {
+        for (from, expected) in [
+            ("\"Ops, Inc.\" <ops@example.org>", "ops@example.org"),
+            ("\"ops@evil.org\" <ops@example.org>", "ops@example.org"),
+            ("ops@evil.org <ops@example.org>", "ops@example.org"),
+            ("Ops (ops@evil.org) <ops@example.org>", "ops@example.org"),
+            (
+                "ops@example.org (Ops, Inc. <ops@evil.org>)",
+                "ops@example.org",
+            ),
+            ("(comment) OPS@Example.ORG", "ops@example.org"),
+            ("Ops <ops@example.org> (evil.org)", "ops@example.org"),
+            // The domain of quoted local parts is preserved
+            ("\"john@doe\"@example.org", "example.org"),
+        ] {
+            let raw_message = format!("From: {from}\r\n\r\nHello world!\r\n");
+            let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+            assert_eq!(message.from(), expected, "{from}");
+        }
+
+        // Mailbox lists and groups
+        let message = AuthenticatedMessage::parse(
+            b"From: \"Doe, John\" <jdoe@example.org>, Team: ops@example.org;\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(message.froms(), ["jdoe@example.org", "ops@example.org"]);
+
+        // Addresses without a local part yield only the domain
+        let message =
+            AuthenticatedMessage::parse(b"From: \"john@doe\"@example.org\r\n\r\n").unwrap();
+        assert_eq!(message.from(), "example.org");
+        assert!(!message.from().starts_with('@'));
+    }
+
+    #[test]
+    fn parse_message_id_and_date() {
+        let message = AuthenticatedMessage::parse(
+            concat!(
+                "From: jdoe@example.org\r\n",
+                "message-id:   <1234@example.org> \r\n",
+                "Date: Tue, 1 Oct 2024\r\n 10:00:00 +0000\r\n",
+                "Message-ID: <5678@example.org>\r\n",
+                "\r\n",
+                "Hello world!\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert!(message.has_message_id_header());
+        assert!(message.has_date_header());
+        assert_eq!(message.message_id(), Some(&b"<1234@example.org>"[..]));
+        assert_eq!(
+            message.date(),
+            Some(&b"Tue, 1 Oct 2024\r\n 10:00:00 +0000"[..])
+        );
+
+        let message =
+            AuthenticatedMessage::parse(b"From: jdoe@example.org\r\n\r\nHello world!\r\n").unwrap();
+        assert!(!message.has_message_id_header());
+        assert_eq!(message.message_id(), None);
+        assert_eq!(message.date(), None);
+    }
+
+    #[test]
+    fn strip_dkim_signatures() {
+        let signature = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; s=default; d=example.org;\r\n",
+            "\tc=relaxed/relaxed; h=From; bh=dGVzdA==; b=dGVzdA==\r\n"
+        );
+        let headers = "From: jdoe@example.org\r\nSubject: Hi\r\n";
+        let body = "\r\nHello world!\r\n";
+        let raw_message = format!("{signature}{headers}{}{body}", signature.to_lowercase());
+
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let ranges = message.dkim_signature_ranges();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(&raw_message[ranges[0].clone()], signature);
+        assert_eq!(
+            &raw_message[ranges[1].clone()],
+            signature.to_lowercase().as_str()
+        );
+
+        let stripped = message.strip_dkim_signatures();
+        assert_eq!(
+            String::from_utf8(stripped.clone()).unwrap(),
+            format!("{headers}{body}")
+        );
+        let message = AuthenticatedMessage::parse(&stripped).unwrap();
+        assert!(message.dkim_headers.is_empty());
+        assert_eq!(message.strip_dkim_signatures(), stripped);
+
+        // Headers and body stored separately
+        let message = AuthenticatedMessage::from_parts(
+            format!("{headers}{signature}\r\n").as_bytes(),
+            b"Hello world!\r\n",
+        )
+        .unwrap()
+        .strip_dkim_signatures();
+        assert_eq!(
+            String::from_utf8(message).unwrap(),
+            format!("{headers}{body}")
+        );
+    }
+
+    #[test]
+    fn parse_mixed_case_dkim_tags() {
+        let message = concat!(
+            "DKIM-Signature: v=1; A=rsa-sha256; s=default; D=example.org; h=From;\r\n",
+            "\tbh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA==\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+            "Hello world!\r\n"
+        );
+
+        // Strict by default
+        let parsed = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        assert_eq!(parsed.dkim_headers[0].header, Err(Error::MissingParameters));
+        let mut verifier = DkimVerifier::new();
+        verifier.write(message.as_bytes());
+        assert_eq!(
+            verifier.finish().unwrap().dkim_headers[0].header,
+            Err(Error::MissingParameters)
+        );
+
+        // Lenient tag names are opt-in
+        let parsed =
+            AuthenticatedMessage::parse_with_lenient_tags(message.as_bytes(), true, true).unwrap();
+        let signature = parsed.dkim_headers[0].header.as_ref().unwrap();
+        assert_eq!(signature.d, "example.org");
+        assert!(signature.mixed_case_tags);
+        let mut verifier = DkimVerifier::new().with_lenient_tags(true);
+        verifier.write(message.as_bytes());
+        assert!(verifier.finish().unwrap().dkim_headers[0].header.is_ok());
     }
 }
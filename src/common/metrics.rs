@@ -0,0 +1,58 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+/// Evaluation metrics emitted as a `tracing` event once a verification
+/// completes. Without the `tracing` feature this is a zero-sized no-op.
+pub(crate) struct Metrics {
+    #[cfg(feature = "tracing")]
+    started: std::time::Instant,
+    #[cfg(feature = "tracing")]
+    dns_lookups: u32,
+}
+
+impl Metrics {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        Metrics {
+            #[cfg(feature = "tracing")]
+            started: std::time::Instant::now(),
+            #[cfg(feature = "tracing")]
+            dns_lookups: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn dns_lookup(&mut self) {
+        #[cfg(feature = "tracing")]
+        {
+            self.dns_lookups += 1;
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_dns_lookups(&mut self, _dns_lookups: u32) {
+        #[cfg(feature = "tracing")]
+        {
+            self.dns_lookups = _dns_lookups;
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn finish(&self, _check: &'static str, _result: &'static str) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            check = _check,
+            elapsed_us = self.started.elapsed().as_micros() as u64,
+            dns_lookups = self.dns_lookups,
+            result = _result,
+            "Evaluation completed"
+        );
+    }
+}
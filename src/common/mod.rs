@@ -10,14 +10,18 @@
 
 use crate::{Error, IprevResult};
 
+pub(crate) mod address;
 pub mod auth_results;
 pub mod base32;
+pub mod clock;
 pub mod crypto;
 pub mod headers;
 pub mod lru;
 pub mod message;
+pub(crate) mod metrics;
 pub mod parse;
 pub mod resolver;
+pub mod retry;
 pub mod verify;
 
 impl From<Error> for IprevResult {
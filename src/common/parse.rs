@@ -25,6 +25,7 @@ pub(crate) const L: u64 = b'l' as u64;
 pub(crate) const N: u64 = b'n' as u64;
 pub(crate) const O: u64 = b'o' as u64;
 pub(crate) const P: u64 = b'p' as u64;
+pub(crate) const Q: u64 = b'q' as u64;
 pub(crate) const R: u64 = b'r' as u64;
 pub(crate) const S: u64 = b's' as u64;
 pub(crate) const T: u64 = b't' as u64;
@@ -33,6 +34,33 @@ pub(crate) const X: u64 = b'x' as u64;
 pub(crate) const Y: u64 = b'y' as u64;
 pub(crate) const Z: u64 = b'z' as u64;
 
+/// Records a tag name, failing if it was already seen in the same tag-list.
+/// Unrecognized or oversized tag names (`u64::MAX`) are not tracked.
+pub(crate) fn check_duplicate(tags: &mut Vec<u64>, key: u64) -> crate::Result<()> {
+    if key != u64::MAX && key != 0 {
+        if tags.contains(&key) {
+            return Err(crate::Error::DuplicateTag);
+        }
+        tags.push(key);
+    }
+    Ok(())
+}
+
+/// Returns the name of the tag read by the last `key()` call, given the
+/// input remaining before and after the call.
+pub(crate) fn tag_name(before: &[u8], after: &[u8]) -> Option<String> {
+    let consumed = before.get(..before.len() - after.len())?;
+    let name = consumed.strip_suffix(b"=").unwrap_or(consumed);
+    let name = std::str::from_utf8(name.rsplit(|&ch| ch == b';').next()?)
+        .ok()?
+        .trim();
+    if !name.is_empty() {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
 pub trait TxtRecordParser: Sized {
     fn parse(record: &[u8]) -> crate::Result<Self>;
 }
@@ -40,6 +68,7 @@ pub trait TxtRecordParser: Sized {
 pub(crate) trait TagParser: Sized {
     fn match_bytes(&mut self, bytes: &[u8]) -> bool;
     fn key(&mut self) -> Option<u64>;
+    fn key_with_case(&mut self) -> Option<(u64, bool)>;
     fn value(&mut self) -> u64;
     fn text(&mut self, to_lower: bool) -> String;
     fn text_qp(&mut self, base: Vec<u8>, to_lower: bool, stop_comma: bool) -> String;
@@ -58,10 +87,17 @@ pub(crate) trait ItemParser: Sized {
 }
 
 impl TagParser for Iter<'_, u8> {
-    #[allow(clippy::while_let_on_iterator)]
+    #[inline(always)]
     fn key(&mut self) -> Option<u64> {
+        self.key_with_case().map(|(key, _)| key)
+    }
+
+    /// Returns the lowercased tag name and whether it was already lowercase.
+    #[allow(clippy::while_let_on_iterator)]
+    fn key_with_case(&mut self) -> Option<(u64, bool)> {
         let mut key: u64 = 0;
         let mut shift = 0;
+        let mut is_lowercase = true;
 
         while let Some(&ch) = self.next() {
             match ch {
@@ -71,14 +107,16 @@ impl TagParser for Iter<'_, u8> {
                 }
                 b' ' | b'\t' | b'\r' | b'\n' => (),
                 b'=' => {
-                    return key.into();
+                    return (key, is_lowercase).into();
                 }
                 b'A'..=b'Z' if shift < 64 => {
                     key |= ((ch - b'A' + b'a') as u64) << shift;
                     shift += 8;
+                    is_lowercase = false;
                 }
                 b';' => {
                     key = 0;
+                    is_lowercase = true;
                 }
                 _ => {
                     key = u64::MAX;
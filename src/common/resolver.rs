@@ -9,15 +9,19 @@
  */
 
 use std::{
-    borrow::Cow,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    borrow::{Borrow, Cow},
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use hickory_resolver::{
-    config::{ResolverConfig, ResolverOpts},
+    config::{NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts},
     error::{ResolveError, ResolveErrorKind},
-    proto::rr::RecordType,
+    proto::{op::ResponseCode, rr::RecordType},
     system_conf::read_system_conf,
     AsyncResolver, Name,
 };
@@ -31,11 +35,15 @@ use crate::{
 };
 
 use super::{
+    clock::{Clock, SystemClock},
     lru::{DnsCache, LruCache},
     parse::TxtRecordParser,
+    retry::{is_temporary, DnsFailures, DnsRetryPolicy},
     verify::DomainKey,
 };
 
+pub(crate) const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
 impl Resolver {
     pub fn new_cloudflare_tls() -> Result<Self, ResolveError> {
         Self::with_capacity(
@@ -66,6 +74,14 @@ impl Resolver {
         Self::with_capacity(config, options, 128)
     }
 
+    /// Creates a resolver that sends all queries to the name server at
+    /// `addr` using `protocol`, either [`Protocol::Udp`] or
+    /// [`Protocol::Tcp`], without reading the system configuration.
+    /// Truncated UDP responses are retried over TCP on the same address.
+    pub fn new_with_addr(addr: SocketAddr, protocol: Protocol) -> Result<Self, ResolveError> {
+        Self::with_capacity(addr_config(addr, protocol), ResolverOpts::default(), 128)
+    }
+
     pub fn with_capacity(
         config: ResolverConfig,
         options: ResolverOpts,
@@ -78,6 +94,13 @@ impl Resolver {
             cache_ipv4: LruCache::with_capacity(capacity),
             cache_ipv6: LruCache::with_capacity(capacity),
             cache_ptr: LruCache::with_capacity(capacity),
+            cache_spf: LruCache::with_capacity(0),
+            spf_cache_ttl: Duration::ZERO,
+            min_ttl: Duration::ZERO,
+            clock: Arc::new(SystemClock),
+            answers: None,
+            dns_retry: DnsRetryPolicy::default(),
+            dns_failures: DnsFailures::new(),
         })
     }
 
@@ -97,14 +120,129 @@ impl Resolver {
             cache_ipv4: LruCache::with_capacity(ipv4_capacity),
             cache_ipv6: LruCache::with_capacity(ipv6_capacity),
             cache_ptr: LruCache::with_capacity(ptr_capacity),
+            cache_spf: LruCache::with_capacity(0),
+            spf_cache_ttl: Duration::ZERO,
+            min_ttl: Duration::ZERO,
+            clock: Arc::new(SystemClock),
+            answers: None,
+            dns_retry: DnsRetryPolicy::default(),
+            dns_failures: DnsFailures::new(),
         })
     }
 
+    /// Creates a resolver that does not cache DNS answers, neither in its own
+    /// record caches nor in the underlying resolver, so that every lookup
+    /// results in a fresh query. Records added through the `*_add` test
+    /// helpers are discarded.
+    pub fn new_uncached(
+        config: ResolverConfig,
+        mut options: ResolverOpts,
+    ) -> Result<Self, ResolveError> {
+        options.cache_size = 0;
+        Self::with_capacity(config, options, 0)
+    }
+
+    /// Sets the minimum time records are cached for, regardless of their TTL.
+    /// By default records are cached for their TTL, and records with a TTL
+    /// of 0 are not cached at all.
+    pub fn with_min_ttl(mut self, min_ttl: Duration) -> Self {
+        self.min_ttl = min_ttl;
+        self
+    }
+
+    /// Sets the time source used for signature expiration checks, the SPF
+    /// `%{t}` macro and DNS cache expiration (defaults to [`SystemClock`]).
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Resolves all queries from `answers` instead of DNS, which allows
+    /// evaluating policies offline, for example to replay an incident from
+    /// captured DNS responses. Names missing from `answers` do not exist,
+    /// even if they are cached. TXT answers are cached for the minimum TTL
+    /// set with [`Resolver::with_min_ttl`], if any.
+    pub fn with_dns_answers(mut self, answers: DnsAnswers) -> Self {
+        self.answers = Some(Arc::new(answers));
+        self
+    }
+
+    /// Sets the policy used to retry DNS lookups failing temporarily and to
+    /// suspend lookups for names that keep failing (defaults to no retries
+    /// and no circuit breaker).
+    pub fn with_dns_retry_policy(mut self, policy: DnsRetryPolicy) -> Self {
+        self.dns_retry = policy;
+        self
+    }
+
+    /// Caches up to `capacity` SPF results by client IP and domain for at
+    /// most `ttl`, or until the first of the DNS records consulted expires.
+    /// Results are not cached when the policy expands macros depending on
+    /// other inputs, such as the sender local part (`%{l}`) or the HELO
+    /// domain (`%{h}`), when the evaluation failed temporarily, or when it
+    /// used non-default
+    /// [`SpfOptions::record_limits`](crate::spf::SpfOptions::record_limits).
+    /// Disabled by default, a `capacity` or `ttl` of zero disables the cache.
+    pub fn with_spf_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.cache_spf = LruCache::with_capacity(capacity);
+        self.spf_cache_ttl = if capacity > 0 { ttl } else { Duration::ZERO };
+        self
+    }
+
+    /// Returns the current time in seconds since the Unix epoch.
+    pub(crate) fn unix_time(&self) -> u64 {
+        self.clock.unix_time()
+    }
+
+    fn valid_until(&self, valid_until: Instant) -> Instant {
+        std::cmp::max(valid_until, self.clock.instant() + self.min_ttl)
+    }
+
+    /// Rebases the expiration of a DNS answer, which is relative to the
+    /// system clock, on the configured clock.
+    fn answer_valid_until(&self, valid_until: Instant) -> Instant {
+        self.valid_until(
+            self.clock.instant() + valid_until.saturating_duration_since(Instant::now()),
+        )
+    }
+
+    /// Runs a lookup for `key`, retrying temporary failures according to the
+    /// retry policy. Lookups for names with an open circuit breaker fail
+    /// immediately.
+    async fn with_retry<T, E, F, Fut>(&self, key: &str, lookup: F) -> crate::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        Error: From<E>,
+    {
+        if self.dns_failures.is_open(key, self.clock.instant()) {
+            return Err(Error::DnsError(format!(
+                "Lookups for {key} suspended after repeated failures"
+            )));
+        }
+
+        let mut attempt = 0;
+        let result = loop {
+            let result = lookup().await.map_err(Error::from);
+            match &result {
+                Err(err) if attempt < self.dns_retry.retries && is_temporary(err) => {
+                    tokio::time::sleep(self.dns_retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                _ => break result,
+            }
+        };
+        self.dns_failures
+            .record(key, &result, &self.dns_retry, self.clock.instant());
+        result
+    }
+
     pub async fn txt_raw_lookup(&self, key: impl IntoFqdn<'_>) -> crate::Result<Vec<u8>> {
+        let key = key.into_fqdn();
+        let name = Name::from_str_relaxed(key.as_ref())?;
         let mut result = vec![];
         for record in self
-            .resolver
-            .txt_lookup(Name::from_str_relaxed(key.into_fqdn().as_ref())?)
+            .with_retry(key.as_ref(), || self.resolver.txt_lookup(name.clone()))
             .await?
             .as_lookup()
             .record_iter()
@@ -124,18 +262,31 @@ impl Resolver {
         key: impl IntoFqdn<'x>,
     ) -> crate::Result<Arc<T>> {
         let key = key.into_fqdn();
-        if let Some(value) = self.cache_txt.get(key.as_ref()) {
+        if let Some(answers) = &self.answers {
+            // Answers take precedence over the cache. They have no TTL, so
+            // they are only cached for the minimum TTL
+            let result = answers.txt_lookup::<T>(key.as_ref())?;
+            return T::unwrap_txt(self.cache_txt.insert(
+                key.into_owned(),
+                result,
+                self.valid_until(self.clock.instant()),
+                self.clock.instant(),
+            ));
+        }
+        if let Some(value) = self.cache_txt.get(key.as_ref(), self.clock.instant()) {
             return T::unwrap_txt(value);
         }
 
         #[cfg(any(test, feature = "test"))]
         if true {
-            return mock_resolve(key.as_ref());
+            return self
+                .with_retry(key.as_ref(), || async { mock_resolve(key.as_ref()) })
+                .await;
         }
 
+        let name = Name::from_str_relaxed(key.as_ref())?;
         let txt_lookup = self
-            .resolver
-            .txt_lookup(Name::from_str_relaxed(key.as_ref())?)
+            .with_retry(key.as_ref(), || self.resolver.txt_lookup(name.clone()))
             .await?;
         let mut result = Err(Error::InvalidRecordType);
         let records = txt_lookup.as_lookup().record_iter().filter_map(|r| {
@@ -154,32 +305,45 @@ impl Resolver {
         });
 
         for record in records {
-            result = T::parse(record.as_ref());
-            if result.is_ok() {
-                break;
+            // Records of other types, such as site verification tokens, are
+            // skipped without replacing the error of a malformed record
+            match T::parse(record.as_ref()) {
+                Err(Error::InvalidRecordType) => (),
+                record => {
+                    result = record;
+                    if result.is_ok() {
+                        break;
+                    }
+                }
             }
         }
         T::unwrap_txt(self.cache_txt.insert(
             key.into_owned(),
             result.into(),
-            txt_lookup.valid_until(),
+            self.answer_valid_until(txt_lookup.valid_until()),
+            self.clock.instant(),
         ))
     }
 
     pub async fn mx_lookup<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<Arc<Vec<MX>>> {
         let key = key.into_fqdn();
-        if let Some(value) = self.cache_mx.get(key.as_ref()) {
+        if let Some(answers) = &self.answers {
+            return DnsAnswers::lookup(&answers.mx, key.as_ref()).cloned();
+        }
+        if let Some(value) = self.cache_mx.get(key.as_ref(), self.clock.instant()) {
             return Ok(value);
         }
 
         #[cfg(any(test, feature = "test"))]
         if true {
-            return mock_resolve(key.as_ref());
+            return self
+                .with_retry(key.as_ref(), || async { mock_resolve(key.as_ref()) })
+                .await;
         }
 
+        let name = Name::from_str_relaxed(key.as_ref())?;
         let mx_lookup = self
-            .resolver
-            .mx_lookup(Name::from_str_relaxed(key.as_ref())?)
+            .with_retry(key.as_ref(), || self.resolver.mx_lookup(name.clone()))
             .await?;
         let mx_records = mx_lookup.as_lookup().records();
         let mut records: Vec<MX> = Vec::with_capacity(mx_records.len());
@@ -199,11 +363,14 @@ impl Resolver {
             }
         }
 
-        records.sort_unstable_by(|a, b| a.preference.cmp(&b.preference));
+        records.sort_unstable_by_key(|a| a.preference);
 
-        Ok(self
-            .cache_mx
-            .insert(key.into_owned(), Arc::new(records), mx_lookup.valid_until()))
+        Ok(self.cache_mx.insert(
+            key.into_owned(),
+            Arc::new(records),
+            self.answer_valid_until(mx_lookup.valid_until()),
+            self.clock.instant(),
+        ))
     }
 
     pub async fn ipv4_lookup<'x>(
@@ -211,18 +378,23 @@ impl Resolver {
         key: impl IntoFqdn<'x>,
     ) -> crate::Result<Arc<Vec<Ipv4Addr>>> {
         let key = key.into_fqdn();
-        if let Some(value) = self.cache_ipv4.get(key.as_ref()) {
+        if let Some(answers) = &self.answers {
+            return DnsAnswers::lookup(&answers.ipv4, key.as_ref()).cloned();
+        }
+        if let Some(value) = self.cache_ipv4.get(key.as_ref(), self.clock.instant()) {
             return Ok(value);
         }
 
         #[cfg(any(test, feature = "test"))]
         if true {
-            return mock_resolve(key.as_ref());
+            return self
+                .with_retry(key.as_ref(), || async { mock_resolve(key.as_ref()) })
+                .await;
         }
 
+        let name = Name::from_str_relaxed(key.as_ref())?;
         let ipv4_lookup = self
-            .resolver
-            .ipv4_lookup(Name::from_str_relaxed(key.as_ref())?)
+            .with_retry(key.as_ref(), || self.resolver.ipv4_lookup(name.clone()))
             .await?;
         let ips: Vec<Ipv4Addr> = ipv4_lookup
             .as_lookup()
@@ -230,9 +402,12 @@ impl Resolver {
             .filter_map(|r| r.data()?.as_a()?.0.into())
             .collect::<Vec<_>>();
 
-        Ok(self
-            .cache_ipv4
-            .insert(key.into_owned(), Arc::new(ips), ipv4_lookup.valid_until()))
+        Ok(self.cache_ipv4.insert(
+            key.into_owned(),
+            Arc::new(ips),
+            self.answer_valid_until(ipv4_lookup.valid_until()),
+            self.clock.instant(),
+        ))
     }
 
     pub async fn ipv6_lookup<'x>(
@@ -240,18 +415,23 @@ impl Resolver {
         key: impl IntoFqdn<'x>,
     ) -> crate::Result<Arc<Vec<Ipv6Addr>>> {
         let key = key.into_fqdn();
-        if let Some(value) = self.cache_ipv6.get(key.as_ref()) {
+        if let Some(answers) = &self.answers {
+            return DnsAnswers::lookup(&answers.ipv6, key.as_ref()).cloned();
+        }
+        if let Some(value) = self.cache_ipv6.get(key.as_ref(), self.clock.instant()) {
             return Ok(value);
         }
 
         #[cfg(any(test, feature = "test"))]
         if true {
-            return mock_resolve(key.as_ref());
+            return self
+                .with_retry(key.as_ref(), || async { mock_resolve(key.as_ref()) })
+                .await;
         }
 
+        let name = Name::from_str_relaxed(key.as_ref())?;
         let ipv6_lookup = self
-            .resolver
-            .ipv6_lookup(Name::from_str_relaxed(key.as_ref())?)
+            .with_retry(key.as_ref(), || self.resolver.ipv6_lookup(name.clone()))
             .await?;
         let ips = ipv6_lookup
             .as_lookup()
@@ -259,9 +439,12 @@ impl Resolver {
             .filter_map(|r| r.data()?.as_aaaa()?.0.into())
             .collect::<Vec<_>>();
 
-        Ok(self
-            .cache_ipv6
-            .insert(key.into_owned(), Arc::new(ips), ipv6_lookup.valid_until()))
+        Ok(self.cache_ipv6.insert(
+            key.into_owned(),
+            Arc::new(ips),
+            self.answer_valid_until(ipv6_lookup.valid_until()),
+            self.clock.instant(),
+        ))
     }
 
     pub async fn ip_lookup(
@@ -308,17 +491,23 @@ impl Resolver {
         }
     }
 
-    pub async fn ptr_lookup<'x>(&self, addr: IpAddr) -> crate::Result<Arc<Vec<String>>> {
-        if let Some(value) = self.cache_ptr.get(&addr) {
+    pub async fn ptr_lookup(&self, addr: IpAddr) -> crate::Result<Arc<Vec<String>>> {
+        if let Some(answers) = &self.answers {
+            return DnsAnswers::lookup(&answers.ptr, &addr).cloned();
+        }
+        if let Some(value) = self.cache_ptr.get(&addr, self.clock.instant()) {
             return Ok(value);
         }
 
+        let key = addr.to_string();
         #[cfg(any(test, feature = "test"))]
         if true {
-            return mock_resolve(&addr.to_string());
+            return self.with_retry(&key, || async { mock_resolve(&key) }).await;
         }
 
-        let ptr_lookup = self.resolver.reverse_lookup(addr).await?;
+        let ptr_lookup = self
+            .with_retry(&key, || self.resolver.reverse_lookup(addr))
+            .await?;
         let ptr = ptr_lookup
             .as_lookup()
             .record_iter()
@@ -332,12 +521,22 @@ impl Resolver {
             })
             .collect::<Vec<_>>();
 
-        Ok(self
-            .cache_ptr
-            .insert(addr, Arc::new(ptr), ptr_lookup.valid_until()))
+        Ok(self.cache_ptr.insert(
+            addr,
+            Arc::new(ptr),
+            self.answer_valid_until(ptr_lookup.valid_until()),
+            self.clock.instant(),
+        ))
     }
 
     pub async fn exists<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<bool> {
+        if let Some(answers) = &self.answers {
+            let key = key.into_fqdn();
+            return Ok(
+                answers.ipv4.contains_key(key.as_ref()) || answers.ipv6.contains_key(key.as_ref())
+            );
+        }
+
         #[cfg(any(test, feature = "test"))]
         if true {
             let key = key.into_fqdn().into_owned();
@@ -353,23 +552,17 @@ impl Resolver {
         }
 
         let key = key.into_fqdn();
+        let name = Name::from_str_relaxed(key.as_ref())?;
         match self
-            .resolver
-            .lookup_ip(Name::from_str_relaxed(key.as_ref())?)
+            .with_retry(key.as_ref(), || self.resolver.lookup_ip(name.clone()))
             .await
         {
             Ok(result) => Ok(result.as_lookup().record_iter().any(|r| {
-                r.data().map_or(false, |d| {
-                    matches!(d.record_type(), RecordType::A | RecordType::AAAA)
-                })
+                r.data()
+                    .is_some_and(|d| matches!(d.record_type(), RecordType::A | RecordType::AAAA))
             })),
-            Err(err) => {
-                if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
-                    Ok(false)
-                } else {
-                    Err(err.into())
-                }
-            }
+            Err(Error::DnsRecordNotFound(_)) => Ok(false),
+            Err(err) => Err(err),
         }
     }
 
@@ -380,8 +573,12 @@ impl Resolver {
         value: impl Into<Txt>,
         valid_until: std::time::Instant,
     ) {
-        self.cache_txt
-            .insert(name.into_fqdn().into_owned(), value.into(), valid_until);
+        self.cache_txt.insert(
+            name.into_fqdn().into_owned(),
+            value.into(),
+            self.valid_until(valid_until),
+            self.clock.instant(),
+        );
     }
 
     #[cfg(any(test, feature = "test"))]
@@ -391,8 +588,12 @@ impl Resolver {
         value: Vec<Ipv4Addr>,
         valid_until: std::time::Instant,
     ) {
-        self.cache_ipv4
-            .insert(name.into_fqdn().into_owned(), Arc::new(value), valid_until);
+        self.cache_ipv4.insert(
+            name.into_fqdn().into_owned(),
+            Arc::new(value),
+            self.valid_until(valid_until),
+            self.clock.instant(),
+        );
     }
 
     #[cfg(any(test, feature = "test"))]
@@ -402,13 +603,22 @@ impl Resolver {
         value: Vec<Ipv6Addr>,
         valid_until: std::time::Instant,
     ) {
-        self.cache_ipv6
-            .insert(name.into_fqdn().into_owned(), Arc::new(value), valid_until);
+        self.cache_ipv6.insert(
+            name.into_fqdn().into_owned(),
+            Arc::new(value),
+            self.valid_until(valid_until),
+            self.clock.instant(),
+        );
     }
 
     #[cfg(any(test, feature = "test"))]
     pub fn ptr_add(&self, name: IpAddr, value: Vec<String>, valid_until: std::time::Instant) {
-        self.cache_ptr.insert(name, Arc::new(value), valid_until);
+        self.cache_ptr.insert(
+            name,
+            Arc::new(value),
+            self.valid_until(valid_until),
+            self.clock.instant(),
+        );
     }
 
     #[cfg(any(test, feature = "test"))]
@@ -418,8 +628,12 @@ impl Resolver {
         value: Vec<MX>,
         valid_until: std::time::Instant,
     ) {
-        self.cache_mx
-            .insert(name.into_fqdn().into_owned(), Arc::new(value), valid_until);
+        self.cache_mx.insert(
+            name.into_fqdn().into_owned(),
+            Arc::new(value),
+            self.valid_until(valid_until),
+            self.clock.instant(),
+        );
     }
 }
 
@@ -575,6 +789,181 @@ impl UnwrapTxtRecord for TlsRpt {
     }
 }
 
+/// Set of DNS answers used to resolve queries offline, see
+/// [`Resolver::with_dns_answers`].
+#[derive(Debug, Clone, Default)]
+pub struct DnsAnswers {
+    txt: HashMap<String, Vec<Vec<u8>>>,
+    mx: HashMap<String, Arc<Vec<MX>>>,
+    ipv4: HashMap<String, Arc<Vec<Ipv4Addr>>>,
+    ipv6: HashMap<String, Arc<Vec<Ipv6Addr>>>,
+    ptr: HashMap<IpAddr, Arc<Vec<String>>>,
+}
+
+impl DnsAnswers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a TXT record, multiple records may be added for the same name.
+    pub fn with_txt<'x>(mut self, name: impl IntoFqdn<'x>, record: impl Into<Vec<u8>>) -> Self {
+        self.txt
+            .entry(name.into_fqdn().into_owned())
+            .or_default()
+            .push(record.into());
+        self
+    }
+
+    /// Adds an MX record.
+    pub fn with_mx<'x>(
+        mut self,
+        name: impl IntoFqdn<'x>,
+        preference: u16,
+        exchange: impl IntoFqdn<'x>,
+    ) -> Self {
+        let records = Arc::make_mut(self.mx.entry(name.into_fqdn().into_owned()).or_default());
+        let exchange = exchange.into_fqdn().into_owned();
+        if let Some(record) = records.iter_mut().find(|r| r.preference == preference) {
+            record.exchanges.push(exchange);
+        } else {
+            records.push(MX {
+                exchanges: vec![exchange],
+                preference,
+            });
+            records.sort_unstable_by_key(|a| a.preference);
+        }
+        self
+    }
+
+    /// Adds an A record.
+    pub fn with_a<'x>(mut self, name: impl IntoFqdn<'x>, addr: Ipv4Addr) -> Self {
+        Arc::make_mut(self.ipv4.entry(name.into_fqdn().into_owned()).or_default()).push(addr);
+        self
+    }
+
+    /// Adds an AAAA record.
+    pub fn with_aaaa<'x>(mut self, name: impl IntoFqdn<'x>, addr: Ipv6Addr) -> Self {
+        Arc::make_mut(self.ipv6.entry(name.into_fqdn().into_owned()).or_default()).push(addr);
+        self
+    }
+
+    /// Adds a PTR record.
+    pub fn with_ptr<'x>(mut self, addr: IpAddr, name: impl IntoFqdn<'x>) -> Self {
+        Arc::make_mut(self.ptr.entry(addr).or_default()).push(name.into_fqdn().into_owned());
+        self
+    }
+
+    /// Adds the records of a zone written in a simplified master file format,
+    /// one `name [ttl] type value` record per line. Supported types are TXT,
+    /// MX, A, AAAA and PTR, with PTR records named after the IP address they
+    /// resolve. TXT values may be split into several quoted strings, which
+    /// are concatenated. TTLs are ignored as offline answers never expire,
+    /// and blank lines or lines starting with `;` or `#` are skipped.
+    pub fn with_zone(mut self, zone: &str) -> crate::Result<Self> {
+        for line in zone.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+
+            let (name, value) = split_token(line)?;
+            let (mut rr_type, mut value) = split_token(value)?;
+            if rr_type.bytes().all(|ch| ch.is_ascii_digit()) {
+                (rr_type, value) = split_token(value)?;
+            }
+
+            self = match rr_type.to_ascii_uppercase().as_str() {
+                "TXT" => self.with_txt(name, parse_txt_value(value)?),
+                "MX" => {
+                    let (preference, exchange) = split_token(value)?;
+                    self.with_mx(
+                        name,
+                        preference.parse().map_err(|_| Error::ParseError)?,
+                        exchange,
+                    )
+                }
+                "A" => self.with_a(name, value.parse().map_err(|_| Error::ParseError)?),
+                "AAAA" => self.with_aaaa(name, value.parse().map_err(|_| Error::ParseError)?),
+                "PTR" => self.with_ptr(name.parse().map_err(|_| Error::ParseError)?, value),
+                _ => return Err(Error::ParseError),
+            };
+        }
+
+        Ok(self)
+    }
+
+    fn txt_lookup<T: TxtRecordParser + Into<Txt> + UnwrapTxtRecord>(
+        &self,
+        key: &str,
+    ) -> crate::Result<Txt> {
+        let mut result = Err(Error::InvalidRecordType);
+        for record in Self::lookup(&self.txt, key)? {
+            match T::parse(record) {
+                Err(Error::InvalidRecordType) => (),
+                record => {
+                    result = record;
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(result.into())
+    }
+
+    fn lookup<'x, K, V, Q>(answers: &'x HashMap<K, V>, key: &Q) -> crate::Result<&'x V>
+    where
+        K: Borrow<Q> + Hash + Eq,
+        Q: Hash + Eq + ?Sized,
+    {
+        answers
+            .get(key)
+            .ok_or(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+}
+
+fn addr_config(addr: SocketAddr, protocol: Protocol) -> ResolverConfig {
+    // Without a TCP connection, truncated responses would be returned as is
+    let mut name_servers = vec![NameServerConfig::new(addr, protocol)];
+    if protocol == Protocol::Udp {
+        name_servers.push(NameServerConfig::new(addr, Protocol::Tcp));
+    }
+
+    ResolverConfig::from_parts(None, vec![], NameServerConfigGroup::from(name_servers))
+}
+
+fn split_token(value: &str) -> crate::Result<(&str, &str)> {
+    value
+        .split_once(char::is_whitespace)
+        .map(|(token, rest)| (token, rest.trim_start()))
+        .filter(|(token, rest)| !token.is_empty() && !rest.is_empty())
+        .ok_or(Error::ParseError)
+}
+
+fn parse_txt_value(value: &str) -> crate::Result<Vec<u8>> {
+    if !value.starts_with('"') {
+        return Ok(value.as_bytes().to_vec());
+    }
+
+    let mut result = Vec::with_capacity(value.len());
+    let mut chars = value.bytes();
+    while let Some(ch) = chars.next() {
+        match ch {
+            b'"' => loop {
+                match chars.next().ok_or(Error::ParseError)? {
+                    b'"' => break,
+                    b'\\' => result.push(chars.next().ok_or(Error::ParseError)?),
+                    ch => result.push(ch),
+                }
+            },
+            ch if ch.is_ascii_whitespace() => (),
+            _ => return Err(Error::ParseError),
+        }
+    }
+
+    Ok(result)
+}
+
 pub trait IntoFqdn<'x> {
     fn into_fqdn(self) -> Cow<'x, str>;
 }
@@ -658,10 +1047,365 @@ pub fn mock_resolve<T>(domain: &str) -> crate::Result<T> {
 }
 
 #[cfg(test)]
-mod test {
-    use std::net::IpAddr;
+pub(crate) mod test {
+    use std::{
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        sync::{
+            atomic::{AtomicU32, AtomicU64, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use hickory_resolver::config::{Protocol, ResolverConfig, ResolverOpts};
+
+    use crate::{
+        common::{
+            clock::Clock,
+            lru::DnsCache,
+            parse::TxtRecordParser,
+            resolver::{DnsAnswers, ToReverseName},
+            retry::DnsRetryPolicy,
+        },
+        spf::Spf,
+        Error, Resolver,
+    };
+
+    /// Clock that only moves when advanced by the test
+    #[derive(Clone)]
+    pub(crate) struct FrozenClock {
+        start: Instant,
+        elapsed: Arc<AtomicU64>,
+    }
 
-    use crate::common::resolver::ToReverseName;
+    impl FrozenClock {
+        pub(crate) fn new(unix_time: u64) -> Self {
+            FrozenClock {
+                start: Instant::now(),
+                elapsed: Arc::new(AtomicU64::new(unix_time)),
+            }
+        }
+
+        pub(crate) fn advance(&self, secs: u64) {
+            self.elapsed.fetch_add(secs, Ordering::Relaxed);
+        }
+    }
+
+    impl Clock for FrozenClock {
+        fn now(&self) -> SystemTime {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(self.elapsed.load(Ordering::Relaxed))
+        }
+
+        fn instant(&self) -> Instant {
+            self.start + Duration::from_secs(self.elapsed.load(Ordering::Relaxed))
+        }
+    }
+
+    #[tokio::test]
+    async fn dns_answers_zone() {
+        let answers = DnsAnswers::new()
+            .with_zone(concat!(
+                "; Comments and blank lines are skipped\n",
+                "\n",
+                "example.org. 3600 TXT \"v=spf1 \" \"mx -all\"\n",
+                "# TTLs are optional\n",
+                "Example.org TXT google-site-verification=abc123\n",
+                "example.org. MX 20 mx2.example.org.\n",
+                "example.org. mx 10 mx1.example.org\n",
+                "mx1.example.org. 300 A 192.0.2.1\n",
+                "mx1.example.org. 300 AAAA 2001:db8::1\n",
+                "192.0.2.1 PTR mx1.example.org.\n",
+                "quoted.example.org. TXT \"escaped \\\"quote\\\"\"\n",
+            ))
+            .unwrap();
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(answers);
+
+        assert_eq!(
+            resolver
+                .txt_lookup::<Spf>("example.org")
+                .await
+                .unwrap()
+                .as_ref(),
+            &Spf::parse(b"v=spf1 mx -all").unwrap()
+        );
+        let mx = resolver.mx_lookup("example.org").await.unwrap();
+        assert_eq!(
+            mx.iter()
+                .map(|mx| (mx.preference, mx.exchanges.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (10, vec!["mx1.example.org.".to_string()]),
+                (20, vec!["mx2.example.org.".to_string()])
+            ]
+        );
+        assert_eq!(
+            resolver
+                .ipv4_lookup("mx1.example.org")
+                .await
+                .unwrap()
+                .as_ref(),
+            &["192.0.2.1".parse::<Ipv4Addr>().unwrap()]
+        );
+        assert_eq!(
+            resolver
+                .ipv6_lookup("mx1.example.org")
+                .await
+                .unwrap()
+                .as_ref(),
+            &["2001:db8::1".parse::<Ipv6Addr>().unwrap()]
+        );
+        assert_eq!(
+            resolver
+                .ptr_lookup("192.0.2.1".parse().unwrap())
+                .await
+                .unwrap()
+                .as_ref(),
+            &["mx1.example.org.".to_string()]
+        );
+
+        for zone in [
+            "example.org.",
+            "example.org. TXT",
+            "example.org. 3600 CNAME example.net.",
+            "example.org. MX mx1.example.org.",
+            "example.org. A 2001:db8::1",
+            "example.org. PTR mx1.example.org.",
+            "example.org. TXT \"unterminated",
+            "example.org. TXT \"v=spf1\" -all",
+        ] {
+            assert_eq!(
+                DnsAnswers::new().with_zone(zone).map(|_| ()),
+                Err(Error::ParseError),
+                "{zone}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dns_answers_over_cache() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        let valid_until = Instant::now() + Duration::from_secs(3600);
+        resolver.txt_add(
+            "example.org",
+            Spf::parse(b"v=spf1 +all").unwrap(),
+            valid_until,
+        );
+        resolver.mx_add("example.org", vec![], valid_until);
+        resolver.ipv4_add("example.org", vec![Ipv4Addr::LOCALHOST], valid_until);
+        resolver.ipv6_add("example.org", vec![Ipv6Addr::LOCALHOST], valid_until);
+        resolver.ptr_add(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            vec!["example.org.".to_string()],
+            valid_until,
+        );
+
+        // Cached entries do not shadow the offline answers
+        let resolver = resolver.with_dns_answers(
+            DnsAnswers::new()
+                .with_txt("example.org", "v=spf1 -all")
+                .with_a("example.org", Ipv4Addr::new(192, 0, 2, 1)),
+        );
+        assert_eq!(
+            resolver
+                .txt_lookup::<Spf>("example.org")
+                .await
+                .unwrap()
+                .as_ref(),
+            &Spf::parse(b"v=spf1 -all").unwrap()
+        );
+        assert_eq!(
+            resolver.ipv4_lookup("example.org").await.unwrap().as_ref(),
+            &[Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        assert!(matches!(
+            resolver.mx_lookup("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+        assert!(matches!(
+            resolver.ipv6_lookup("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+        assert!(matches!(
+            resolver.ptr_lookup(IpAddr::V4(Ipv4Addr::LOCALHOST)).await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dns_uncached() {
+        let resolver =
+            Resolver::new_uncached(ResolverConfig::default(), ResolverOpts::default()).unwrap();
+        let valid_until = Instant::now() + Duration::from_secs(3600);
+        resolver.txt_add(
+            "example.org",
+            Spf::parse(b"v=spf1 -all").unwrap(),
+            valid_until,
+        );
+        resolver.ipv4_add("example.org", vec![Ipv4Addr::LOCALHOST], valid_until);
+        resolver.ptr_add(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            vec!["example.org.".to_string()],
+            valid_until,
+        );
+
+        assert!(matches!(
+            resolver.txt_lookup::<Spf>("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+        assert!(matches!(
+            resolver.ipv4_lookup("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+        assert!(matches!(
+            resolver.ptr_lookup(IpAddr::V4(Ipv4Addr::LOCALHOST)).await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+
+        // Answers are not kept after a lookup, unlike with a cached resolver
+        let answers = DnsAnswers::new().with_txt("example.org", "v=spf1 -all");
+        for (resolver, expect_cached) in [
+            (
+                Resolver::new_uncached(ResolverConfig::default(), ResolverOpts::default()).unwrap(),
+                false,
+            ),
+            (Resolver::new_system_conf().unwrap(), true),
+        ] {
+            let resolver = resolver
+                .with_min_ttl(Duration::from_secs(3600))
+                .with_dns_answers(answers.clone());
+            assert!(resolver.txt_lookup::<Spf>("example.org").await.is_ok());
+            assert_eq!(
+                resolver
+                    .cache_txt
+                    .get("example.org.", Instant::now())
+                    .is_some(),
+                expect_cached
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dns_cache_ttl() {
+        // Records with a TTL of 0 are not cached
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.org",
+            Spf::parse(b"v=spf1 -all").unwrap(),
+            Instant::now(),
+        );
+        assert!(matches!(
+            resolver.txt_lookup::<Spf>("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+
+        // Unless a minimum TTL is configured
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_min_ttl(Duration::from_secs(60));
+        resolver.txt_add(
+            "example.org",
+            Spf::parse(b"v=spf1 -all").unwrap(),
+            Instant::now(),
+        );
+        assert!(resolver.txt_lookup::<Spf>("example.org").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn dns_cache_clock() {
+        let clock = FrozenClock::new(0);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_clock(clock.clone());
+        resolver.txt_add(
+            "example.org",
+            Spf::parse(b"v=spf1 -all").unwrap(),
+            clock.instant() + Duration::from_secs(60),
+        );
+        assert!(resolver.txt_lookup::<Spf>("example.org").await.is_ok());
+
+        // Records expire once the clock moves past their TTL
+        clock.advance(30);
+        assert!(resolver.txt_lookup::<Spf>("example.org").await.is_ok());
+        clock.advance(31);
+        assert!(matches!(
+            resolver.txt_lookup::<Spf>("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dns_circuit_breaker() {
+        let clock = FrozenClock::new(0);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_dns_retry_policy(
+                DnsRetryPolicy::new().with_circuit_breaker(2, Duration::from_secs(60)),
+            );
+
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.txt_lookup::<Spf>("_dns_error.example.org").await,
+                Err(Error::DnsError("".to_string()))
+            );
+        }
+
+        // Lookups are suspended after two consecutive failures
+        assert!(matches!(
+            resolver.txt_lookup::<Spf>("_dns_error.example.org").await,
+            Err(Error::DnsError(err)) if err.contains("suspended")
+        ));
+        assert!(matches!(
+            resolver.txt_lookup::<Spf>("example.org").await,
+            Err(Error::DnsRecordNotFound(_))
+        ));
+
+        // And resumed once the cooldown has elapsed
+        clock.advance(61);
+        assert_eq!(
+            resolver.txt_lookup::<Spf>("_dns_error.example.org").await,
+            Err(Error::DnsError("".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn dns_retry_backoff() {
+        let policy = DnsRetryPolicy::new()
+            .with_retries(2)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(2));
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(1), Duration::from_millis(2));
+        assert_eq!(policy.backoff(5), Duration::from_millis(2));
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_retry_policy(policy);
+
+        // Temporary failures are retried
+        let attempts = AtomicU32::new(0);
+        let result: crate::Result<()> = resolver
+            .with_retry("example.org.", || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(Error::DnsError("timeout".to_string()))
+            })
+            .await;
+        assert_eq!(result, Err(Error::DnsError("timeout".to_string())));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+
+        // Permanent failures are not
+        let attempts = AtomicU32::new(0);
+        let result: crate::Result<()> = resolver
+            .with_retry("example.org.", || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(Error::DnsRecordNotFound(
+                    hickory_resolver::proto::op::ResponseCode::NXDomain,
+                ))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
 
     #[test]
     fn reverse_lookup_addr() {
@@ -679,4 +1423,96 @@ mod test {
             assert_eq!(addr.parse::<IpAddr>().unwrap().to_reverse_name(), expected);
         }
     }
+
+    #[tokio::test]
+    async fn resolver_with_addr() {
+        let addr = "192.0.2.1:53".parse().unwrap();
+        for (protocol, expected) in [
+            (Protocol::Udp, vec![Protocol::Udp, Protocol::Tcp]),
+            (Protocol::Tcp, vec![Protocol::Tcp]),
+        ] {
+            let config = super::addr_config(addr, protocol);
+            assert!(config.domain().is_none());
+            assert!(config.search().is_empty());
+            assert_eq!(
+                config
+                    .name_servers()
+                    .iter()
+                    .map(|ns| (ns.socket_addr, ns.protocol))
+                    .collect::<Vec<_>>(),
+                expected
+                    .into_iter()
+                    .map(|protocol| (addr, protocol))
+                    .collect::<Vec<_>>(),
+                "{protocol:?}"
+            );
+            Resolver::new_with_addr(addr, protocol).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn resolver_truncated_udp_response() {
+        use hickory_resolver::proto::{
+            op::{Message, MessageType},
+            rr::{rdata::TXT, RData, Record},
+        };
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{TcpListener, UdpSocket},
+        };
+
+        let record = ["a".repeat(255), "b".repeat(255), "c".repeat(255)];
+        let response = move |request: &[u8], truncated: bool| {
+            let request = Message::from_vec(request).unwrap();
+            let mut response = Message::new();
+            response
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_desired(request.recursion_desired())
+                .set_recursion_available(true)
+                .set_truncated(truncated)
+                .add_queries(request.queries().to_vec());
+            if !truncated {
+                response.add_answer(Record::from_rdata(
+                    request.queries()[0].name().clone(),
+                    300,
+                    RData::TXT(TXT::new(record.to_vec())),
+                ));
+            }
+            response.to_vec().unwrap()
+        };
+
+        // UDP answers are always truncated, TCP answers are complete
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).await.unwrap();
+        let udp_response = response.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            while let Ok((len, peer)) = udp.recv_from(&mut buf).await {
+                udp.send_to(&udp_response(&buf[..len], true), peer)
+                    .await
+                    .ok();
+            }
+        });
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = tcp.accept().await {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).await.unwrap();
+                let mut request = vec![0u8; u16::from_be_bytes(len) as usize];
+                stream.read_exact(&mut request).await.unwrap();
+                let response = response(&request, false);
+                stream
+                    .write_all(&(response.len() as u16).to_be_bytes())
+                    .await
+                    .unwrap();
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+
+        let resolver = Resolver::new_with_addr(addr, Protocol::Udp).unwrap();
+        let txt = resolver.txt_raw_lookup("large.example.org").await.unwrap();
+        assert_eq!(txt.len(), 765);
+        assert!(txt.starts_with(b"aaa") && txt.ends_with(b"ccc"));
+    }
 }
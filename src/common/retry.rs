@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::time::{Duration, Instant};
+
+use hickory_resolver::proto::op::ResponseCode;
+use parking_lot::Mutex;
+
+use crate::Error;
+
+const MAX_TRACKED_NAMES: usize = 1024;
+
+/// Policy applied to temporary DNS failures, such as timeouts or `SERVFAIL`
+/// responses. By default failed lookups are not retried beyond the attempts
+/// made by the underlying resolver and the circuit breaker is disabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsRetryPolicy {
+    pub(crate) retries: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) failure_threshold: u32,
+    pub(crate) cooldown: Duration,
+}
+
+impl DnsRetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of times a lookup failing temporarily is retried.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, which doubles on every
+    /// subsequent retry up to `max_backoff`.
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// After `failure_threshold` consecutive temporary failures for a name,
+    /// lookups for that name fail immediately with a temporary error until
+    /// `cooldown` has elapsed. A threshold of 0 disables the circuit breaker.
+    pub fn with_circuit_breaker(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.failure_threshold = failure_threshold;
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for DnsRetryPolicy {
+    fn default() -> Self {
+        DnsRetryPolicy {
+            retries: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            failure_threshold: 0,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FailureState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Consecutive temporary failures per name, used by the circuit breaker.
+pub(crate) struct DnsFailures {
+    names: Mutex<lru_cache::LruCache<String, FailureState, ahash::RandomState>>,
+}
+
+impl DnsFailures {
+    pub(crate) fn new() -> Self {
+        DnsFailures {
+            names: Mutex::new(lru_cache::LruCache::with_hasher(
+                MAX_TRACKED_NAMES,
+                ahash::RandomState::new(),
+            )),
+        }
+    }
+
+    pub(crate) fn is_open(&self, name: &str, now: Instant) -> bool {
+        self.names
+            .lock()
+            .get_mut(name)
+            .and_then(|state| state.open_until)
+            .is_some_and(|open_until| open_until > now)
+    }
+
+    pub(crate) fn record<T>(
+        &self,
+        name: &str,
+        result: &crate::Result<T>,
+        policy: &DnsRetryPolicy,
+        now: Instant,
+    ) {
+        let mut names = self.names.lock();
+        if !matches!(result, Err(err) if is_temporary(err)) {
+            names.remove(name);
+        } else if policy.failure_threshold > 0 {
+            let failures = names.get_mut(name).map_or(1, |state| state.failures + 1);
+            let state = if failures >= policy.failure_threshold {
+                FailureState {
+                    failures: 0,
+                    open_until: Some(now + policy.cooldown),
+                }
+            } else {
+                FailureState {
+                    failures,
+                    open_until: None,
+                }
+            };
+            names.insert(name.to_string(), state);
+        }
+    }
+}
+
+impl Clone for DnsFailures {
+    fn clone(&self) -> Self {
+        DnsFailures {
+            names: Mutex::new(self.names.lock().clone()),
+        }
+    }
+}
+
+/// Whether a lookup error is transient and worth retrying.
+pub(crate) fn is_temporary(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::DnsError(_) | Error::DnsRecordNotFound(ResponseCode::ServFail)
+    )
+}
@@ -12,19 +12,35 @@ use std::net::IpAddr;
 
 use crate::{dkim::Canonicalization, Error, IprevOutput, IprevResult, Resolver};
 
-use super::crypto::{Algorithm, VerifyingKey};
+use super::{
+    crypto::{Algorithm, VerifyingKey, VerifyingKeyType},
+    metrics::Metrics,
+};
 
 pub struct DomainKey {
     pub p: Box<dyn VerifyingKey + Send + Sync>,
+    pub(crate) k: VerifyingKeyType,
     pub f: u64,
+    pub(crate) unknown_tags: Vec<String>,
+    pub(crate) record: Option<String>,
 }
 
 impl Resolver {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     pub async fn verify_iprev(&self, addr: IpAddr) -> IprevOutput {
+        let mut metrics = Metrics::new();
+        let output = self.verify_iprev_(addr, &mut metrics).await;
+        metrics.finish("iprev", output.result.as_keyword());
+        output
+    }
+
+    async fn verify_iprev_(&self, addr: IpAddr, metrics: &mut Metrics) -> IprevOutput {
+        metrics.dns_lookup();
         match self.ptr_lookup(addr).await {
             Ok(ptr) => {
                 let mut last_err = None;
                 for host in ptr.iter().take(2) {
+                    metrics.dns_lookup();
                     match &addr {
                         IpAddr::V4(ip) => match self.ipv4_lookup(host).await {
                             Ok(ips) => {
@@ -79,6 +95,11 @@ impl IprevOutput {
 }
 
 impl DomainKey {
+    /// Returns the public key type published in the `k=` tag.
+    pub fn key_type(&self) -> VerifyingKeyType {
+        self.k
+    }
+
     pub(crate) fn verify<'a>(
         &self,
         headers: &mut dyn Iterator<Item = (&'a [u8], &'a [u8])>,
@@ -104,8 +125,8 @@ pub trait VerifySignature {
     fn algorithm(&self) -> Algorithm;
 
     fn domain_key(&self) -> String {
-        let s = self.selector();
-        let d = self.domain();
+        let s = self.selector().trim_end_matches('.');
+        let d = self.domain().trim_end_matches('.');
         let mut key = String::with_capacity(s.len() + d.len() + 13);
         key.push_str(s);
         key.push_str("._domainkey.");
@@ -8,11 +8,20 @@
  * except according to those terms.
  */
 
-use crate::common::crypto::{HashAlgorithm, SigningKey};
+use crate::{
+    common::{
+        address::split_address,
+        crypto::{HashAlgorithm, Signer},
+    },
+    Error,
+};
+
+#[cfg(feature = "jwk")]
+use crate::common::crypto::JwkKey;
 
 use super::{Canonicalization, DkimSigner, Done, NeedDomain, NeedHeaders, NeedSelector, Signature};
 
-impl<T: SigningKey> DkimSigner<T> {
+impl<T: Signer> DkimSigner<T> {
     pub fn from_key(key: T) -> DkimSigner<T, NeedDomain> {
         DkimSigner {
             _state: Default::default(),
@@ -22,11 +31,22 @@ impl<T: SigningKey> DkimSigner<T> {
                 ..Default::default()
             },
             key,
+            crlf_normalization: false,
+            body_length_marker: None,
         }
     }
 }
 
-impl<T: SigningKey> DkimSigner<T, NeedDomain> {
+#[cfg(feature = "jwk")]
+impl DkimSigner<JwkKey> {
+    /// Creates a signer from a private key in JWK format, selecting the
+    /// signing algorithm that matches the key type.
+    pub fn from_jwk(json: &str) -> crate::Result<DkimSigner<JwkKey, NeedDomain>> {
+        JwkKey::from_json(json).map(DkimSigner::from_key)
+    }
+}
+
+impl<T: Signer> DkimSigner<T, NeedDomain> {
     /// Sets the domain to use for signing.
     pub fn domain(mut self, domain: impl Into<String>) -> DkimSigner<T, NeedSelector> {
         self.template.d = domain.into();
@@ -34,11 +54,13 @@ impl<T: SigningKey> DkimSigner<T, NeedDomain> {
             _state: Default::default(),
             key: self.key,
             template: self.template,
+            crlf_normalization: self.crlf_normalization,
+            body_length_marker: self.body_length_marker,
         }
     }
 }
 
-impl<T: SigningKey> DkimSigner<T, NeedSelector> {
+impl<T: Signer> DkimSigner<T, NeedSelector> {
     /// Sets the selector to use for signing.
     pub fn selector(mut self, selector: impl Into<String>) -> DkimSigner<T, NeedHeaders> {
         self.template.s = selector.into();
@@ -46,11 +68,13 @@ impl<T: SigningKey> DkimSigner<T, NeedSelector> {
             _state: Default::default(),
             key: self.key,
             template: self.template,
+            crlf_normalization: self.crlf_normalization,
+            body_length_marker: self.body_length_marker,
         }
     }
 }
 
-impl<T: SigningKey> DkimSigner<T, NeedHeaders> {
+impl<T: Signer> DkimSigner<T, NeedHeaders> {
     /// Sets the headers to sign.
     pub fn headers(
         mut self,
@@ -61,11 +85,13 @@ impl<T: SigningKey> DkimSigner<T, NeedHeaders> {
             _state: Default::default(),
             key: self.key,
             template: self.template,
+            crlf_normalization: self.crlf_normalization,
+            body_length_marker: self.body_length_marker,
         }
     }
 }
 
-impl<T: SigningKey> DkimSigner<T, Done> {
+impl<T: Signer> DkimSigner<T, Done> {
     /// Sets the third party signature.
     pub fn atps(mut self, atps: impl Into<String>) -> Self {
         self.template.atps = Some(atps.into());
@@ -90,12 +116,38 @@ impl<T: SigningKey> DkimSigner<T, Done> {
         self
     }
 
-    /// Include the body length in the signature.
+    /// Include the body length in the signature. When enabled, the `l=` tag
+    /// is set to the length of the body at signing time, so content appended
+    /// afterwards (such as a mailing list footer) does not invalidate the
+    /// signature.
+    ///
+    /// Note that this allows anyone relaying the message to append arbitrary
+    /// content, including a replacement body hidden behind MIME boundaries,
+    /// without breaking the signature. For this reason verifiers in strict
+    /// mode reject signatures carrying `l=`, so it should only be enabled
+    /// when surviving intermediaries matters more than body integrity.
     pub fn body_length(mut self, body_length: bool) -> Self {
         self.template.l = u64::from(body_length);
         self
     }
 
+    /// Signs the body only up to the last occurrence of `marker`, such as a
+    /// `"\r\n-- \r\n"` signature separator, setting the `l=` tag to the
+    /// offset at which the marker starts. Both the body and the marker are
+    /// searched in their canonicalized form, so that `l=` counts canonicalized
+    /// octets as RFC 6376 requires. If the marker is not found, or is
+    /// found at the start of the body, the whole body is signed and `l=` is
+    /// only included if [`DkimSigner::body_length`] is enabled.
+    ///
+    /// Everything from the marker onwards is left unsigned, so the same
+    /// caveats as [`DkimSigner::body_length`] apply: the unsigned part can be
+    /// replaced with arbitrary content without breaking the signature, and
+    /// verifiers in strict mode reject signatures carrying `l=`.
+    pub fn body_length_before_marker(mut self, marker: impl Into<Vec<u8>>) -> Self {
+        self.body_length_marker = Some(marker.into());
+        self
+    }
+
     /// Request reports.
     pub fn reporting(mut self, reporting: bool) -> Self {
         self.template.r = reporting;
@@ -113,4 +165,46 @@ impl<T: SigningKey> DkimSigner<T, Done> {
         self.template.cb = cb;
         self
     }
+
+    /// Sets both the header and body canonicalization algorithms.
+    pub fn canonicalization(self, ch: Canonicalization, cb: Canonicalization) -> Self {
+        self.header_canonicalization(ch).body_canonicalization(cb)
+    }
+
+    /// Converts bare LF and lone CR line endings to CRLF before signing,
+    /// so that messages built with `\n` separators obtain a signature that
+    /// validates once the message is transmitted with CRLF line endings.
+    /// When signing chained messages, the chunks are first copied into a
+    /// single buffer.
+    pub fn crlf_normalization(mut self, crlf_normalization: bool) -> Self {
+        self.crlf_normalization = crlf_normalization;
+        self
+    }
+
+    /// Validates the signer configuration, returning
+    /// [`Error::MissingParameters`] if the domain or selector are empty or the
+    /// `From` header is not signed (RFC 6376, Section 5.4), and
+    /// [`Error::FailedAuidMatch`] if the agent or user identifier is not
+    /// within the signing domain.
+    pub fn build(self) -> crate::Result<Self> {
+        let template = &self.template;
+        if template.d.is_empty()
+            || template.s.is_empty()
+            || !template.h.iter().any(|h| h.eq_ignore_ascii_case("From"))
+        {
+            return Err(Error::MissingParameters);
+        }
+
+        if !template.i.is_empty() {
+            let domain = template.d.to_lowercase();
+            let auid_domain = split_address(&template.i)
+                .map_or(template.i.as_str(), |(_, d)| d)
+                .to_lowercase();
+            if auid_domain != domain && !auid_domain.ends_with(&format!(".{domain}")) {
+                return Err(Error::FailedAuidMatch);
+            }
+        }
+
+        Ok(self)
+    }
 }
@@ -15,71 +15,192 @@ use super::{Canonicalization, Signature};
 pub struct CanonicalBody<'a> {
     canonicalization: Canonicalization,
     body: &'a [u8],
+    l: u64,
+}
+
+impl CanonicalBody<'_> {
+    /// Limits the body to the octets preceding the last occurrence of
+    /// `marker` once both are canonicalized, returning the new length, or
+    /// `None` if the marker does not appear after the start of the body.
+    pub(crate) fn truncate_before(&mut self, marker: &[u8]) -> Option<u64> {
+        let canonicalize = |bytes: &[u8]| {
+            let mut result = Vec::with_capacity(bytes.len() + 2);
+            let mut canonicalizer = BodyCanonicalizer::new(self.canonicalization);
+            canonicalizer.write(bytes, &mut result);
+            canonicalizer.finish(&mut result);
+            result
+        };
+        let mut marker_ = canonicalize(marker);
+        if !marker.ends_with(b"\n") && marker_.ends_with(b"\r\n") {
+            marker_.truncate(marker_.len() - 2);
+        }
+        if marker_.is_empty() {
+            return None;
+        }
+        let pos = canonicalize(self.body)
+            .windows(marker_.len())
+            .rposition(|window| window == marker_)
+            .filter(|&pos| pos > 0)?;
+        self.l = pos as u64;
+        Some(self.l)
+    }
+}
+
+impl CanonicalBody<'_> {
+    /// Returns the length of the canonicalized body, regardless of `l=`.
+    pub(crate) fn len(&self) -> u64 {
+        let mut writer = BodyLimit {
+            writer: Vec::new(),
+            remaining: 0,
+            len: 0,
+        };
+        let mut canonicalizer = BodyCanonicalizer::new(self.canonicalization);
+        canonicalizer.write(self.body, &mut writer);
+        canonicalizer.finish(&mut writer);
+        writer.len
+    }
 }
 
 impl Writable for CanonicalBody<'_> {
     fn write(self, hasher: &mut impl Writer) {
-        let mut crlf_seq = 0;
+        let mut canonicalizer = BodyCanonicalizer::new(self.canonicalization);
+        let mut writer = BodyLimit::new(hasher, self.l);
+        canonicalizer.write(self.body, &mut writer);
+        canonicalizer.finish(&mut writer);
+    }
+}
+
+/// Passes through the first `l=` octets of a canonicalized body, or all
+/// of them if `l=` is zero, while counting the length of the whole body.
+pub(crate) struct BodyLimit<W> {
+    pub(crate) writer: W,
+    remaining: u64,
+    pub(crate) len: u64,
+}
+
+impl<W: Writer> BodyLimit<W> {
+    pub(crate) fn new(writer: W, l: u64) -> Self {
+        BodyLimit {
+            writer,
+            remaining: if l > 0 { l } else { u64::MAX },
+            len: 0,
+        }
+    }
+}
+
+impl<W: Writer> Writer for BodyLimit<W> {
+    fn write(&mut self, buf: &[u8]) {
+        let len = std::cmp::min(self.remaining, buf.len() as u64);
+        if len > 0 {
+            self.writer.write(&buf[..len as usize]);
+            self.remaining -= len;
+        }
+        self.len += buf.len() as u64;
+    }
+}
+
+/// Incremental body canonicalizer, allows a message body to be
+/// canonicalized and hashed in chunks.
+///
+/// Lines may end in either CRLF or a bare LF. A bare CR that is not
+/// followed by LF is not a line ending and is hashed as literal data,
+/// matching the behavior of OpenDKIM.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BodyCanonicalizer {
+    canonicalization: Canonicalization,
+    crlf_seq: usize,
+    last_ch: u8,
+    is_empty: bool,
+    pending_cr: bool,
+}
 
+impl BodyCanonicalizer {
+    pub(crate) fn new(canonicalization: Canonicalization) -> Self {
+        BodyCanonicalizer {
+            canonicalization,
+            crlf_seq: 0,
+            last_ch: 0,
+            is_empty: true,
+            pending_cr: false,
+        }
+    }
+
+    pub(crate) fn write(&mut self, body: &[u8], hasher: &mut impl Writer) {
+        for &ch in body {
+            match ch {
+                b'\r' => {
+                    // Wait for the next byte, which may be in the next chunk
+                    if self.pending_cr {
+                        self.write_ch(b'\r', hasher);
+                    }
+                    self.pending_cr = true;
+                }
+                b'\n' => {
+                    self.pending_cr = false;
+                    self.write_ch(ch, hasher);
+                }
+                _ => {
+                    if std::mem::take(&mut self.pending_cr) {
+                        self.write_ch(b'\r', hasher);
+                    }
+                    self.write_ch(ch, hasher);
+                }
+            }
+        }
+    }
+
+    fn write_ch(&mut self, ch: u8, hasher: &mut impl Writer) {
         match self.canonicalization {
             Canonicalization::Relaxed => {
-                let mut last_ch = 0;
-                let mut is_empty = true;
-
-                for &ch in self.body {
-                    match ch {
-                        b' ' | b'\t' => {
-                            while crlf_seq > 0 {
-                                hasher.write(b"\r\n");
-                                crlf_seq -= 1;
-                            }
-                            is_empty = false;
+                match ch {
+                    b' ' | b'\t' => {
+                        while self.crlf_seq > 0 {
+                            hasher.write(b"\r\n");
+                            self.crlf_seq -= 1;
                         }
-                        b'\n' => {
-                            crlf_seq += 1;
+                        self.is_empty = false;
+                    }
+                    b'\n' => {
+                        self.crlf_seq += 1;
+                    }
+                    _ => {
+                        while self.crlf_seq > 0 {
+                            hasher.write(b"\r\n");
+                            self.crlf_seq -= 1;
                         }
-                        b'\r' => {}
-                        _ => {
-                            while crlf_seq > 0 {
-                                hasher.write(b"\r\n");
-                                crlf_seq -= 1;
-                            }
 
-                            if last_ch == b' ' || last_ch == b'\t' {
-                                hasher.write(b" ");
-                            }
-
-                            hasher.write(&[ch]);
-                            is_empty = false;
+                        if self.last_ch == b' ' || self.last_ch == b'\t' {
+                            hasher.write(b" ");
                         }
-                    }
 
-                    last_ch = ch;
+                        hasher.write(&[ch]);
+                        self.is_empty = false;
+                    }
                 }
 
-                if !is_empty {
-                    hasher.write(b"\r\n");
-                }
+                self.last_ch = ch;
             }
-            Canonicalization::Simple => {
-                for &ch in self.body {
-                    match ch {
-                        b'\n' => {
-                            crlf_seq += 1;
-                        }
-                        b'\r' => {}
-                        _ => {
-                            while crlf_seq > 0 {
-                                hasher.write(b"\r\n");
-                                crlf_seq -= 1;
-                            }
-                            hasher.write(&[ch]);
-                        }
+            Canonicalization::Simple => match ch {
+                b'\n' => {
+                    self.crlf_seq += 1;
+                }
+                _ => {
+                    while self.crlf_seq > 0 {
+                        hasher.write(b"\r\n");
+                        self.crlf_seq -= 1;
                     }
+                    hasher.write(&[ch]);
                 }
+            },
+        }
+    }
 
-                hasher.write(b"\r\n");
-            }
+    pub(crate) fn finish(mut self, hasher: &mut impl Writer) {
+        if self.pending_cr {
+            self.write_ch(b'\r', hasher);
+        }
+        if self.canonicalization == Canonicalization::Simple || !self.is_empty {
+            hasher.write(b"\r\n");
         }
     }
 }
@@ -138,14 +259,13 @@ impl Canonicalization {
         }
     }
 
+    /// Returns the canonicalized form of `body`, limited to its first `l`
+    /// octets once canonicalized, or the whole body if `l` is zero.
     pub fn canonical_body<'a>(&self, body: &'a [u8], l: u64) -> CanonicalBody<'a> {
         CanonicalBody {
             canonicalization: *self,
-            body: if l == 0 || body.is_empty() {
-                body
-            } else {
-                &body[..std::cmp::min(l as usize, body.len())]
-            },
+            body,
+            l,
         }
     }
 
@@ -181,7 +301,7 @@ impl Signature {
         let body = message.body();
         let body_len = body.len();
         let canonical_headers = self.ch.canonical_headers(headers);
-        let canonical_body = self.ch.canonical_body(body, u64::MAX);
+        let canonical_body = self.cb.canonical_body(body, 0);
 
         // Add any missing headers
         signed_headers.reverse();
@@ -211,13 +331,13 @@ impl<'a> Writable for CanonicalHeaders<'a> {
 mod test {
     use mail_builder::encoders::base64::base64_encode;
 
-    use super::{CanonicalBody, CanonicalHeaders};
+    use super::{BodyCanonicalizer, CanonicalBody, CanonicalHeaders};
     use crate::{
         common::{
             crypto::{HashImpl, Sha256},
             headers::{HeaderIterator, Writable},
         },
-        dkim::Canonicalization,
+        dkim::{Canonicalization, Signature},
     };
 
     #[test]
@@ -249,30 +369,30 @@ mod test {
                 ),
                 (
                     concat!("from:John doe <jdoe@domain.com>\r\n", "subject:test\r\n"),
-                    concat!(" body\r\n"),
+                    " body\r\n",
                 ),
                 (
                     concat!(
                         "  From : John\tdoe <jdoe@domain.com>\t\r\n",
                         "SUB JECT:\ttest  \t  \r\n"
                     ),
-                    concat!(" body \t   \r\n"),
+                    " body \t   \r\n",
                 ),
             ),
             (
-                concat!("H: value\t\r\n\r\n",),
-                (concat!("h:value\r\n"), concat!("")),
-                (concat!("H: value\t\r\n"), concat!("\r\n")),
+                "H: value\t\r\n\r\n",
+                ("h:value\r\n", ""),
+                ("H: value\t\r\n", "\r\n"),
             ),
             (
-                concat!("\tx\t: \t\t\tz\r\n\r\nabc",),
-                (concat!("x:z\r\n"), concat!("abc\r\n")),
-                ("\tx\t: \t\t\tz\r\n", concat!("abc\r\n")),
+                "\tx\t: \t\t\tz\r\n\r\nabc",
+                ("x:z\r\n", "abc\r\n"),
+                ("\tx\t: \t\t\tz\r\n", "abc\r\n"),
             ),
             (
-                concat!("Subject: hello\r\n\r\n\r\n",),
-                (concat!("subject:hello\r\n"), ""),
-                ("Subject: hello\r\n", concat!("\r\n")),
+                "Subject: hello\r\n\r\n\r\n",
+                ("subject:hello\r\n", ""),
+                ("Subject: hello\r\n", "\r\n"),
             ),
         ] {
             let mut header_iterator = HeaderIterator::new(message.as_bytes());
@@ -298,6 +418,7 @@ mod test {
                 CanonicalBody {
                     canonicalization,
                     body: raw_body,
+                    l: 0,
                 }
                 .write(&mut body);
                 assert_eq!(expected_body, String::from_utf8(body).unwrap());
@@ -320,6 +441,7 @@ mod test {
                 CanonicalBody {
                     canonicalization,
                     body: body.as_bytes(),
+                    l: 0,
                 }
                 .write(&mut hasher);
 
@@ -341,4 +463,116 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn dkim_canonicalize_simple_body() {
+        for (body, expected) in [
+            // RFC 6376 section 3.4.5 example
+            (" C \r\nD \t E\r\n\r\n\r\n", " C \r\nD \t E\r\n"),
+            // Empty body is a single CRLF
+            ("", "\r\n"),
+            ("\r\n", "\r\n"),
+            ("\r\n\r\n\r\n", "\r\n"),
+            // Trailing empty lines are removed, a terminating CRLF is added
+            ("abc", "abc\r\n"),
+            ("abc\r\n", "abc\r\n"),
+            ("abc\r\n\r\n", "abc\r\n"),
+            // Leading and inner empty lines are preserved
+            ("\r\n\r\nabc\r\n", "\r\n\r\nabc\r\n"),
+            ("a\r\n\r\nb\r\n\r\n", "a\r\n\r\nb\r\n"),
+            // Whitespace-only lines are not empty lines
+            (" \r\n", " \r\n"),
+            ("abc\r\n \r\n\r\n", "abc\r\n \r\n"),
+        ] {
+            let mut result = Vec::new();
+            CanonicalBody {
+                canonicalization: Canonicalization::Simple,
+                body: body.as_bytes(),
+                l: 0,
+            }
+            .write(&mut result);
+            assert_eq!(String::from_utf8(result).unwrap(), expected, "{body:?}");
+
+            // Same output when canonicalized byte by byte
+            let mut result = Vec::new();
+            let mut canonicalizer = BodyCanonicalizer::new(Canonicalization::Simple);
+            for chunk in body.as_bytes().chunks(1) {
+                canonicalizer.write(chunk, &mut result);
+            }
+            canonicalizer.finish(&mut result);
+            assert_eq!(String::from_utf8(result).unwrap(), expected, "{body:?}");
+        }
+    }
+
+    #[test]
+    fn dkim_canonicalize_body_algorithm() {
+        let message = "From: jdoe@example.org\r\n\r\nHello  \r\n\r\n";
+        for (ch, cb, expected_body) in [
+            (
+                Canonicalization::Relaxed,
+                Canonicalization::Simple,
+                "Hello  \r\n",
+            ),
+            (
+                Canonicalization::Simple,
+                Canonicalization::Relaxed,
+                "Hello\r\n",
+            ),
+        ] {
+            let signature = Signature {
+                h: vec!["From".to_string()],
+                ch,
+                cb,
+                ..Default::default()
+            };
+            let (_, _, _, body) = signature.canonicalize(HeaderIterator::new(message.as_bytes()));
+            let mut result = Vec::new();
+            body.write(&mut result);
+            assert_eq!(
+                String::from_utf8(result).unwrap(),
+                expected_body,
+                "c={ch:?}/{cb:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_canonicalize_bare_cr() {
+        for (body, expected_simple, expected_relaxed) in [
+            // Bare CRs are literal data
+            ("a\rb\r\n", "a\rb\r\n", "a\rb\r\n"),
+            ("a \rb\r\n", "a \rb\r\n", "a \rb\r\n"),
+            ("a \r \r\n", "a \r \r\n", "a \r\r\n"),
+            ("\r\r\n", "\r\r\n", "\r\r\n"),
+            ("a\r\r\r\n", "a\r\r\r\n", "a\r\r\r\n"),
+            // Including at the end of the body
+            ("a\r", "a\r\r\n", "a\r\r\n"),
+            ("a\r\n\r", "a\r\n\r\r\n", "a\r\n\r\r\n"),
+            // While CRLF and bare LF end lines
+            ("a\r\nb\nc\r\n\n", "a\r\nb\r\nc\r\n", "a\r\nb\r\nc\r\n"),
+        ] {
+            for (canonicalization, expected) in [
+                (Canonicalization::Simple, expected_simple),
+                (Canonicalization::Relaxed, expected_relaxed),
+            ] {
+                let mut result = Vec::new();
+                CanonicalBody {
+                    canonicalization,
+                    body: body.as_bytes(),
+                    l: 0,
+                }
+                .write(&mut result);
+                assert_eq!(String::from_utf8(result).unwrap(), expected, "{body:?}");
+
+                // A CR at the end of a chunk is resolved by the next one
+                let mut result = Vec::new();
+                let mut canonicalizer = BodyCanonicalizer::new(canonicalization);
+                for chunk in body.as_bytes().chunks(1) {
+                    canonicalizer.write(chunk, &mut result);
+                }
+                canonicalizer.finish(&mut result);
+                assert_eq!(String::from_utf8(result).unwrap(), expected, "{body:?}");
+            }
+        }
+    }
 }
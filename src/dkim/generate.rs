@@ -8,7 +8,10 @@
  * except according to those terms.
  */
 
+use std::time::SystemTime;
+
 use mail_builder::encoders::base64::base64_encode;
+use mail_parser::DateTime;
 use rsa::{
     pkcs1::{EncodeRsaPrivateKey, EncodeRsaPublicKey},
     RsaPrivateKey, RsaPublicKey,
@@ -70,12 +73,33 @@ impl DkimKeyPair {
     pub fn encoded_public_key(&self) -> String {
         String::from_utf8(base64_encode(&self.public_key).unwrap_or_default()).unwrap_or_default()
     }
+
+    /// Returns a date-based selector for a key published at `time`, formatted
+    /// as `YYYYMM` so that each key rotation gets a new selector.
+    pub fn suggested_selector(time: SystemTime) -> String {
+        let timestamp = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let date = DateTime::from_timestamp(timestamp as i64);
+        format!("{:04}{:02}", date.year, date.month)
+    }
+
+    /// Returns the DNS name the public key has to be published under,
+    /// `<selector>._domainkey.<domain>.`
+    pub fn dns_record_name(selector: &str, domain: &str) -> String {
+        format!(
+            "{}._domainkey.{}.",
+            selector,
+            domain.trim_end_matches('.').to_lowercase()
+        )
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::dkim::sign::test::verify;
-    use std::time::{Duration, Instant};
+    use std::time::{Duration, Instant, SystemTime};
 
     use crate::{
         common::{
@@ -87,6 +111,30 @@ mod test {
         Resolver,
     };
 
+    #[test]
+    fn dkim_generate_selector() {
+        for (timestamp, expected) in [
+            (0, "197001"),
+            (1700000000, "202311"),
+            (1735689599, "202412"),
+            (1735689600, "202501"),
+        ] {
+            assert_eq!(
+                DkimKeyPair::suggested_selector(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp)
+                ),
+                expected
+            );
+        }
+
+        for (selector, domain, expected) in [
+            ("202501", "example.com", "202501._domainkey.example.com."),
+            ("ed", "Mail.Example.com.", "ed._domainkey.mail.example.com."),
+        ] {
+            assert_eq!(DkimKeyPair::dns_record_name(selector, domain), expected);
+        }
+    }
+
     #[tokio::test]
     async fn dkim_generate_verify() {
         let rsa_pkcs = DkimKeyPair::generate_rsa(2048).unwrap();
@@ -10,9 +10,9 @@
 
 use std::fmt::{Display, Formatter};
 
-use crate::common::headers::{HeaderWriter, Writer};
+use crate::common::headers::{HeaderIterator, HeaderWriter, Writer};
 
-use super::{Algorithm, Canonicalization, HashAlgorithm, Signature};
+use super::{Canonicalization, HashAlgorithm, Signature, SignaturePosition};
 
 impl Signature {
     pub(crate) fn write(&self, writer: &mut impl Writer, as_header: bool) {
@@ -22,11 +22,7 @@ impl Signature {
         };
         writer.write(header);
         writer.write(b"v=1; a=");
-        writer.write(match self.a {
-            Algorithm::RsaSha256 => b"rsa-sha256",
-            Algorithm::RsaSha1 => b"rsa-sha1",
-            Algorithm::Ed25519Sha256 => b"ed25519-sha256",
-        });
+        writer.write(self.a.as_str().as_bytes());
         for (tag, value) in [(&b"; s="[..], &self.s), (&b"; d="[..], &self.d)] {
             writer.write(tag);
             writer.write(value.as_bytes());
@@ -144,3 +140,45 @@ impl Display for Signature {
         f.write_str(&String::from_utf8(buf).map_err(|_| std::fmt::Error)?)
     }
 }
+
+/// Returns a copy of `message` with the `DKIM-Signature` header inserted at
+/// `position`. A signature never covers its own header, so the placement does
+/// not affect verification.
+pub fn insert_signature(
+    message: &[u8],
+    signature: &Signature,
+    position: SignaturePosition,
+) -> Vec<u8> {
+    let mut headers = HeaderIterator::new(message);
+    let offset = match position {
+        SignaturePosition::Top => 0,
+        SignaturePosition::Index(index) => {
+            let mut offset = 0;
+            for _ in 0..index {
+                if headers.next().is_none() {
+                    break;
+                }
+                offset = headers.header_end();
+            }
+            offset
+        }
+        SignaturePosition::After(name) => {
+            let mut offset = 0;
+            while let Some((header_name, _)) = headers.next() {
+                if header_name
+                    .trim_ascii()
+                    .eq_ignore_ascii_case(name.as_bytes())
+                {
+                    offset = headers.header_end();
+                }
+            }
+            offset
+        }
+    };
+
+    let mut result = Vec::with_capacity(message.len() + 512);
+    result.extend_from_slice(&message[..offset]);
+    signature.write_header(&mut result);
+    result.extend_from_slice(&message[offset..]);
+    result
+}
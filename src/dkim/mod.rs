@@ -8,13 +8,17 @@
  * except according to those terms.
  */
 
+use std::time::Duration;
+
 use crate::{
     arc::Set,
     common::{
-        crypto::{Algorithm, HashAlgorithm, SigningKey},
+        base32::Base32Writer,
+        crypto::{Algorithm, HashAlgorithm, Signer},
         verify::VerifySignature,
     },
-    ArcOutput, DkimOutput, DkimResult, Error, Version,
+    dmarc::{is_aligned, Alignment},
+    ArcOutput, DkimOutput, DkimResult, DkimWarning, Error, Version,
 };
 
 pub mod builder;
@@ -24,6 +28,7 @@ pub mod generate;
 pub mod headers;
 pub mod parse;
 pub mod sign;
+pub mod stream;
 pub mod verify;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -34,10 +39,30 @@ pub enum Canonicalization {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
-pub struct DkimSigner<T: SigningKey, State = NeedDomain> {
+pub struct DkimSigner<T: Signer, State = NeedDomain> {
     _state: std::marker::PhantomData<State>,
     pub key: T,
     pub template: Signature,
+    pub(crate) crlf_normalization: bool,
+    pub(crate) body_length_marker: Option<Vec<u8>>,
+}
+
+/// Incremental DKIM verifier for messages received in chunks, such as
+/// from a milter. Header bytes are buffered until the end of the header
+/// section is found, after which the body is streamed into the body hash
+/// contexts requested by the DKIM and ARC signatures present.
+///
+/// Once the whole message has been written, [`DkimVerifier::finish`] returns
+/// an [`AuthenticatedMessage`] that can be passed to `verify_dkim`,
+/// `verify_arc` and `verify_dmarc`. Since the body is not retained, the
+/// returned message cannot be used for ARC sealing.
+pub struct DkimVerifier {
+    strict: bool,
+    lenient_tags: bool,
+    headers: Vec<u8>,
+    line_start: bool,
+    in_body: bool,
+    body_hashes: Vec<stream::BodyHasher>,
 }
 
 pub struct NeedDomain;
@@ -64,6 +89,40 @@ pub struct Signature {
     pub atpsh: Option<HashAlgorithm>, // RFC 6541
     pub ch: Canonicalization,
     pub cb: Canonicalization,
+    pub(crate) mixed_case_tags: bool,
+    pub(crate) unknown_tags: Vec<String>,
+}
+
+/// Where [`headers::insert_signature`] places a `DKIM-Signature` header.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum SignaturePosition {
+    /// Before all other headers.
+    #[default]
+    Top,
+    /// Before the header at the given zero-based index, or after the last
+    /// header if the index is past the end of the header section.
+    Index(usize),
+    /// After the last occurrence of the named header, such as `Received`,
+    /// or before all other headers if the message does not contain it.
+    After(String),
+}
+
+/// Controls how many DKIM signatures are evaluated, see
+/// [`DkimOptions::mode`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DkimVerifyMode {
+    /// Verify every signature. Required when the per-signature results are
+    /// needed, for example to generate failure reports or DMARC aggregate
+    /// reports.
+    #[default]
+    EvaluateAll,
+    /// Stop at the first signature that verifies, skipping the DNS lookups of
+    /// the remaining ones. When a domain is given, only a passing signature
+    /// whose `d=` is aligned with it under relaxed DMARC alignment stops the
+    /// evaluation, that is, the domain itself, a subdomain of it or a parent
+    /// of it other than a top-level domain.
+    /// The output omits the signatures that were not evaluated.
+    StopOnFirstPass { domain: Option<String> },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -93,6 +152,25 @@ pub(crate) const RR_UNKNOWN_TAG: u8 = 0x10;
 pub(crate) const RR_VERIFICATION: u8 = 0x20;
 pub(crate) const RR_EXPIRATION: u8 = 0x40;
 
+pub(crate) const MAX_DKIM_SIGNATURES: usize = 10;
+pub(crate) const DEFAULT_DKIM_ALGORITHMS: [Algorithm; 3] = [
+    Algorithm::RsaSha1,
+    Algorithm::RsaSha256,
+    Algorithm::Ed25519Sha256,
+];
+
+/// Options controlling how DKIM signatures are verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DkimOptions {
+    pub(crate) max_signatures: usize,
+    pub(crate) ignore_expiration: bool,
+    pub(crate) debug: bool,
+    pub(crate) key_records: bool,
+    pub(crate) max_clock_skew: Option<Duration>,
+    pub(crate) algorithms: Vec<Algorithm>,
+    pub(crate) mode: DkimVerifyMode,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[repr(u64)]
 pub(crate) enum Service {
@@ -153,9 +231,235 @@ impl VerifySignature for Signature {
 }
 
 impl Signature {
+    /// Returns the Agent or User Identifier (`i=`), with its
+    /// dkim-quoted-printable (e.g. `=2E`) and percent (`%2E`) escapes
+    /// already decoded. Only the domain is lowercased.
     pub fn identity(&self) -> &str {
         &self.i
     }
+
+    /// Returns the names of the signed headers (`h=`) in the order and case
+    /// in which they were published.
+    pub fn signed_headers(&self) -> impl Iterator<Item = &str> {
+        self.h.iter().map(|name| name.as_str())
+    }
+
+    /// Returns `true` if `name` is listed in the `h=` tag, compared
+    /// case-insensitively.
+    pub fn is_header_signed(&self, name: &str) -> bool {
+        self.h.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the names of the tags that were not recognized while parsing.
+    pub fn unknown_tags(&self) -> &[String] {
+        &self.unknown_tags
+    }
+
+    /// Returns the DNS name queried for the signing key,
+    /// `<selector>._domainkey.<domain>.`
+    pub fn dns_key_name(&self) -> String {
+        self.domain_key()
+    }
+
+    /// Returns the DNS name queried to authorize an `atps=` third-party
+    /// signature, `<label>._atps.<atps-domain>.`, or `None` if the signature
+    /// has no `atps=` tag. The label is the signing domain hashed with the
+    /// `atpsh=` algorithm and encoded in unpadded uppercase base32, or the
+    /// signing domain itself when `atpsh=none` (RFC 6541, Section 4.3).
+    pub fn atps_dns_name(&self) -> Option<String> {
+        let atps = self.atps.as_deref()?.trim_end_matches('.');
+        let domain = self.d.trim_end_matches('.').to_lowercase();
+        let mut name = match &self.atpsh {
+            Some(algorithm) => Base32Writer::encode(algorithm.hash(domain.as_bytes()).as_ref()),
+            None => domain,
+        };
+        name.push_str("._atps.");
+        name.push_str(atps);
+        name.push('.');
+        Some(name)
+    }
+
+    /// Returns `true` if the signer requested failure reports with `r=y`
+    /// (RFC 6651, Section 4.1).
+    pub fn reports_requested(&self) -> bool {
+        self.r
+    }
+
+    /// Returns the DNS name queried for the signer's reporting policy,
+    /// `_report._domainkey.<domain>.`
+    pub fn report_dns_name(&self) -> String {
+        format!("_report._domainkey.{}.", self.d.trim_end_matches('.'))
+    }
+}
+
+impl Default for DkimOptions {
+    fn default() -> Self {
+        Self {
+            max_signatures: MAX_DKIM_SIGNATURES,
+            ignore_expiration: false,
+            debug: false,
+            key_records: false,
+            max_clock_skew: None,
+            algorithms: DEFAULT_DKIM_ALGORITHMS.to_vec(),
+            mode: DkimVerifyMode::EvaluateAll,
+        }
+    }
+}
+
+impl DkimOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of DKIM signatures verified per message
+    /// (defaults to 10). Additional signatures are not evaluated and are
+    /// reported as neutral with [`Error::SignatureLimitExceeded`], see
+    /// [`DkimOutput::exceeds_signature_limit`].
+    pub fn max_signatures(mut self, max_signatures: usize) -> Self {
+        self.max_signatures = max_signatures;
+        self
+    }
+
+    /// When enabled, DKIM signatures whose `x=` expiration time has passed
+    /// are still verified, with the result flagged through
+    /// [`DkimOutput::expired_at_verification`]. Intended for verifying
+    /// archived messages, it should not be enabled when evaluating live mail.
+    pub fn ignore_expiration(mut self, ignore_expiration: bool) -> Self {
+        self.ignore_expiration = ignore_expiration;
+        self
+    }
+
+    /// When enabled, DKIM signatures that do not verify keep a copy of the
+    /// canonicalized header block and body computed for them, available
+    /// through [`DkimOutput::canonical_form`]. Meant for diagnosing
+    /// interoperability issues, as it holds a copy of the message per
+    /// failed signature.
+    ///
+    /// The canonical form is rebuilt from the message once verification is
+    /// done, with the same canonicalization the verifier hashed. Messages
+    /// verified through a [`DkimVerifier`] do not retain their body, so only
+    /// the header block is available for them and the body is left empty.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// When enabled, the text of the DomainKey record used to verify each DKIM
+    /// signature is made available through [`DkimOutput::key_record`], and
+    /// that of the key used to verify the latest ARC-Message-Signature
+    /// through [`ArcOutput::key_record`], so audits can establish which key
+    /// verified a message after it has been rotated. Disabled by default, as
+    /// it copies the record into every output.
+    pub fn key_records(mut self, key_records: bool) -> Self {
+        self.key_records = key_records;
+        self
+    }
+
+    /// Sets how far in the future a DKIM signature's `t=` timestamp may be
+    /// before the signature is rejected with [`Error::SignatureTimeInFuture`].
+    /// `None` disables the check, which is the default.
+    pub fn max_clock_skew(mut self, max_clock_skew: Option<Duration>) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    /// Restricts the algorithms accepted in DKIM signatures (defaults to all
+    /// supported algorithms). Signatures using any other algorithm are not
+    /// verified and are reported as neutral with [`Error::AlgorithmNotAllowed`].
+    pub fn allowed_algorithms(mut self, algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+        self.algorithms = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Sets how many signatures are evaluated (defaults to
+    /// [`DkimVerifyMode::EvaluateAll`]). [`DkimVerifyMode::StopOnFirstPass`]
+    /// saves the DNS lookups and cryptographic work of the remaining
+    /// signatures but leaves them out of the output, so it is not suitable
+    /// when every verdict has to be reported.
+    pub fn mode(mut self, mode: DkimVerifyMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl DkimVerifyMode {
+    pub(crate) fn is_final(&self, signature: &Signature) -> bool {
+        match self {
+            DkimVerifyMode::EvaluateAll => false,
+            DkimVerifyMode::StopOnFirstPass { domain: None } => true,
+            DkimVerifyMode::StopOnFirstPass {
+                domain: Some(domain),
+            } => is_aligned(&signature.d, domain, Alignment::Relaxed).is_some(),
+        }
+    }
+}
+
+impl DomainKeyReport {
+    /// Returns the local-part reports are sent to (`ra=`).
+    pub fn local_part(&self) -> &str {
+        &self.ra
+    }
+
+    /// Returns the address reports are sent to, composed of the `ra=`
+    /// local-part and the signing domain.
+    pub fn address(&self, domain: &str) -> String {
+        format!("{}@{}", self.ra, domain.trim_end_matches('.'))
+    }
+
+    /// Returns the percentage of failures that should be reported (`rp=`).
+    pub fn percent(&self) -> u8 {
+        self.rp
+    }
+
+    /// Returns the text to include in SMTP rejections (`rs=`).
+    pub fn smtp_text(&self) -> Option<&str> {
+        self.rs.as_deref()
+    }
+
+    /// Returns `true` if the `rr=` tag requests reports for the
+    /// error class of `err`.
+    #[allow(deprecated)]
+    pub fn wants_report(&self, err: &Error) -> bool {
+        match err {
+            Error::CryptoError(_)
+            | Error::Io(_)
+            | Error::FailedVerification
+            | Error::FailedBodyHashMatch
+            | Error::BodyHashMismatch { .. }
+            | Error::BodyLengthExceeded
+            | Error::FailedAuidMatch => (self.rr & RR_VERIFICATION) != 0,
+            Error::Base64
+            | Error::UnsupportedVersion
+            | Error::UnsupportedAlgorithm
+            | Error::UnsupportedCanonicalization
+            | Error::UnsupportedKeyType
+            | Error::IncompatibleAlgorithms
+            | Error::DuplicateTag
+            | Error::AlgorithmMismatch
+            | Error::UnsupportedQueryMethod
+            | Error::InvalidBodyHashLength
+            | Error::SignatureTimeInFuture => (self.rr & RR_SIGNATURE) != 0,
+            Error::SignatureExpired => (self.rr & RR_EXPIRATION) != 0,
+            Error::DnsError(_)
+            | Error::DnsRecordNotFound(_)
+            | Error::InvalidRecordType
+            | Error::ParseError
+            | Error::RevokedPublicKey => (self.rr & RR_DNS) != 0,
+            Error::MissingParameters
+            | Error::NoHeadersFound
+            | Error::ArcChainTooLong
+            | Error::ArcInvalidInstance(_)
+            | Error::ArcInvalidCV
+            | Error::ArcHasHeaderTag
+            | Error::ArcBrokenChain
+            | Error::SignatureLength
+            | Error::SignatureLimitExceeded
+            | Error::AlgorithmNotAllowed
+            | Error::MultipleFromHeaders
+            | Error::InvalidAuthservId
+            | Error::NotAligned => (self.rr & RR_OTHER) != 0,
+        }
+    }
 }
 
 impl<'x> DkimOutput<'x> {
@@ -165,6 +469,10 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            expired_at_verification: false,
+            warnings: Vec::new(),
+            canonical_form: None,
+            key_record: None,
         }
     }
 
@@ -174,6 +482,10 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            expired_at_verification: false,
+            warnings: Vec::new(),
+            canonical_form: None,
+            key_record: None,
         }
     }
 
@@ -183,6 +495,10 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            expired_at_verification: false,
+            warnings: Vec::new(),
+            canonical_form: None,
+            key_record: None,
         }
     }
 
@@ -192,6 +508,10 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            expired_at_verification: false,
+            warnings: Vec::new(),
+            canonical_form: None,
+            key_record: None,
         }
     }
 
@@ -201,6 +521,10 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            expired_at_verification: false,
+            warnings: Vec::new(),
+            canonical_form: None,
+            key_record: None,
         }
     }
 
@@ -213,10 +537,18 @@ impl<'x> DkimOutput<'x> {
     }
 
     pub(crate) fn with_signature(mut self, signature: &'x Signature) -> Self {
+        if signature.mixed_case_tags {
+            self.warnings.push(DkimWarning::MixedCaseTags);
+        }
         self.signature = signature.into();
         self
     }
 
+    pub(crate) fn with_key_record(mut self, key_record: Option<&str>) -> Self {
+        self.key_record = key_record.map(str::to_string);
+        self
+    }
+
     pub(crate) fn with_atps(mut self) -> Self {
         self.is_atps = true;
         self
@@ -233,6 +565,55 @@ impl<'x> DkimOutput<'x> {
     pub fn failure_report_addr(&self) -> Option<&str> {
         self.report.as_deref()
     }
+
+    pub fn warnings(&self) -> &[DkimWarning] {
+        &self.warnings
+    }
+
+    /// Whether the signature had expired at verification time. Only set when
+    /// expiration checks are disabled with
+    /// [`DkimOptions::ignore_expiration`],
+    /// in which case the result reflects the cryptographic verification alone.
+    pub fn expired_at_verification(&self) -> bool {
+        self.expired_at_verification
+    }
+
+    /// Returns the canonicalized header block and body computed for a
+    /// signature that did not verify, when enabled with
+    /// [`DkimOptions::debug`]. The body is empty for messages verified
+    /// through a [`DkimVerifier`], which does not retain it.
+    /// See [`AuthenticatedMessage::canonical_form`](crate::AuthenticatedMessage::canonical_form).
+    pub fn canonical_form(&self) -> Option<(&[u8], &[u8])> {
+        self.canonical_form
+            .as_deref()
+            .map(|(headers, body)| (headers.as_slice(), body.as_slice()))
+    }
+
+    /// Returns the text of the DomainKey record used to verify this signature,
+    /// when enabled with
+    /// [`DkimOptions::key_records`].
+    pub fn key_record(&self) -> Option<&str> {
+        self.key_record.as_deref()
+    }
+
+    /// Whether this signature was not evaluated because the message exceeded
+    /// the maximum number of DKIM signatures set with
+    /// [`DkimOptions::max_signatures`].
+    pub fn exceeds_signature_limit(&self) -> bool {
+        matches!(
+            self.result,
+            DkimResult::Neutral(Error::SignatureLimitExceeded)
+        )
+    }
+
+    /// Whether this signature was not evaluated because the message exceeded
+    /// the maximum number of DKIM signatures or its algorithm is not allowed.
+    pub fn is_skipped(&self) -> bool {
+        matches!(
+            self.result,
+            DkimResult::Neutral(Error::SignatureLimitExceeded | Error::AlgorithmNotAllowed)
+        )
+    }
 }
 
 impl<'x> ArcOutput<'x> {
@@ -240,9 +621,16 @@ impl<'x> ArcOutput<'x> {
         &self.result
     }
 
-    pub fn sets(&self) -> &[Set] {
+    pub fn sets(&self) -> &[Set<'_>] {
         &self.set
     }
+
+    /// Returns the text of the DomainKey record used to verify the
+    /// ARC-Message-Signature of the latest set, when enabled with
+    /// [`DkimOptions::key_records`].
+    pub fn key_record(&self) -> Option<&str> {
+        self.key_record.as_deref()
+    }
 }
 
 impl From<Error> for DkimResult {
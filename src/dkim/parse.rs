@@ -10,7 +10,9 @@
 
 use std::slice::Iter;
 
-use mail_parser::decoders::base64::base64_decode_stream;
+use mail_parser::decoders::{
+    base64::base64_decode_stream, quoted_printable::quoted_printable_decode_char,
+};
 
 use crate::{
     common::{crypto::VerifyingKeyType, parse::*, verify::DomainKey},
@@ -44,8 +46,21 @@ const RS: u64 = (b'r' as u64) | (b's' as u64) << 8;
 const ALL: u64 = (b'a' as u64) | (b'l' as u64) << 8 | (b'l' as u64) << 16;
 
 impl Signature {
-    #[allow(clippy::while_let_on_iterator)]
+    /// Parses a DKIM-Signature header value, without the header name.
+    /// Folded lines are accepted and the `b=` and `bh=` tags are returned
+    /// base64-decoded. Tag names that are not lowercase are treated as
+    /// unknown tags, as required by RFC 6376.
+    #[inline(always)]
     pub fn parse(header: &'_ [u8]) -> crate::Result<Self> {
+        Self::parse_with_opts(header, false)
+    }
+
+    /// Parses a DKIM-Signature header value. When `lenient_tags` is enabled,
+    /// tag names that are not lowercase are normalized instead and reported
+    /// as a [`DkimWarning::MixedCaseTags`](crate::DkimWarning::MixedCaseTags)
+    /// warning once the signature is verified.
+    #[allow(clippy::while_let_on_iterator)]
+    pub fn parse_with_opts(header: &'_ [u8], lenient_tags: bool) -> crate::Result<Self> {
         let mut signature = Signature {
             v: 0,
             a: Algorithm::RsaSha256,
@@ -64,11 +79,24 @@ impl Signature {
             r: false,
             atps: None,
             atpsh: None,
+            mixed_case_tags: false,
+            unknown_tags: Vec::new(),
         };
         let header_len = header.len();
         let mut header = header.iter();
+        let mut tags = Vec::with_capacity(16);
+        let mut tag_start = header.as_slice();
 
-        while let Some(key) = header.key() {
+        while let Some((key, is_lowercase)) = header.key_with_case() {
+            check_duplicate(&mut tags, key)?;
+            let key = if is_lowercase {
+                key
+            } else if lenient_tags {
+                signature.mixed_case_tags = true;
+                key
+            } else {
+                u64::MAX
+            };
             match key {
                 V => {
                     signature.v = header.number().unwrap_or(0) as u32;
@@ -88,14 +116,26 @@ impl Signature {
                         base64_decode_stream(&mut header, header_len, b';').ok_or(Error::Base64)?
                 }
                 C => {
+                    // A single algorithm applies to the header, the body
+                    // then defaults to "simple" (RFC 6376, Section 3.5)
                     let (ch, cb) = header.canonicalization(Canonicalization::Simple)?;
                     signature.ch = ch;
                     signature.cb = cb;
                 }
                 D => signature.d = header.text(true),
                 H => signature.h = header.items(),
-                I => signature.i = header.text_qp(Vec::with_capacity(20), true, false),
+                I => signature.i = decode_auid(header.text_qp(Vec::new(), false, false)),
                 L => signature.l = header.number().unwrap_or(0),
+                Q => {
+                    // Only the "dns/txt" query method is supported
+                    if !header
+                        .items::<String>()
+                        .iter()
+                        .any(|method| method.eq_ignore_ascii_case("dns/txt"))
+                    {
+                        return Err(Error::UnsupportedQueryMethod);
+                    }
+                }
                 S => signature.s = header.text(true),
                 T => signature.t = header.number().unwrap_or(0),
                 X => signature.x = header.number().unwrap_or(0),
@@ -117,11 +157,20 @@ impl Signature {
                         }
                     };
                 }
-                _ => header.ignore(),
+                _ => {
+                    signature
+                        .unknown_tags
+                        .extend(tag_name(tag_start, header.as_slice()));
+                    header.ignore();
+                }
             }
+            tag_start = header.as_slice();
         }
 
-        if !signature.d.is_empty()
+        if signature.v != 1 {
+            // The v= tag is required (RFC 6376, Section 3.5)
+            Err(Error::UnsupportedVersion)
+        } else if !signature.d.is_empty()
             && !signature.s.is_empty()
             && !signature.b.is_empty()
             && !signature.bh.is_empty()
@@ -242,14 +291,18 @@ impl SignatureParser for Iter<'_, u8> {
 
 impl TxtRecordParser for DomainKey {
     #[allow(clippy::while_let_on_iterator)]
-    fn parse(header: &[u8]) -> crate::Result<Self> {
-        let header_len = header.len();
-        let mut header = header.iter();
+    fn parse(record: &[u8]) -> crate::Result<Self> {
+        let header_len = record.len();
+        let mut header = record.iter();
         let mut flags = 0;
         let mut key_type = VerifyingKeyType::Rsa;
         let mut public_key = None;
+        let mut tags = Vec::with_capacity(8);
+        let mut unknown_tags = Vec::new();
+        let mut tag_start = header.as_slice();
 
         while let Some(key) = header.key() {
+            check_duplicate(&mut tags, key)?;
             match key {
                 V => {
                     if !header.match_bytes(b"DKIM1") || !header.seek_tag_end() {
@@ -267,19 +320,13 @@ impl TxtRecordParser for DomainKey {
                 K => {
                     if let Some(ch) = header.next_skip_whitespaces() {
                         match ch {
-                            b'r' | b'R' => {
-                                if header.match_bytes(b"sa") && header.seek_tag_end() {
-                                    key_type = VerifyingKeyType::Rsa;
-                                } else {
-                                    return Err(Error::UnsupportedKeyType);
-                                }
+                            b'r' | b'R' if header.match_bytes(b"sa") && header.seek_tag_end() => {
+                                key_type = VerifyingKeyType::Rsa;
                             }
-                            b'e' | b'E' => {
-                                if header.match_bytes(b"d25519") && header.seek_tag_end() {
-                                    key_type = VerifyingKeyType::Ed25519;
-                                } else {
-                                    return Err(Error::UnsupportedKeyType);
-                                }
+                            b'e' | b'E'
+                                if header.match_bytes(b"d25519") && header.seek_tag_end() =>
+                            {
+                                key_type = VerifyingKeyType::Ed25519;
                             }
                             b';' => (),
                             _ => {
@@ -289,15 +336,20 @@ impl TxtRecordParser for DomainKey {
                     }
                 }
                 _ => {
+                    unknown_tags.extend(tag_name(tag_start, header.as_slice()));
                     header.ignore();
                 }
             }
+            tag_start = header.as_slice();
         }
 
         match public_key {
             Some(public_key) => Ok(DomainKey {
                 p: key_type.verifying_key(&public_key)?,
+                k: key_type,
                 f: flags,
+                unknown_tags,
+                record: String::from_utf8_lossy(record).into_owned().into(),
             }),
             _ => Err(Error::InvalidRecordType),
         }
@@ -417,6 +469,48 @@ impl DomainKey {
     pub fn has_flag(&self, flag: impl Into<u64>) -> bool {
         (self.f & flag.into()) != 0
     }
+
+    /// Returns the names of the tags that were not recognized while parsing.
+    pub fn unknown_tags(&self) -> &[String] {
+        &self.unknown_tags
+    }
+
+    /// Returns `true` if the key type can verify signatures of the given algorithm.
+    pub fn matches_algorithm(&self, algorithm: Algorithm) -> bool {
+        matches!(
+            (self.k, algorithm),
+            (
+                VerifyingKeyType::Rsa,
+                Algorithm::RsaSha1 | Algorithm::RsaSha256
+            ) | (VerifyingKeyType::Ed25519, Algorithm::Ed25519Sha256)
+        )
+    }
+}
+
+/// Decodes the `%XX` escapes that some signers use in the `i=` local-part
+/// instead of dkim-quoted-printable, which `text_qp` already decoded, and
+/// lowercases the domain. The local-part is case-sensitive and kept as is.
+fn decode_auid(auid: String) -> String {
+    let mut decoded = Vec::with_capacity(auid.len());
+    let mut bytes = auid.as_bytes();
+    while let Some((&ch, rest)) = bytes.split_first() {
+        if let (b'%', [hex1, hex2, ..]) = (ch, rest) {
+            if let Some(ch) = quoted_printable_decode_char(*hex1, *hex2) {
+                decoded.push(ch);
+                bytes = &rest[2..];
+                continue;
+            }
+        }
+        decoded.push(ch);
+        bytes = rest;
+    }
+
+    let mut auid = String::from_utf8(decoded)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+    if let Some(pos) = auid.rfind('@') {
+        auid[pos..].make_ascii_lowercase();
+    }
+    auid
 }
 
 impl ItemParser for HashAlgorithm {
@@ -466,10 +560,11 @@ mod test {
             verify::DomainKey,
         },
         dkim::{
-            Canonicalization, DomainKeyReport, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER,
-            RR_POLICY, RR_SIGNATURE, RR_UNKNOWN_TAG, RR_VERIFICATION, R_FLAG_MATCH_DOMAIN,
-            R_FLAG_TESTING, R_SVC_ALL, R_SVC_EMAIL,
+            Canonicalization, DomainKeyReport, HashAlgorithm, Signature, RR_DNS, RR_EXPIRATION,
+            RR_OTHER, RR_POLICY, RR_SIGNATURE, RR_UNKNOWN_TAG, RR_VERIFICATION,
+            R_FLAG_MATCH_DOMAIN, R_FLAG_TESTING, R_SVC_ALL, R_SVC_EMAIL,
         },
+        AuthenticatedMessage, DkimOutput, DkimResult, DkimWarning, Error, Resolver,
     };
 
     #[test]
@@ -510,6 +605,8 @@ mod test {
                     r: false,
                     atps: None,
                     atpsh: None,
+                    mixed_case_tags: false,
+                    unknown_tags: vec![],
                 },
             ),
             (
@@ -553,6 +650,8 @@ mod test {
                     r: false,
                     atps: None,
                     atpsh: None,
+                    mixed_case_tags: false,
+                    unknown_tags: vec![],
                 },
             ),
             (
@@ -601,6 +700,8 @@ mod test {
                     r: false,
                     atps: None,
                     atpsh: None,
+                    mixed_case_tags: false,
+                    unknown_tags: vec![],
                 },
             ),
         ] {
@@ -672,6 +773,247 @@ mod test {
         }
     }
 
+    #[test]
+    fn dkim_dns_key_name() {
+        for (s, d, expected) in [
+            ("default", "example.org", "default._domainkey.example.org."),
+            (
+                "sel.2023",
+                "mail.example.org.",
+                "sel.2023._domainkey.mail.example.org.",
+            ),
+        ] {
+            let signature = Signature {
+                s: s.to_string(),
+                d: d.to_string(),
+                ..Default::default()
+            };
+            assert_eq!(signature.dns_key_name(), expected);
+        }
+    }
+
+    #[test]
+    fn dkim_atps_dns_name() {
+        for (d, atps, atpsh, expected) in [
+            (
+                "one.example.net",
+                Some("example.com"),
+                Some(HashAlgorithm::Sha1),
+                Some("QSP4I4D24CRHOPDZ3O3ZIU2KSGS3X6Z6._atps.example.com."),
+            ),
+            (
+                "One.Example.Net.",
+                Some("example.com."),
+                Some(HashAlgorithm::Sha1),
+                Some("QSP4I4D24CRHOPDZ3O3ZIU2KSGS3X6Z6._atps.example.com."),
+            ),
+            (
+                "one.example.net",
+                Some("example.com"),
+                Some(HashAlgorithm::Sha256),
+                Some("SQWHEPKQYG5KRIOG6F7LPEDTTNOIF7DQUSVCO2PCHSH3QUGXAKHA._atps.example.com."),
+            ),
+            (
+                "one.example.net",
+                Some("example.com"),
+                None,
+                Some("one.example.net._atps.example.com."),
+            ),
+            ("one.example.net", None, Some(HashAlgorithm::Sha256), None),
+        ] {
+            let signature = Signature {
+                d: d.to_string(),
+                atps: atps.map(|atps| atps.to_string()),
+                atpsh,
+                ..Default::default()
+            };
+            assert_eq!(signature.atps_dns_name().as_deref(), expected);
+        }
+    }
+
+    #[test]
+    fn dkim_unknown_tags() {
+        let signature = Signature::parse(
+            b"v=1; a=rsa-sha256; s=default; d=example.org; h=From; bh=dGVzdA==; b=dGVzdA==; new_tag=1; xyz=abc",
+        )
+        .unwrap();
+        assert_eq!(signature.unknown_tags(), ["new_tag", "xyz"]);
+
+        let record = DomainKey::parse(
+            b"v=DKIM1; k=ed25519; nt=1; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+        )
+        .unwrap();
+        assert_eq!(record.unknown_tags(), ["nt"]);
+    }
+
+    #[tokio::test]
+    async fn dkim_signature_version() {
+        let resolver = Resolver::new_system_conf().unwrap();
+
+        for (version, expected) in [
+            ("v=1; ", Ok(())),
+            ("v=2; ", Err(Error::UnsupportedVersion)),
+            ("v=; ", Err(Error::UnsupportedVersion)),
+            ("", Err(Error::UnsupportedVersion)),
+        ] {
+            let header = format!(
+                "{version}a=rsa-sha256; s=default; d=example.org; h=From; bh=dGVzdA==; b=dGVzdA=="
+            );
+            assert_eq!(
+                Signature::parse(header.as_bytes()).map(|_| ()),
+                expected,
+                "{version}"
+            );
+
+            // Signatures with an unsupported version are not verified
+            if let Err(err) = expected {
+                let message = format!("DKIM-Signature: {header}\r\nFrom: jdoe@example.org\r\n\r\n");
+                let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+                let dkim = resolver.verify_dkim(&message).await;
+                assert_eq!(dkim.len(), 1);
+                assert_eq!(dkim[0].result(), &DkimResult::Neutral(err));
+                assert!(dkim[0].signature().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn dkim_canonicalization_defaults() {
+        for (tag, expected) in [
+            ("", Ok((Canonicalization::Simple, Canonicalization::Simple))),
+            (
+                "c=simple; ",
+                Ok((Canonicalization::Simple, Canonicalization::Simple)),
+            ),
+            (
+                "c=relaxed; ",
+                Ok((Canonicalization::Relaxed, Canonicalization::Simple)),
+            ),
+            (
+                "c=Relaxed; ",
+                Ok((Canonicalization::Relaxed, Canonicalization::Simple)),
+            ),
+            (
+                "c=relaxed/; ",
+                Ok((Canonicalization::Relaxed, Canonicalization::Simple)),
+            ),
+            (
+                "c=simple/relaxed; ",
+                Ok((Canonicalization::Simple, Canonicalization::Relaxed)),
+            ),
+            (
+                "c=relaxed/relaxed; ",
+                Ok((Canonicalization::Relaxed, Canonicalization::Relaxed)),
+            ),
+            (
+                "c= relaxed / simple ; ",
+                Ok((Canonicalization::Relaxed, Canonicalization::Simple)),
+            ),
+            ("c=nofws; ", Err(Error::UnsupportedCanonicalization)),
+            ("c=relaxed/nofws; ", Err(Error::UnsupportedCanonicalization)),
+        ] {
+            assert_eq!(
+                Signature::parse(
+                    format!(
+                        "v=1; a=rsa-sha256; s=default; d=example.org; {tag}h=From; bh=dGVzdA==; b=dGVzdA=="
+                    )
+                    .as_bytes()
+                )
+                .map(|signature| (signature.ch, signature.cb)),
+                expected,
+                "{tag}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_query_method() {
+        for (query_method, expected) in [
+            ("dns/txt", Ok(())),
+            ("DNS/TXT", Ok(())),
+            ("http/well-known:dns/txt", Ok(())),
+            ("http/well-known", Err(Error::UnsupportedQueryMethod)),
+            ("dns", Err(Error::UnsupportedQueryMethod)),
+        ] {
+            assert_eq!(
+                Signature::parse(
+                    format!(
+                        "v=1; a=rsa-sha256; s=default; d=example.org; h=From; q={query_method}; bh=dGVzdA==; b=dGVzdA=="
+                    )
+                    .as_bytes()
+                )
+                .map(|_| ()),
+                expected,
+                "{query_method}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_duplicate_tags() {
+        for signature in [
+            concat!(
+                "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:To; ",
+                "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA==; b=dGVzdA=="
+            ),
+            concat!(
+                "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:To; H=Subject; ",
+                "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+            ),
+            concat!(
+                "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:To; d=evil.org; ",
+                "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+            ),
+        ] {
+            assert_eq!(
+                Signature::parse(signature.as_bytes()),
+                Err(Error::DuplicateTag),
+                "{signature:?}"
+            );
+        }
+
+        assert!(matches!(
+            DomainKey::parse(b"v=DKIM1; k=rsa; p=dGVzdA==; p=dGVzdA=="),
+            Err(Error::DuplicateTag)
+        ));
+    }
+
+    #[test]
+    fn dkim_mixed_case_tags() {
+        let signature = concat!(
+            "v=1; A=rsa-sha256; s=default; D=stalw.art; h=From:To; ",
+            "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+        );
+
+        // Strict mode treats uppercase tag names as unknown tags
+        assert_eq!(
+            Signature::parse(signature.as_bytes()),
+            Err(Error::MissingParameters)
+        );
+
+        // Lenient mode normalizes and flags them
+        let signature = Signature::parse_with_opts(signature.as_bytes(), true).unwrap();
+        assert_eq!(signature.d, "stalw.art");
+        assert_eq!(signature.a, Algorithm::RsaSha256);
+        assert!(signature.mixed_case_tags);
+        assert_eq!(
+            DkimOutput::pass().with_signature(&signature).warnings(),
+            &[DkimWarning::MixedCaseTags]
+        );
+
+        // Lowercase tag names are not flagged
+        let signature = Signature::parse_with_opts(
+            concat!(
+                "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:To; ",
+                "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+            )
+            .as_bytes(),
+            true,
+        )
+        .unwrap();
+        assert!(!signature.mixed_case_tags);
+    }
+
     #[test]
     fn dkim_report_record_parse() {
         for (record, expected_result) in [
@@ -705,5 +1047,162 @@ mod test {
                 expected_result
             );
         }
+
+        let record =
+            DomainKeyReport::parse(b"ra=dkim-errors; rp=25; rr=v:x; rs=Try=20again").unwrap();
+        assert_eq!(record.local_part(), "dkim-errors");
+        assert_eq!(record.address("example.org."), "dkim-errors@example.org");
+        assert_eq!(record.percent(), 25);
+        assert_eq!(record.smtp_text(), Some("Try again"));
+        assert!(record.wants_report(&Error::FailedVerification));
+        assert!(record.wants_report(&Error::SignatureExpired));
+        assert!(!record.wants_report(&Error::UnsupportedAlgorithm));
+        assert!(!record.wants_report(&Error::RevokedPublicKey));
+
+        // Omitting rr= requests all reports, ra= is mandatory
+        let record = DomainKeyReport::parse(b"ra=postmaster").unwrap();
+        assert_eq!(record.percent(), 100);
+        assert!(record.wants_report(&Error::RevokedPublicKey));
+        assert_eq!(
+            DomainKeyReport::parse(b"rp=100; rr=all"),
+            Err(Error::InvalidRecordType)
+        );
+    }
+
+    #[test]
+    fn dkim_algorithm_round_trip() {
+        for (tag, algorithm) in [
+            ("rsa-sha1", Algorithm::RsaSha1),
+            ("rsa-sha256", Algorithm::RsaSha256),
+            ("ed25519-sha256", Algorithm::Ed25519Sha256),
+        ] {
+            let signature = format!(
+                concat!(
+                    "v=1; a={}; s=default; d=stalw.art; h=From:To; ",
+                    "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+                ),
+                tag
+            );
+            let signature = Signature::parse(signature.as_bytes()).unwrap();
+            assert_eq!(signature.a, algorithm);
+            assert_eq!(signature.a.as_str(), tag);
+
+            // Serialized signatures use the same token
+            let mut header = Vec::new();
+            signature.write(&mut header, true);
+            let header = String::from_utf8_lossy(&header);
+            assert!(header.contains(&format!("a={tag};")), "{header}");
+        }
+    }
+
+    #[test]
+    fn dkim_signature_identity_qp() {
+        for (auid, expected) in [
+            ("jo=2Ehn@example.com", "jo.hn@example.com"),
+            ("jo=2ehn@Example=2ECom", "jo.hn@example.com"),
+            ("=40example.com", "@example.com"),
+            ("jo%2Ehn@example.com", "jo.hn@example.com"),
+            ("jo%2ehn@Example.COM", "jo.hn@example.com"),
+            ("jo%2Ghn@example.com", "jo%2Ghn@example.com"),
+            ("JoHn@Example.Com", "JoHn@example.com"),
+        ] {
+            let signature = format!(
+                "v=1; a=rsa-sha256; s=default; d=example.com; i={auid}; h=From; bh={}; b=dGVzdA==",
+                "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+            );
+            assert_eq!(
+                Signature::parse(signature.as_bytes()).unwrap().identity(),
+                expected,
+                "{auid}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_signed_headers() {
+        let signature = concat!(
+            "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:to: Subject :DATE:from; ",
+            "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+        );
+        let signature = Signature::parse(signature.as_bytes()).unwrap();
+        assert_eq!(
+            signature.signed_headers().collect::<Vec<_>>(),
+            ["From", "to", "Subject", "DATE", "from"]
+        );
+        assert!(signature.is_header_signed("subject"));
+        assert!(signature.is_header_signed("Date"));
+        assert!(!signature.is_header_signed("Message-ID"));
+    }
+
+    #[test]
+    fn dkim_signature_reporting() {
+        let signature = concat!(
+            "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:To; r=y; ",
+            "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+        );
+        let signature = Signature::parse(signature.as_bytes()).unwrap();
+        assert!(signature.reports_requested());
+        assert_eq!(signature.report_dns_name(), "_report._domainkey.stalw.art.");
+
+        let signature = concat!(
+            "v=1; a=rsa-sha256; s=default; d=stalw.art; h=From:To; ",
+            "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA=="
+        );
+        assert!(!Signature::parse(signature.as_bytes())
+            .unwrap()
+            .reports_requested());
+    }
+
+    #[test]
+    fn dkim_signature_parse_folded() {
+        let signature = concat!(
+            " v=1; a=rsa-sha256; c=relaxed/relaxed;\r\n",
+            "        d=google.com; s=20210112;\r\n",
+            "        h=content-transfer-encoding:content-disposition:to:from:subject\r\n",
+            "         :message-id:date:mime-version:from:to:cc:subject:date:message-id\r\n",
+            "         :reply-to;\r\n",
+            "        bh=sMF/38UFRhmUYFRJST4vLBu/U1BXgsdCUE02HF8nXx8=;\r\n",
+            "        b=I7WONP7tMsULp4eKjJeeKtM+nDYqMSIYMxqNHqCP1bTsnUiW2xM278I2+F8EjtFNYf\r\n",
+            "         XOgusNn8kqbSnA4w1+q4G87zTF4K3tGnxNpuUMQ7GzcofBKtr7VPv9XFqvTPJ+N8YSwe\r\n",
+            "         926ec7xi71BpSHAgqp5Wqocj8ruIVjcCZ37hYrG0C4s+FVBtbaU3EeyPpkESaaY2vE5y\r\n",
+            "         Qa2KsrMsyJXlbyW/sFJ7AGDDuXwyGkTa+btP/xIiQM2HlBKy7vNOFZKkxInOuQsXJgZy\r\n",
+            "         3H7ivlpD3hMrszwU77o5jBArVwN0RIkUSosAPQf+pzgvRlkseRlDrmzKQutvYWIaTP3/\r\n",
+            "         FHPA==\r\n",
+        );
+        let signature = Signature::parse(signature.as_bytes()).unwrap();
+        assert_eq!(signature.v, 1);
+        assert_eq!(signature.a, Algorithm::RsaSha256);
+        assert_eq!(signature.d, "google.com");
+        assert_eq!(signature.s, "20210112");
+        assert_eq!(signature.ch, Canonicalization::Relaxed);
+        assert_eq!(signature.cb, Canonicalization::Relaxed);
+        assert_eq!(
+            signature.signed_headers().collect::<Vec<_>>(),
+            [
+                "content-transfer-encoding",
+                "content-disposition",
+                "to",
+                "from",
+                "subject",
+                "message-id",
+                "date",
+                "mime-version",
+                "from",
+                "to",
+                "cc",
+                "subject",
+                "date",
+                "message-id",
+                "reply-to"
+            ]
+        );
+        assert_eq!(
+            signature.bh,
+            base64_decode(b"sMF/38UFRhmUYFRJST4vLBu/U1BXgsdCUE02HF8nXx8=").unwrap()
+        );
+        assert_eq!(signature.b.len(), 256);
+        assert_eq!(signature.b[..4], [35, 181, 142, 52]);
+        assert_eq!(signature.b[252..], [223, 241, 71, 60]);
+        assert_eq!(signature.dns_key_name(), "20210112._domainkey.google.com.");
     }
 }
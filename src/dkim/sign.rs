@@ -16,23 +16,26 @@ use super::{canonicalize::CanonicalHeaders, DkimSigner, Done, Signature};
 
 use crate::{
     common::{
-        crypto::SigningKey,
+        crypto::{HashContext, HashImpl, Signer, SigningKey},
         headers::{ChainedHeaderIterator, HeaderIterator, HeaderStream, Writable, Writer},
     },
-    Error,
+    AuthenticatedMessage, Error,
 };
 
 impl<T: SigningKey> DkimSigner<T, Done> {
     /// Signs a message.
     #[inline(always)]
     pub fn sign(&self, message: &[u8]) -> crate::Result<Signature> {
-        self.sign_stream(
-            HeaderIterator::new(message),
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
-        )
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if self.crlf_normalization {
+            let message = AuthenticatedMessage::normalize_line_endings(message);
+            self.sign_stream(HeaderIterator::new(&message), now)
+        } else {
+            self.sign_stream(HeaderIterator::new(message), now)
+        }
     }
 
     #[inline(always)]
@@ -41,13 +44,43 @@ impl<T: SigningKey> DkimSigner<T, Done> {
         &self,
         chunks: impl Iterator<Item = &'x [u8]>,
     ) -> crate::Result<Signature> {
-        self.sign_stream(
-            ChainedHeaderIterator::new(chunks),
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
-        )
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if self.crlf_normalization {
+            let message = chunks.flatten().copied().collect::<Vec<_>>();
+            let message = AuthenticatedMessage::normalize_line_endings(&message);
+            self.sign_stream(HeaderIterator::new(&message), now)
+        } else {
+            self.sign_stream(ChainedHeaderIterator::new(chunks), now)
+        }
+    }
+
+    /// Signs a message using `rng` for any randomized step of the signing
+    /// operation, such as RSA blinding, where [`DkimSigner::sign`] uses the
+    /// operating system's random number generator. Only available with the
+    /// `rust-crypto` backend, `ring` always uses the system generator.
+    #[cfg(feature = "rust-crypto")]
+    pub fn sign_with_rng(
+        &self,
+        message: &[u8],
+        rng: &mut impl rsa::rand_core::CryptoRngCore,
+    ) -> crate::Result<Signature> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if self.crlf_normalization {
+            let message = AuthenticatedMessage::normalize_line_endings(message);
+            self.sign_stream_with(HeaderIterator::new(&message), now, |input| {
+                SigningKey::sign_with_rng(&self.key, input, rng)
+            })
+        } else {
+            self.sign_stream_with(HeaderIterator::new(message), now, |input| {
+                SigningKey::sign_with_rng(&self.key, input, rng)
+            })
+        }
     }
 
     fn sign_stream<'x>(
@@ -55,8 +88,79 @@ impl<T: SigningKey> DkimSigner<T, Done> {
         message: impl HeaderStream<'x>,
         now: u64,
     ) -> crate::Result<Signature> {
+        self.sign_stream_with(message, now, |input| SigningKey::sign(&self.key, input))
+    }
+
+    fn sign_stream_with<'x>(
+        &self,
+        message: impl HeaderStream<'x>,
+        now: u64,
+        sign: impl FnOnce(SignableMessage<'_>) -> crate::Result<Vec<u8>>,
+    ) -> crate::Result<Signature> {
+        let (mut signature, canonical_headers) = self.prepare(message, now)?;
+
+        // Sign
+        let b = sign(SignableMessage {
+            headers: canonical_headers,
+            signature: &signature,
+        })?;
+
+        // Encode
+        signature.b = base64_encode(&b)?;
+
+        Ok(signature)
+    }
+}
+
+impl<T: Signer> DkimSigner<T, Done> {
+    /// Signs a message, delegating the signing operation to a [`Signer`]
+    /// which may complete asynchronously, such as a KMS or HSM backed key.
+    pub async fn sign_async(&self, message: &[u8]) -> crate::Result<Signature> {
+        let (data, mut signature) = self.signing_input(message)?;
+        let b = Signer::sign(&self.key, &data).await?;
+
+        // Encode
+        signature.b = base64_encode(&b)?;
+
+        Ok(signature)
+    }
+
+    /// Returns the data to be signed, which are the canonicalized signed
+    /// headers followed by the DKIM-Signature header with an empty `b=` tag,
+    /// together with the signature being built. Once the data is signed
+    /// externally, the signature is completed by setting `b` to the base64
+    /// encoded signature bytes.
+    pub fn signing_input(&self, message: &[u8]) -> crate::Result<(Vec<u8>, Signature)> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let message = if self.crlf_normalization {
+            AuthenticatedMessage::normalize_line_endings(message)
+        } else {
+            message.into()
+        };
+        let (signature, canonical_headers) = self.prepare(HeaderIterator::new(&message), now)?;
+
+        let mut data = Vec::with_capacity(256);
+        SignableMessage {
+            headers: canonical_headers,
+            signature: &signature,
+        }
+        .write(&mut data);
+
+        Ok((data, signature))
+    }
+
+    /// Canonicalizes the message and builds the signature, leaving
+    /// the `b=` tag empty.
+    fn prepare<'x>(
+        &self,
+        message: impl HeaderStream<'x>,
+        now: u64,
+    ) -> crate::Result<(Signature, CanonicalHeaders<'x>)> {
         // Canonicalize headers and body
-        let (body_len, canonical_headers, signed_headers, canonical_body) =
+        let (_, canonical_headers, signed_headers, mut canonical_body) =
             self.template.canonicalize(message);
 
         if signed_headers.is_empty() {
@@ -65,7 +169,18 @@ impl<T: SigningKey> DkimSigner<T, Done> {
 
         // Create Signature
         let mut signature = self.template.clone();
-        let body_hash = self.key.hash(canonical_body);
+        if let Some(len) = self
+            .body_length_marker
+            .as_deref()
+            .and_then(|marker| canonical_body.truncate_before(marker))
+        {
+            signature.l = len;
+        } else if signature.l > 0 {
+            signature.l = canonical_body.len();
+        }
+        let mut hasher = <T::Hasher as HashImpl>::hasher();
+        canonical_body.write(&mut hasher);
+        let body_hash = hasher.complete();
         signature.bh = base64_encode(body_hash.as_ref())?;
         signature.t = now;
         signature.x = if signature.x > 0 {
@@ -74,20 +189,8 @@ impl<T: SigningKey> DkimSigner<T, Done> {
             0
         };
         signature.h = signed_headers;
-        if signature.l > 0 {
-            signature.l = body_len as u64;
-        }
-
-        // Sign
-        let b = self.key.sign(SignableMessage {
-            headers: canonical_headers,
-            signature: &signature,
-        })?;
 
-        // Encode
-        signature.b = base64_encode(&b)?;
-
-        Ok(signature)
+        Ok((signature, canonical_headers))
     }
 }
 
@@ -110,16 +213,23 @@ pub mod test {
     use std::time::{Duration, Instant};
 
     use hickory_resolver::proto::op::ResponseCode;
+    use mail_builder::encoders::base64::base64_encode;
     use mail_parser::decoders::base64::base64_decode;
 
     use crate::{
         common::{
-            crypto::{Ed25519Key, RsaKey, Sha256},
+            clock::Clock,
+            crypto::{Algorithm, Ed25519Key, RsaKey, Sha256, Signer, SigningKey},
             headers::HeaderIterator,
             parse::TxtRecordParser,
+            resolver::{test::FrozenClock, DnsAnswers},
             verify::DomainKey,
         },
-        dkim::{Atps, Canonicalization, DkimSigner, DomainKeyReport, HashAlgorithm, Signature},
+        dkim::{
+            headers::insert_signature, Atps, Canonicalization, DkimOptions, DkimSigner,
+            DkimVerifier, DkimVerifyMode, DomainKeyReport, Done, HashAlgorithm, Signature,
+            SignaturePosition,
+        },
         AuthenticatedMessage, DkimOutput, DkimResult, Resolver,
     };
 
@@ -141,6 +251,61 @@ pub mod test {
     const ED25519_PUBLIC_KEY: &str =
         "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
 
+    const TPS_REPORT: &str = concat!(
+        "From: bill@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: TPS Report\r\n",
+        "\r\n",
+        "I'm going to need those TPS reports ASAP.\r\n"
+    );
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    fn rsa_key() -> RsaKey<Sha256> {
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        pk_rsa
+    }
+
+    /// Signer using the RSA test key as `default._domainkey.example.com`.
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    fn rsa_signer() -> DkimSigner<RsaKey<Sha256>, Done> {
+        DkimSigner::from_key(rsa_key())
+            .domain("example.com")
+            .selector("default")
+            .headers(["From", "To", "Subject"])
+    }
+
+    /// Returns `message` with `signature` prepended, along with the DNS answers
+    /// publishing the RSA test key as `default._domainkey.example.com`.
+    fn signed_message(
+        signature: &Signature,
+        message: &str,
+    ) -> (AuthenticatedMessage<'static>, DnsAnswers) {
+        signed_message_with_opts(signature, message, true)
+    }
+
+    fn signed_message_with_opts(
+        signature: &Signature,
+        message: &str,
+        strict: bool,
+    ) -> (AuthenticatedMessage<'static>, DnsAnswers) {
+        let mut raw_message = Vec::new();
+        signature.write(&mut raw_message, true);
+        raw_message.extend_from_slice(message.as_bytes());
+        (
+            AuthenticatedMessage::parse_with_opts(raw_message.leak(), strict).unwrap(),
+            DnsAnswers::new().with_txt("default._domainkey.example.com.", RSA_PUBLIC_KEY),
+        )
+    }
+
     #[cfg(any(
         feature = "rust-crypto",
         all(feature = "ring", feature = "rustls-pemfile")
@@ -373,6 +538,30 @@ pub mod test {
         )
         .await;
 
+        dbg!("Test RSA-SHA256 simple/relaxed with fixed body length (modified body)");
+        #[cfg(feature = "rust-crypto")]
+        let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_rsa = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        verify_with_opts(
+            &resolver,
+            DkimSigner::from_key(pk_rsa)
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .header_canonicalization(Canonicalization::Simple)
+                .body_length(true)
+                .sign(message.as_bytes())
+                .unwrap(),
+            &(message.replace("ASAP", "later") + "\r\n----- Mailing list"),
+            Err(super::Error::BodyHashMismatch {
+                expected: "Xcxymouf0VhlJ7c/vHLAM3LPTUR4LKFKX7PRNniWCEs=".to_string(),
+                computed: "e92s5e2Y316vfQbFEzuodEFAr40Y0zeWa4XC5Ew3ljM=".to_string(),
+            }),
+            false,
+        )
+        .await;
+
         dbg!("Test RSA-SHA256 simple/relaxed with fixed body length (strict)");
         #[cfg(feature = "rust-crypto")]
         let pk_rsa = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
@@ -509,6 +698,1271 @@ pub mod test {
         .await;
     }
 
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_lf_message() {
+        let message = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n",
+            "So, if you could do that, that'd be great.\r\n"
+        );
+
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        let signature = rsa_signer()
+            .header_canonicalization(Canonicalization::Simple)
+            .body_canonicalization(Canonicalization::Simple)
+            .sign(message.as_bytes())
+            .unwrap();
+
+        // Message stored with LF-only line endings
+        let mut raw_message = Vec::new();
+        signature.write(&mut raw_message, true);
+        raw_message.extend_from_slice(message.as_bytes());
+        let signed_message = raw_message.clone();
+        let raw_message = String::from_utf8(raw_message)
+            .unwrap()
+            .replace("\r\n", "\n");
+
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert!(matches!(
+            dkim.last().unwrap().result(),
+            DkimResult::Fail(super::Error::FailedVerification)
+        ));
+
+        let normalized = AuthenticatedMessage::normalize_line_endings(raw_message.as_bytes());
+        assert_eq!(normalized.as_ref(), signed_message.as_slice());
+        let message = AuthenticatedMessage::parse(&normalized).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+
+        // Lone CRs and messages already using CRLF
+        assert_eq!(
+            AuthenticatedMessage::normalize_line_endings(b"a\rb\nc\r\n\r").as_ref(),
+            b"a\r\nb\r\nc\r\n\r\n"
+        );
+        assert!(matches!(
+            AuthenticatedMessage::normalize_line_endings(&signed_message),
+            std::borrow::Cow::Borrowed(_)
+        ));
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_sign_body_length_before_marker() {
+        let headers = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+        );
+        let body = "I'm going to need those TPS reports ASAP.\r\n";
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        let sign = |message: &str| {
+            rsa_signer()
+                .body_length_before_marker("\r\n-- \r\n")
+                .sign(message.as_bytes())
+                .unwrap()
+        };
+
+        // The signature separator and everything after it is not signed
+        let message = format!("{headers}{body}\r\n-- \r\nBill\r\n");
+        let signature = sign(&message);
+        assert_eq!(signature.l, body.len() as u64);
+        verify_with_opts(
+            &resolver,
+            signature.clone(),
+            &format!("{headers}{body}\r\n-- \r\nMailing list footer\r\n"),
+            Ok(()),
+            false,
+        )
+        .await;
+        let (message, _) =
+            signed_message_with_opts(&signature, &message.replace("ASAP", "later"), false);
+        assert!(matches!(
+            resolver
+                .verify_dkim(&message)
+                .await
+                .last()
+                .unwrap()
+                .result(),
+            DkimResult::Neutral(crate::Error::BodyHashMismatch { .. })
+        ));
+
+        // Without the marker the whole body is signed
+        let message = format!("{headers}{body}");
+        let signature = sign(&message);
+        assert_eq!(signature.l, 0);
+        verify(&resolver, signature, &message, Ok(())).await;
+
+        // The marker is located in the canonicalized body
+        for (cb, raw_body, l) in [
+            (
+                Canonicalization::Relaxed,
+                "Hi  there \r\n\r\n--\t\r\nBill\r\n",
+                "Hi there\r\n".len(),
+            ),
+            (
+                Canonicalization::Simple,
+                "Hi  there \n\n-- \nBill\n",
+                "Hi  there \r\n".len(),
+            ),
+        ] {
+            let message = format!("{headers}{raw_body}");
+            let signature = rsa_signer()
+                .body_canonicalization(cb)
+                .body_length_before_marker("\r\n-- \r\n")
+                .sign(message.as_bytes())
+                .unwrap();
+            assert_eq!(signature.l, l as u64, "{cb:?}");
+            verify_with_opts(
+                &resolver,
+                signature,
+                &format!("{message}Mailing list footer\r\n"),
+                Ok(()),
+                false,
+            )
+            .await;
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_sign_crlf_normalization() {
+        let message = concat!(
+            "From: bill@example.com\n",
+            "To: jdoe@example.com\n",
+            "Subject: TPS Report\n",
+            "\n",
+            "I'm going to need those TPS reports ASAP.\n",
+            "So, if you could do that, that'd be great.\n"
+        );
+        let crlf_message = message.replace('\n', "\r\n");
+
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        for (crlf_normalization, chained, expect) in [
+            (true, false, Ok(())),
+            (true, true, Ok(())),
+            (false, false, Err(super::Error::FailedVerification)),
+        ] {
+            let signer = rsa_signer()
+                .header_canonicalization(Canonicalization::Simple)
+                .body_canonicalization(Canonicalization::Simple)
+                .body_length(true)
+                .crlf_normalization(crlf_normalization);
+            let signature = if chained {
+                signer.sign_chained(message.as_bytes().chunks(5)).unwrap()
+            } else {
+                signer.sign(message.as_bytes()).unwrap()
+            };
+            if crlf_normalization {
+                assert_eq!(
+                    signature.l,
+                    crlf_message.split_once("\r\n\r\n").unwrap().1.len() as u64
+                );
+            }
+
+            verify_with_opts(&resolver, signature, &crlf_message, expect, false).await;
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_canonical_body_length() {
+        let headers = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+        );
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        // l= counts the canonicalized body, which can be longer than the raw one
+        for (body, cb, l) in [
+            ("", Canonicalization::Simple, 2),
+            ("Hi\nthere\n", Canonicalization::Simple, 11),
+            ("Hi\nthere\n", Canonicalization::Relaxed, 11),
+        ] {
+            let message = format!("{headers}{body}");
+            let signature = rsa_signer()
+                .body_canonicalization(cb)
+                .body_length(true)
+                .sign(message.as_bytes())
+                .unwrap();
+            assert_eq!(signature.l, l, "{body:?} {cb:?}");
+            verify_with_opts(&resolver, signature.clone(), &message, Ok(()), false).await;
+
+            let mut raw_message = Vec::new();
+            signature.write(&mut raw_message, true);
+            raw_message.extend_from_slice(message.as_bytes());
+            let mut verifier = DkimVerifier::new().with_strict(false);
+            verifier.write(&raw_message);
+            let message = verifier.finish().unwrap();
+            assert_eq!(
+                resolver.verify_dkim(&message).await[0].result(),
+                &DkimResult::Pass,
+                "{body:?} {cb:?}"
+            );
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_key_resolver() {
+        let signature = DkimSigner::from_key(rsa_key())
+            .domain("example.com")
+            .selector("archived")
+            .headers(["From", "To", "Subject"])
+            .sign(TPS_REPORT.as_bytes())
+            .unwrap();
+        let (message, _) = signed_message(&signature, TPS_REPORT);
+
+        // Keys are not published in DNS
+        let resolver = Resolver::new_system_conf().unwrap();
+        let dkim = resolver
+            .verify_dkim_with_key_resolver(&message, &DkimOptions::default(), |domain, selector| {
+                if (domain, selector) == ("example.com", "archived") {
+                    DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).ok()
+                } else {
+                    None
+                }
+            })
+            .await;
+        assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+
+        let dkim = resolver
+            .verify_dkim_with_key_resolver(&message, &DkimOptions::default(), |_, _| None)
+            .await;
+        assert_eq!(
+            dkim.last().unwrap().result(),
+            &DkimResult::PermError(super::Error::DnsRecordNotFound(ResponseCode::NXDomain))
+        );
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_max_signatures() {
+        let mut raw_message = Vec::new();
+        for selector in ["s1", "s2", "s3"] {
+            DkimSigner::from_key(rsa_key())
+                .domain("example.com")
+                .selector(selector)
+                .headers(["From", "To", "Subject"])
+                .sign(TPS_REPORT.as_bytes())
+                .unwrap()
+                .write(&mut raw_message, true);
+        }
+        raw_message.extend_from_slice(TPS_REPORT.as_bytes());
+        let message = AuthenticatedMessage::parse(&raw_message).unwrap();
+
+        let lookups = std::sync::atomic::AtomicUsize::new(0);
+        let resolver = Resolver::new_system_conf().unwrap();
+        let opts = DkimOptions::new().max_signatures(2);
+        let dkim = resolver
+            .verify_dkim_with_key_resolver(&message, &opts, |_, _| {
+                lookups.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).ok()
+            })
+            .await;
+        assert_eq!(lookups.into_inner(), 2);
+        assert_eq!(dkim.len(), 3);
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(dkim[1].result(), &DkimResult::Pass);
+        assert_eq!(
+            dkim[2].result(),
+            &DkimResult::Neutral(super::Error::SignatureLimitExceeded)
+        );
+        assert!(dkim[2].exceeds_signature_limit());
+        assert!(!dkim[1].exceeds_signature_limit());
+        assert_eq!(dkim[2].signature().unwrap().s, "s3");
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_stop_on_first_pass() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        let mut raw_message = Vec::new();
+        for (domain, publish_key) in [
+            ("example.org", false),
+            ("example.net", true),
+            ("com", true),
+            ("example.com", true),
+        ] {
+            DkimSigner::from_key(rsa_key())
+                .domain(domain)
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .sign(TPS_REPORT.as_bytes())
+                .unwrap()
+                .write(&mut raw_message, true);
+            if publish_key {
+                resolver.txt_add(
+                    format!("default._domainkey.{domain}."),
+                    DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+                    Instant::now() + Duration::new(3600, 0),
+                );
+            }
+        }
+        raw_message.extend_from_slice(TPS_REPORT.as_bytes());
+        let message = AuthenticatedMessage::parse(&raw_message).unwrap();
+        let domains = |dkim: &[DkimOutput]| {
+            dkim.iter()
+                .map(|o| o.signature().unwrap().d.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Evaluate all signatures
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(
+            domains(&dkim),
+            ["example.org", "example.net", "com", "example.com"]
+        );
+        assert_eq!(
+            dkim,
+            resolver
+                .verify_dkim_with_opts(
+                    &message,
+                    &DkimOptions::new().mode(DkimVerifyMode::EvaluateAll)
+                )
+                .await
+        );
+
+        // Failures do not stop the evaluation
+        let dkim = resolver
+            .verify_dkim_with_opts(
+                &message,
+                &DkimOptions::new().mode(DkimVerifyMode::StopOnFirstPass { domain: None }),
+            )
+            .await;
+        assert_eq!(domains(&dkim), ["example.org", "example.net"]);
+        assert!(matches!(dkim[0].result(), DkimResult::PermError(_)));
+        assert_eq!(dkim[1].result(), &DkimResult::Pass);
+
+        // Only an aligned signature stops the evaluation, which a public
+        // suffix never is
+        let dkim = resolver
+            .verify_dkim_with_opts(
+                &message,
+                &DkimOptions::new().mode(DkimVerifyMode::StopOnFirstPass {
+                    domain: Some("mail.example.com".to_string()),
+                }),
+            )
+            .await;
+        assert_eq!(
+            domains(&dkim),
+            ["example.org", "example.net", "com", "example.com"]
+        );
+        assert_eq!(dkim[2].result(), &DkimResult::Pass);
+        assert_eq!(dkim[3].result(), &DkimResult::Pass);
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_stop_on_first_atps_pass() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "example.net._atps.example.com.".to_string(),
+            Atps::parse(b"v=ATPS1;").unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        let mut raw_message = Vec::new();
+        for domain in ["example.net", "example.com"] {
+            DkimSigner::from_key(rsa_key())
+                .domain(domain)
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .atps("example.com")
+                .sign(TPS_REPORT.as_bytes())
+                .unwrap()
+                .write(&mut raw_message, true);
+        }
+        raw_message.extend_from_slice(TPS_REPORT.as_bytes());
+        let message = AuthenticatedMessage::parse(&raw_message).unwrap();
+
+        for (mode, expected_lookups) in [
+            (DkimVerifyMode::EvaluateAll, 2),
+            (DkimVerifyMode::StopOnFirstPass { domain: None }, 1),
+        ] {
+            let lookups = std::sync::atomic::AtomicUsize::new(0);
+            let dkim = resolver
+                .verify_dkim_with_key_resolver(&message, &DkimOptions::new().mode(mode), |_, _| {
+                    lookups.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).ok()
+                })
+                .await;
+            assert_eq!(lookups.into_inner(), expected_lookups);
+            assert_eq!(dkim.len(), expected_lookups);
+            assert_eq!(dkim[0].result(), &DkimResult::Pass);
+            assert!(dkim[0].is_atps);
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_body_hash_mismatch() {
+        for body_length in [false, true] {
+            let signature = rsa_signer()
+                .body_length(body_length)
+                .sign(TPS_REPORT.as_bytes())
+                .unwrap();
+            let (message, answers) = signed_message_with_opts(
+                &signature,
+                &(TPS_REPORT.replace("ASAP", "later") + "\r\n----- Mailing list"),
+                false,
+            );
+            let resolver = Resolver::new_system_conf()
+                .unwrap()
+                .with_dns_answers(answers);
+
+            let dkim = resolver.verify_dkim(&message).await;
+            match dkim.last().unwrap().result() {
+                DkimResult::Neutral(crate::Error::BodyHashMismatch { expected, computed }) => {
+                    assert_eq!(expected.as_bytes(), signature.bh);
+                    assert_eq!(
+                        base64_decode(computed.as_bytes()).unwrap(),
+                        message.body_hashes[0].3
+                    );
+                    assert_ne!(expected, computed);
+                }
+                result => panic!("Unexpected result {result:?}"),
+            }
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_debug_canonical_form() {
+        let signature = rsa_signer().sign(TPS_REPORT.as_bytes()).unwrap();
+        let (intact, answers) = signed_message(&signature, TPS_REPORT);
+        let (modified, _) = signed_message(
+            &signature,
+            &TPS_REPORT.replace("TPS Report", "Re: TPS Report"),
+        );
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(answers);
+
+        // Disabled by default
+        let dkim = resolver.verify_dkim(&modified).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Fail(crate::Error::FailedVerification)
+        );
+        assert_eq!(dkim[0].canonical_form(), None);
+
+        // Failed signatures keep the canonical form that was hashed
+        let opts = DkimOptions::new().debug(true);
+        let dkim = resolver.verify_dkim_with_opts(&modified, &opts).await;
+        let (headers, body) = dkim[0].canonical_form().unwrap();
+        assert!(
+            headers.starts_with(b"subject:Re: TPS Report\r\n"),
+            "{}",
+            String::from_utf8_lossy(headers)
+        );
+        assert_eq!(body, b"I'm going to need those TPS reports ASAP.\r\n");
+
+        // Streamed messages do not retain their body
+        let mut verifier = DkimVerifier::new();
+        verifier.write(modified.raw_message());
+        let streamed = verifier.finish().unwrap();
+        let dkim = resolver.verify_dkim_with_opts(&streamed, &opts).await;
+        let (streamed_headers, body) = dkim[0].canonical_form().unwrap();
+        assert_eq!(streamed_headers, headers);
+        assert!(body.is_empty());
+
+        // Passing signatures do not
+        let dkim = resolver.verify_dkim_with_opts(&intact, &opts).await;
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(dkim[0].canonical_form(), None);
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_key_record() {
+        let signature = rsa_signer().sign(TPS_REPORT.as_bytes()).unwrap();
+        let (signed, answers) = signed_message(&signature, TPS_REPORT);
+        let (modified, _) = signed_message(
+            &signature,
+            &TPS_REPORT.replace("TPS Report", "Re: TPS Report"),
+        );
+        let answers = answers.with_txt("default._domainkey.example.com.", "v=spf1 -all");
+
+        // Disabled by default
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(answers);
+        let dkim = resolver.verify_dkim(&signed).await;
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(dkim[0].key_record(), None);
+
+        // The record that verified the signature is kept, both for passing
+        // and failing signatures
+        let opts = DkimOptions::new().key_records(true);
+        let dkim = resolver.verify_dkim_with_opts(&signed, &opts).await;
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(dkim[0].key_record(), Some(RSA_PUBLIC_KEY));
+        let dkim = resolver.verify_dkim_with_opts(&modified, &opts).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Fail(crate::Error::FailedVerification)
+        );
+        assert_eq!(dkim[0].key_record(), Some(RSA_PUBLIC_KEY));
+
+        // Keys cached before the option was enabled are also reported
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        assert_eq!(resolver.verify_dkim(&signed).await[0].key_record(), None);
+        assert_eq!(
+            resolver.verify_dkim_with_opts(&signed, &opts).await[0].key_record(),
+            Some(RSA_PUBLIC_KEY)
+        );
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_ignore_expiration() {
+        let signature = rsa_signer()
+            .expiration(12345)
+            .sign_stream(HeaderIterator::new(TPS_REPORT.as_bytes()), 12345)
+            .unwrap();
+
+        for (body, ignore_expiration, expected_result, expected_expired) in [
+            (
+                TPS_REPORT.to_string(),
+                false,
+                DkimResult::Neutral(crate::Error::SignatureExpired),
+                false,
+            ),
+            (TPS_REPORT.to_string(), true, DkimResult::Pass, true),
+            (
+                TPS_REPORT.replace("TPS Report", "TPS Reports"),
+                true,
+                DkimResult::Fail(crate::Error::FailedVerification),
+                true,
+            ),
+        ] {
+            let (message, answers) = signed_message(&signature, &body);
+            let resolver = Resolver::new_system_conf()
+                .unwrap()
+                .with_dns_answers(answers);
+            let opts = DkimOptions::new().ignore_expiration(ignore_expiration);
+
+            let dkim = resolver.verify_dkim_with_opts(&message, &opts).await;
+            let dkim = dkim.last().unwrap();
+            assert_eq!(dkim.result(), &expected_result);
+            assert_eq!(dkim.expired_at_verification(), expected_expired);
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_clock() {
+        let signature = rsa_signer()
+            .expiration(12345)
+            .sign_stream(HeaderIterator::new(TPS_REPORT.as_bytes()), 12345)
+            .unwrap();
+        let (message, answers) = signed_message(&signature, TPS_REPORT);
+
+        // Expiration is checked against the configured clock
+        let clock = FrozenClock::new(20000);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_dns_answers(answers);
+        assert_eq!(
+            resolver
+                .verify_dkim(&message)
+                .await
+                .last()
+                .unwrap()
+                .result(),
+            &DkimResult::Pass
+        );
+
+        clock.advance(10000);
+        assert_eq!(
+            resolver
+                .verify_dkim(&message)
+                .await
+                .last()
+                .unwrap()
+                .result(),
+            &DkimResult::Neutral(crate::Error::SignatureExpired)
+        );
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_future_timestamp() {
+        let clock = FrozenClock::new(20000);
+
+        for (signed_at, max_skew, expected_result) in [
+            (
+                20000 + 3600,
+                Some(Duration::from_secs(300)),
+                DkimResult::Neutral(crate::Error::SignatureTimeInFuture),
+            ),
+            (20000 + 60, Some(Duration::from_secs(300)), DkimResult::Pass),
+            (
+                20000 + 3600,
+                Some(Duration::from_secs(7200)),
+                DkimResult::Pass,
+            ),
+            (20000 + 3600, None, DkimResult::Pass),
+        ] {
+            let signature = rsa_signer()
+                .sign_stream(HeaderIterator::new(TPS_REPORT.as_bytes()), signed_at)
+                .unwrap();
+            let (message, answers) = signed_message(&signature, TPS_REPORT);
+
+            let resolver = Resolver::new_system_conf()
+                .unwrap()
+                .with_clock(clock.clone())
+                .with_dns_answers(answers);
+            let opts = DkimOptions::new().max_clock_skew(max_skew);
+            assert_eq!(
+                resolver
+                    .verify_dkim_with_opts(&message, &opts)
+                    .await
+                    .last()
+                    .unwrap()
+                    .result(),
+                &expected_result
+            );
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[test]
+    fn dkim_canonicalization_matches() {
+        let message = concat!(
+            "From:   bill@example.com\r\n",
+            "To: jdoe@example.com \r\n",
+            "Subject: TPS  Report\r\n",
+            "\r\n",
+            "I'm going to need  those TPS reports ASAP. \r\n",
+            "\r\n",
+            "\r\n"
+        );
+        let key = DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap();
+
+        for (ch, cb) in [
+            (Canonicalization::Relaxed, Canonicalization::Relaxed),
+            (Canonicalization::Simple, Canonicalization::Relaxed),
+            (Canonicalization::Relaxed, Canonicalization::Simple),
+        ] {
+            let signature = rsa_signer()
+                .header_canonicalization(ch)
+                .body_canonicalization(cb)
+                .sign(message.as_bytes())
+                .unwrap();
+            let (message, _) = signed_message(&signature, message);
+            let signature = message.dkim_headers[0].header.as_ref().unwrap();
+
+            assert_eq!(
+                message.canonicalization_matches(signature, Some(&key)),
+                vec![(ch, cb)]
+            );
+            assert_eq!(
+                message.canonicalization_matches(signature, None),
+                vec![
+                    (Canonicalization::Relaxed, cb),
+                    (Canonicalization::Simple, cb)
+                ]
+            );
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[test]
+    fn dkim_signer_build() {
+        let signer = DkimSigner::from_key(rsa_key())
+            .domain("example.com")
+            .selector("s1")
+            .headers(["From", "To", "Subject"])
+            .canonicalization(Canonicalization::Simple, Canonicalization::Relaxed)
+            .agent_user_identifier("\"jdoe@example.org\"@mail.example.com")
+            .expiration(3600)
+            .build()
+            .unwrap();
+        assert_eq!(signer.template.ch, Canonicalization::Simple);
+        assert_eq!(signer.template.cb, Canonicalization::Relaxed);
+        assert_eq!(signer.template.x, 3600);
+
+        for (domain, selector, headers, auid, expected_err) in [
+            ("", "s1", vec!["From"], "", crate::Error::MissingParameters),
+            (
+                "example.com",
+                "",
+                vec!["From"],
+                "",
+                crate::Error::MissingParameters,
+            ),
+            (
+                "example.com",
+                "s1",
+                vec!["To", "Subject"],
+                "",
+                crate::Error::MissingParameters,
+            ),
+            (
+                "example.com",
+                "s1",
+                vec!["from"],
+                "jdoe@example.org",
+                crate::Error::FailedAuidMatch,
+            ),
+            (
+                "example.com",
+                "s1",
+                vec!["from"],
+                "jdoe@badexample.com",
+                crate::Error::FailedAuidMatch,
+            ),
+        ] {
+            let result = DkimSigner::from_key(rsa_key())
+                .domain(domain)
+                .selector(selector)
+                .headers(headers)
+                .agent_user_identifier(auid)
+                .build();
+            assert_eq!(result.err(), Some(expected_err));
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_sign_insert_signature() {
+        let message = concat!(
+            "Received: from mx1.example.com by mx2.example.com\r\n",
+            "Received: from client.example.com\r\n",
+            "\tby mx1.example.com\r\n",
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+
+        let signature = DkimSigner::from_key(rsa_key())
+            .domain("example.com")
+            .selector("default")
+            .headers(["From", "To", "Subject", "Received"])
+            .sign(message.as_bytes())
+            .unwrap();
+
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        for (position, expected_index) in [
+            (SignaturePosition::Top, 0),
+            (SignaturePosition::Index(0), 0),
+            (SignaturePosition::Index(1), 1),
+            (SignaturePosition::After("received".to_string()), 2),
+            (SignaturePosition::After("Subject".to_string()), 5),
+            (SignaturePosition::After("Reply-To".to_string()), 0),
+            (SignaturePosition::Index(100), 5),
+        ] {
+            let signed = insert_signature(message.as_bytes(), &signature, position.clone());
+            assert_eq!(
+                HeaderIterator::new(&signed).position(|(name, _)| name == b"DKIM-Signature"),
+                Some(expected_index),
+                "{position:?}"
+            );
+            assert!(signed.ends_with(b"\r\n\r\nI'm going to need those TPS reports ASAP.\r\n"));
+
+            let message = AuthenticatedMessage::parse(&signed).unwrap();
+            let dkim = resolver.verify_dkim(&message).await;
+            assert_eq!(dkim.len(), 1);
+            assert_eq!(dkim[0].result(), &DkimResult::Pass, "{position:?}");
+        }
+    }
+
+    #[cfg(feature = "jwk")]
+    #[tokio::test]
+    async fn dkim_sign_verify_jwk() {
+        let message = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP. ",
+            "So, if you could do that, that'd be great.\r\n"
+        );
+
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        resolver.txt_add(
+            "ed._domainkey.example.com.".to_string(),
+            DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        for (jwk, selector, algorithm) in [
+            (
+                include_str!("../../resources/rsa-private.jwk"),
+                "default",
+                Algorithm::RsaSha256,
+            ),
+            (
+                include_str!("../../resources/ed25519-private.jwk"),
+                "ed",
+                Algorithm::Ed25519Sha256,
+            ),
+        ] {
+            let signer = DkimSigner::from_jwk(jwk).unwrap();
+            assert_eq!(signer.template.a, algorithm);
+            verify(
+                &resolver,
+                signer
+                    .domain("example.com")
+                    .selector(selector)
+                    .headers(["From", "To", "Subject"])
+                    .sign(message.as_bytes())
+                    .unwrap(),
+                message,
+                Ok(()),
+            )
+            .await;
+        }
+
+        for jwk in [
+            "{\"kty\":\"EC\",\"crv\":\"P-256\",\"d\":\"AA\"}",
+            "{\"kty\":\"OKP\",\"crv\":\"X25519\",\"d\":\"AA\",\"x\":\"AA\"}",
+            "{\"kty\":\"OKP\",\"crv\":\"Ed25519\",\"d\":\"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A\"}",
+            concat!(
+                "{\"kty\":\"OKP\",\"crv\":\"Ed25519\",\"alg\":\"RS256\",",
+                "\"d\":\"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A\",",
+                "\"x\":\"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo\"}"
+            ),
+            concat!(
+                "{\"kty\":\"OKP\",\"crv\":\"Ed25519\",",
+                "\"d\":\"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A\",",
+                "\"x\":\"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\"}"
+            ),
+            "{\"kty\":\"RSA\",\"n\":\"AQAB\",\"e\":\"AQAB\"}",
+            "not json",
+        ] {
+            assert!(
+                matches!(DkimSigner::from_jwk(jwk), Err(crate::Error::CryptoError(_))),
+                "{jwk}"
+            );
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[test]
+    fn dkim_verify_body_hash() {
+        let (headers, body) = TPS_REPORT.split_at(TPS_REPORT.find("\r\n\r\n").unwrap() + 4);
+        let signature = rsa_signer().sign(TPS_REPORT.as_bytes()).unwrap();
+
+        for (headers, body, expected_result) in [
+            (headers, body, true),
+            // Modified headers do not affect the body hash
+            (
+                headers.replace("TPS Report", "TPS Reports").as_str(),
+                body,
+                true,
+            ),
+            (
+                headers,
+                "I'm going to need those TPS reports today.\r\n",
+                false,
+            ),
+        ] {
+            let (message, _) = signed_message(&signature, &format!("{headers}{body}"));
+            let signature = message.dkim_headers[0].header.as_ref().unwrap();
+
+            assert_eq!(message.verify_body_hash(signature), expected_result);
+        }
+    }
+
+    #[cfg(feature = "rust-crypto")]
+    #[tokio::test]
+    async fn dkim_sign_with_rng() {
+        use rsa::rand_core::{impls, CryptoRng, RngCore};
+
+        // Deterministic xorshift generator, not suitable outside of tests
+        struct SeededRng(u64);
+
+        impl RngCore for SeededRng {
+            fn next_u32(&mut self) -> u32 {
+                self.next_u64() as u32
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                impls::fill_bytes_via_next(self, dest)
+            }
+
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rsa::rand_core::Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+
+        impl CryptoRng for SeededRng {}
+
+        // Blinding must not alter the deterministic PKCS#1 v1.5 signature
+        let pk_rsa = rsa_key();
+        let data = b"from:bill@example.com\r\n".as_slice();
+        let signature = SigningKey::sign_with_rng(&pk_rsa, data, &mut SeededRng(0x5eed)).unwrap();
+        assert_eq!(
+            signature,
+            SigningKey::sign_with_rng(&pk_rsa, data, &mut SeededRng(0x5eed)).unwrap()
+        );
+        assert_eq!(
+            signature,
+            SigningKey::sign_with_rng(&pk_rsa, data, &mut SeededRng(0xc0ffee)).unwrap()
+        );
+        assert_eq!(signature, SigningKey::sign(&pk_rsa, data).unwrap());
+
+        // Ed25519 signing ignores the RNG
+        let pk_ed = Ed25519Key::from_bytes(&base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap())
+            .unwrap();
+        assert_eq!(
+            SigningKey::sign_with_rng(&pk_ed, data, &mut SeededRng(0x5eed)).unwrap(),
+            SigningKey::sign(&pk_ed, data).unwrap()
+        );
+
+        // Signatures produced with an injected RNG verify
+        let signature = rsa_signer()
+            .sign_with_rng(TPS_REPORT.as_bytes(), &mut SeededRng(0x5eed))
+            .unwrap();
+        let (message, answers) = signed_message(&signature, TPS_REPORT);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(answers);
+        assert_eq!(
+            resolver
+                .verify_dkim(&message)
+                .await
+                .last()
+                .unwrap()
+                .result(),
+            &DkimResult::Pass
+        );
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_signing_input() {
+        let message = TPS_REPORT;
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        let signer = rsa_signer();
+        let (data, mut signature) = signer.signing_input(message.as_bytes()).unwrap();
+        assert!(signature.b.is_empty());
+        assert!(data.starts_with(b"subject:TPS Report\r\nto:jdoe@example.com\r\n"));
+        assert!(
+            data.ends_with(b"; b=;"),
+            "{}",
+            String::from_utf8_lossy(&data)
+        );
+
+        // Sign externally and attach the signature
+        let b = SigningKey::sign(&signer.key, data.as_slice()).unwrap();
+        signature.b = base64_encode(&b).unwrap();
+        verify(&resolver, signature, message, Ok(())).await;
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[test]
+    fn dkim_canonical_form() {
+        let message = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject:   TPS   Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.  \r\n\r\n\r\n"
+        );
+
+        let signer = rsa_signer();
+        let (data, signature) = signer.signing_input(message.as_bytes()).unwrap();
+
+        // Signature not present in the message
+        let unsigned = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let (headers, body) = unsigned.canonical_form(&signature);
+        assert_eq!(headers, data);
+        assert_eq!(body, b"I'm going to need those TPS reports ASAP.\r\n");
+
+        // Signature parsed from the signed message
+        let signature = signer.sign(message.as_bytes()).unwrap();
+        let (signed, _) = signed_message(&signature, message);
+        let signature = signed.dkim_headers[0].header.as_ref().unwrap();
+        let (headers, body) = signed.canonical_form(signature);
+        assert!(
+            headers.starts_with(
+                b"subject:TPS Report\r\nto:jdoe@example.com\r\nfrom:bill@example.com\r\n"
+            ),
+            "{}",
+            String::from_utf8_lossy(&headers)
+        );
+        assert!(
+            headers.ends_with(b"; b=;"),
+            "{}",
+            String::from_utf8_lossy(&headers)
+        );
+        assert_eq!(body, b"I'm going to need those TPS reports ASAP.\r\n");
+        assert!(signed.verify_body_hash(signature));
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_sign_external_signer() {
+        // Signer that completes asynchronously, as a KMS client would
+        struct ExternalKey<T: SigningKey>(T);
+
+        impl<T: SigningKey + Sync> Signer for ExternalKey<T> {
+            type Hasher = T::Hasher;
+
+            async fn sign(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+                tokio::task::yield_now().await;
+                SigningKey::sign(&self.0, data)
+            }
+
+            fn algorithm(&self) -> Algorithm {
+                SigningKey::algorithm(&self.0)
+            }
+        }
+
+        let message = TPS_REPORT;
+
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "default._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        resolver.txt_add(
+            "ed._domainkey.example.com.".to_string(),
+            DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        let pk_rsa = rsa_key();
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed = Ed25519Key::from_bytes(&base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap())
+            .unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed = Ed25519Key::from_seed_and_public_key(
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+        )
+        .unwrap();
+
+        dbg!("Test RSA-SHA256 with an external signer");
+        verify(
+            &resolver,
+            DkimSigner::from_key(ExternalKey(pk_rsa))
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "To", "Subject"])
+                .sign_async(message.as_bytes())
+                .await
+                .unwrap(),
+            message,
+            Ok(()),
+        )
+        .await;
+
+        dbg!("Test ED25519-SHA256 with an external signer");
+        verify(
+            &resolver,
+            DkimSigner::from_key(ExternalKey(pk_ed))
+                .domain("example.com")
+                .selector("ed")
+                .headers(["From", "To", "Subject"])
+                .sign_async(message.as_bytes())
+                .await
+                .unwrap(),
+            message,
+            Ok(()),
+        )
+        .await;
+
+        dbg!("Test in-memory keys implement Signer");
+        verify(
+            &resolver,
+            rsa_signer().sign_async(message.as_bytes()).await.unwrap(),
+            message,
+            Ok(()),
+        )
+        .await;
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dkim_verify_algorithm_mismatch() {
+        let message = TPS_REPORT;
+
+        // Keys published under each other's selector
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "rsa._domainkey.example.com.".to_string(),
+            DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        resolver.txt_add(
+            "ed._domainkey.example.com.".to_string(),
+            DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+
+        let pk_rsa = rsa_key();
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed = Ed25519Key::from_bytes(&base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap())
+            .unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed = Ed25519Key::from_seed_and_public_key(
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+        )
+        .unwrap();
+
+        dbg!("Test RSA-SHA256 signature with an Ed25519 key");
+        verify(
+            &resolver,
+            DkimSigner::from_key(pk_rsa)
+                .domain("example.com")
+                .selector("rsa")
+                .headers(["From", "To", "Subject"])
+                .sign(message.as_bytes())
+                .unwrap(),
+            message,
+            Err(super::Error::AlgorithmMismatch),
+        )
+        .await;
+
+        dbg!("Test ED25519-SHA256 signature with an RSA key");
+        verify(
+            &resolver,
+            DkimSigner::from_key(pk_ed)
+                .domain("example.com")
+                .selector("ed")
+                .headers(["From", "To", "Subject"])
+                .sign(message.as_bytes())
+                .unwrap(),
+            message,
+            Err(super::Error::AlgorithmMismatch),
+        )
+        .await;
+    }
+
     pub async fn verify_with_opts<'x>(
         resolver: &Resolver,
         signature: Signature,
@@ -538,6 +1992,10 @@ pub mod test {
                 signature: None,
                 report: d.report,
                 is_atps: d.is_atps,
+                expired_at_verification: d.expired_at_verification,
+                warnings: d.warnings,
+                canonical_form: d.canonical_form,
+                key_record: d.key_record,
             })
             .collect()
     }
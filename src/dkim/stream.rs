@@ -0,0 +1,280 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::{
+    common::{
+        crypto::{HashAlgorithm, HashContext, HashImpl, Sha1, Sha256},
+        headers::Writer,
+    },
+    AuthenticatedMessage,
+};
+
+use super::{
+    canonicalize::{BodyCanonicalizer, BodyLimit},
+    Canonicalization, DkimVerifier,
+};
+
+pub(crate) struct BodyHasher {
+    cb: Canonicalization,
+    ha: HashAlgorithm,
+    l: u64,
+    canonicalizer: BodyCanonicalizer,
+    context: BodyLimit<BodyHashContext>,
+}
+
+enum BodyHashContext {
+    Sha1(<Sha1 as HashImpl>::Context),
+    Sha256(<Sha256 as HashImpl>::Context),
+}
+
+impl DkimVerifier {
+    pub fn new() -> Self {
+        DkimVerifier {
+            strict: true,
+            lenient_tags: false,
+            headers: Vec::with_capacity(1024),
+            line_start: true,
+            in_body: false,
+            body_hashes: Vec::new(),
+        }
+    }
+
+    /// When disabled, signatures containing the `l=` tag are not rejected.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// When enabled, DKIM-Signature tag names that are not lowercase are
+    /// normalized rather than treated as unknown tags.
+    pub fn with_lenient_tags(mut self, lenient_tags: bool) -> Self {
+        self.lenient_tags = lenient_tags;
+        self
+    }
+
+    /// Writes a chunk of the raw message, which may contain headers,
+    /// body or both.
+    pub fn write(&mut self, bytes: &[u8]) {
+        if self.in_body {
+            self.write_body(bytes);
+            return;
+        }
+
+        for (pos, &ch) in bytes.iter().enumerate() {
+            match ch {
+                b'\n' if self.line_start => {
+                    self.headers.extend_from_slice(&bytes[..=pos]);
+                    self.start_body();
+                    self.write_body(&bytes[pos + 1..]);
+                    return;
+                }
+                b'\n' => {
+                    self.line_start = true;
+                }
+                b'\r' => (),
+                _ => {
+                    self.line_start = false;
+                }
+            }
+        }
+
+        self.headers.extend_from_slice(bytes);
+    }
+
+    /// Writes a chunk of the message body. Any headers still being
+    /// buffered are considered complete.
+    pub fn write_body(&mut self, bytes: &[u8]) {
+        if !self.in_body {
+            if !self.line_start {
+                self.headers.extend_from_slice(b"\r\n");
+            }
+            self.headers.extend_from_slice(b"\r\n");
+            self.start_body();
+        }
+
+        for hasher in &mut self.body_hashes {
+            hasher.canonicalizer.write(bytes, &mut hasher.context);
+        }
+    }
+
+    /// Completes the body hashes and returns the parsed message, or `None`
+    /// if no headers were written.
+    pub fn finish(&mut self) -> Option<AuthenticatedMessage<'_>> {
+        if !self.in_body {
+            self.start_body();
+        }
+
+        let mut body_hashes = Vec::with_capacity(self.body_hashes.len());
+        let mut body_len = Vec::new();
+        for mut hasher in std::mem::take(&mut self.body_hashes) {
+            hasher.canonicalizer.finish(&mut hasher.context);
+            if hasher.l > 0 && !body_len.iter().any(|(cb, _)| cb == &hasher.cb) {
+                body_len.push((hasher.cb, hasher.context.len));
+            }
+            body_hashes.push((
+                hasher.cb,
+                hasher.ha,
+                hasher.l,
+                hasher.context.writer.complete(),
+            ));
+        }
+
+        let mut message =
+            AuthenticatedMessage::parse_headers(&self.headers, self.strict, self.lenient_tags)?;
+        message.body_offset = self.headers.len();
+        message.body_len = body_len;
+        for (cb, ha, l, bh) in &mut message.body_hashes {
+            if let Some((_, _, _, hash)) = body_hashes
+                .iter()
+                .find(|(c, h, l_, _)| c == cb && h == ha && l_ == l)
+            {
+                *bh = hash.clone();
+            }
+        }
+
+        message.into()
+    }
+
+    fn start_body(&mut self) {
+        self.in_body = true;
+        if let Some(message) =
+            AuthenticatedMessage::parse_headers(&self.headers, self.strict, self.lenient_tags)
+        {
+            self.body_hashes = message
+                .body_hashes
+                .into_iter()
+                .map(|(cb, ha, l, _)| BodyHasher {
+                    cb,
+                    ha,
+                    l,
+                    canonicalizer: BodyCanonicalizer::new(cb),
+                    context: BodyLimit::new(
+                        match ha {
+                            HashAlgorithm::Sha1 => BodyHashContext::Sha1(Sha1::hasher()),
+                            HashAlgorithm::Sha256 => BodyHashContext::Sha256(Sha256::hasher()),
+                        },
+                        l,
+                    ),
+                })
+                .collect();
+        }
+    }
+}
+
+impl Default for DkimVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Writer for BodyHashContext {
+    fn write(&mut self, buf: &[u8]) {
+        match self {
+            BodyHashContext::Sha1(context) => context.write(buf),
+            BodyHashContext::Sha256(context) => context.write(buf),
+        }
+    }
+}
+
+impl BodyHashContext {
+    fn complete(self) -> Vec<u8> {
+        match self {
+            BodyHashContext::Sha1(context) => context.complete().as_ref().to_vec(),
+            BodyHashContext::Sha256(context) => context.complete().as_ref().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        common::{parse::TxtRecordParser, verify::DomainKey},
+        dkim::DkimOptions,
+        AuthenticatedMessage, DkimResult, Resolver,
+    };
+
+    use super::DkimVerifier;
+
+    #[tokio::test]
+    async fn dkim_verify_stream() {
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("resources");
+        test_dir.push("dkim");
+
+        for file_name in fs::read_dir(&test_dir).unwrap() {
+            let file_name = file_name.unwrap().path();
+            println!("DKIM stream verifying {}", file_name.to_str().unwrap());
+
+            let test = String::from_utf8(fs::read(&file_name).unwrap()).unwrap();
+            let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+            let resolver = Resolver::new_system_conf().unwrap();
+            for (key, value) in dns_records
+                .split('\n')
+                .filter_map(|r| r.split_once(' ').map(|(a, b)| (a, b.as_bytes())))
+            {
+                resolver.txt_add(
+                    format!("{key}."),
+                    DomainKey::parse(value).unwrap(),
+                    Instant::now() + Duration::new(3200, 0),
+                );
+            }
+            let raw_message = raw_message.replace('\n', "\r\n");
+            let expected = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+            for chunk_size in [1, 7, 64, raw_message.len()] {
+                let mut verifier = DkimVerifier::new();
+                for chunk in raw_message.as_bytes().chunks(chunk_size) {
+                    verifier.write(chunk);
+                }
+                let message = verifier.finish().unwrap();
+                assert_eq!(message.body_hashes, expected.body_hashes);
+
+                let dkim = resolver
+                    .verify_dkim_(&message, 1667843664, None, &DkimOptions::default())
+                    .await;
+                assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_stream_body() {
+        let raw_message = concat!(
+            "From: jdoe@example.org\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; s=default; d=example.org; ",
+            "c=relaxed/simple; h=From; bh=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=; ",
+            "b=dGVzdA==\r\n",
+            "\r\n",
+            "Hello world!\r\n\r\n",
+        );
+        let expected = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let (headers, body) = raw_message.split_once("\r\n\r\n").unwrap();
+
+        // Headers lacking the empty line separator
+        let mut verifier = DkimVerifier::new();
+        for line in headers.split_inclusive("\r\n") {
+            verifier.write(line.as_bytes());
+        }
+        verifier.write_body(body.as_bytes());
+        let message = verifier.finish().unwrap();
+        assert_eq!(message.body_hashes, expected.body_hashes);
+        assert_eq!(message.dkim_headers.len(), 1);
+        assert_eq!(message.from, vec!["jdoe@example.org".to_string()]);
+
+        // Empty message
+        assert!(DkimVerifier::new().finish().is_none());
+    }
+}
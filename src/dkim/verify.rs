@@ -8,21 +8,26 @@
  * except according to those terms.
  */
 
-use std::time::SystemTime;
+use std::sync::Arc;
+
+use futures_util::{stream, StreamExt};
+use hickory_resolver::proto::op::ResponseCode;
+use mail_builder::encoders::base64::base64_encode;
 
 use crate::{
     common::{
-        base32::Base32Writer,
-        headers::Writer,
+        address::split_address,
+        headers::Writable,
+        metrics::Metrics,
+        resolver::MAX_CONCURRENT_LOOKUPS,
         verify::{DomainKey, VerifySignature},
     },
     is_within_pct, AuthenticatedMessage, DkimOutput, DkimResult, Error, Resolver,
 };
 
-use super::{
-    Atps, DomainKeyReport, Flag, HashAlgorithm, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER,
-    RR_SIGNATURE, RR_VERIFICATION,
-};
+use super::{Atps, Canonicalization, DkimOptions, DomainKeyReport, Flag, HashAlgorithm, Signature};
+
+type KeyResolver<'x> = dyn Fn(&str, &str) -> Option<DomainKey> + Sync + 'x;
 
 impl Resolver {
     /// Verifies DKIM headers of an RFC5322 message.
@@ -31,26 +36,89 @@ impl Resolver {
         &self,
         message: &'x AuthenticatedMessage<'x>,
     ) -> Vec<DkimOutput<'x>> {
-        self.verify_dkim_(
-            message,
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+        self.verify_dkim_with_opts(message, &DkimOptions::default())
+            .await
+    }
+
+    /// Verifies DKIM headers of an RFC5322 message using the given
+    /// [`DkimOptions`].
+    pub async fn verify_dkim_with_opts<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        opts: &DkimOptions,
+    ) -> Vec<DkimOutput<'x>> {
+        self.verify_dkim_(message, self.unix_time(), None, opts)
+            .await
+    }
+
+    /// Verifies DKIM headers of an RFC5322 message, obtaining the public keys
+    /// from `key_resolver` instead of DNS. The closure receives the signing
+    /// domain and selector, which allows verifying archived messages against
+    /// the keys that were published when they were signed. ATPS and report
+    /// records are still looked up in DNS.
+    pub async fn verify_dkim_with_key_resolver<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        opts: &DkimOptions,
+        key_resolver: impl Fn(&str, &str) -> Option<DomainKey> + Sync,
+    ) -> Vec<DkimOutput<'x>> {
+        self.verify_dkim_(message, self.unix_time(), Some(&key_resolver), opts)
+            .await
+    }
+
+    /// Looks up the public keys of all the DKIM signatures of a message
+    /// concurrently, so that a subsequent [`Resolver::verify_dkim`] finds
+    /// them in the cache. Signatures that failed to parse or exceed
+    /// [`DkimOptions::max_signatures`] are skipped, and lookup failures are
+    /// ignored as they are reported again during verification.
+    pub async fn prefetch_dkim_keys(&self, message: &AuthenticatedMessage<'_>, opts: &DkimOptions) {
+        let mut key_names = Vec::with_capacity(message.dkim_headers.len());
+        for header in message.dkim_headers.iter().take(opts.max_signatures) {
+            if let Ok(signature) = &header.header {
+                let key_name = signature.domain_key();
+                if !key_names.contains(&key_name) {
+                    key_names.push(key_name);
+                }
+            }
+        }
+
+        stream::iter(
+            key_names
+                .into_iter()
+                .map(|key_name| async move { self.txt_lookup::<DomainKey>(key_name).await }),
         )
-        .await
+        .buffer_unordered(MAX_CONCURRENT_LOOKUPS)
+        .for_each(|_| async {})
+        .await;
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(signatures = message.dkim_headers.len()))
+    )]
     pub(crate) async fn verify_dkim_<'x>(
         &self,
         message: &'x AuthenticatedMessage<'x>,
         now: u64,
+        key_resolver: Option<&KeyResolver<'_>>,
+        opts: &DkimOptions,
     ) -> Vec<DkimOutput<'x>> {
+        let mut metrics = Metrics::new();
         let mut output = Vec::with_capacity(message.dkim_headers.len());
         let mut report_requested = false;
 
         // Validate DKIM headers
-        for header in &message.dkim_headers {
+        for (idx, header) in message.dkim_headers.iter().enumerate() {
+            // Skip signatures beyond the configured limit
+            if idx >= opts.max_signatures {
+                let skipped = DkimOutput::neutral(Error::SignatureLimitExceeded);
+                output.push(match &header.header {
+                    Ok(signature) => skipped.with_signature(signature),
+                    Err(_) => skipped,
+                });
+                continue;
+            }
+
             // Validate body hash
             let signature = match &header.header {
                 Ok(signature) => {
@@ -58,13 +126,31 @@ impl Resolver {
                         report_requested = true;
                     }
 
-                    if signature.x == 0 || (signature.x > signature.t && signature.x > now) {
-                        signature
-                    } else {
+                    if signature.x != 0
+                        && (signature.x <= signature.t
+                            || (signature.x <= now && !opts.ignore_expiration))
+                    {
                         output.push(
                             DkimOutput::neutral(Error::SignatureExpired).with_signature(signature),
                         );
                         continue;
+                    } else if opts
+                        .max_clock_skew
+                        .is_some_and(|skew| signature.t > now.saturating_add(skew.as_secs()))
+                    {
+                        output.push(
+                            DkimOutput::neutral(Error::SignatureTimeInFuture)
+                                .with_signature(signature),
+                        );
+                        continue;
+                    } else if !opts.algorithms.contains(&signature.a) {
+                        output.push(
+                            DkimOutput::neutral(Error::AlgorithmNotAllowed)
+                                .with_signature(signature),
+                        );
+                        continue;
+                    } else {
+                        signature
                     }
                 }
                 Err(err) => {
@@ -73,6 +159,16 @@ impl Resolver {
                 }
             };
 
+            // The signed body length cannot exceed the canonicalized body
+            if message
+                .body_len
+                .iter()
+                .any(|(cb, len)| cb == &signature.cb && signature.l > *len)
+            {
+                output.push(DkimOutput::fail(Error::BodyLengthExceeded).with_signature(signature));
+                continue;
+            }
+
             // Validate body hash
             let ha = HashAlgorithm::from(signature.a);
             let bh = &message
@@ -82,25 +178,62 @@ impl Resolver {
                 .unwrap()
                 .3;
 
-            if bh != &signature.bh {
+            if bh.len() != signature.bh.len() {
+                // A truncated or oversized body hash can never match
+                output.push(
+                    DkimOutput::neutral(Error::InvalidBodyHashLength).with_signature(signature),
+                );
+                continue;
+            } else if bh != &signature.bh {
                 output.push(
-                    DkimOutput::neutral(Error::FailedBodyHashMatch).with_signature(signature),
+                    DkimOutput::neutral(Error::body_hash_mismatch(&signature.bh, bh))
+                        .with_signature(signature),
                 );
                 continue;
             }
 
             // Obtain ._domainkey TXT record
-            let record = match self.txt_lookup::<DomainKey>(signature.domain_key()).await {
-                Ok(record) => record,
-                Err(err) => {
-                    output.push(DkimOutput::dns_error(err).with_signature(signature));
-                    continue;
+            let record = if let Some(key_resolver) = key_resolver {
+                match key_resolver(&signature.d, &signature.s) {
+                    Some(record) => Arc::new(record),
+                    None => {
+                        output.push(
+                            DkimOutput::perm_err(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+                                .with_signature(signature),
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                metrics.dns_lookup();
+                match self.txt_lookup::<DomainKey>(signature.domain_key()).await {
+                    Ok(record) => record,
+                    Err(err) => {
+                        output.push(DkimOutput::dns_error(err).with_signature(signature));
+                        continue;
+                    }
                 }
             };
 
+            let key_record = record.record.as_deref().filter(|_| opts.key_records);
+
+            // Signature algorithm must match the key type
+            if !record.matches_algorithm(signature.a) {
+                output.push(
+                    DkimOutput::perm_err(Error::AlgorithmMismatch)
+                        .with_key_record(key_record)
+                        .with_signature(signature),
+                );
+                continue;
+            }
+
             // Enforce t=s flag
             if !signature.validate_auid(&record) {
-                output.push(DkimOutput::fail(Error::FailedAuidMatch).with_signature(signature));
+                output.push(
+                    DkimOutput::fail(Error::FailedAuidMatch)
+                        .with_key_record(key_record)
+                        .with_signature(signature),
+                );
                 continue;
             }
 
@@ -110,46 +243,42 @@ impl Resolver {
 
             // Verify signature
             if let Err(err) = record.verify(&mut headers, signature, signature.ch) {
-                output.push(DkimOutput::fail(err).with_signature(signature));
+                output.push(
+                    DkimOutput::fail(err)
+                        .with_key_record(key_record)
+                        .with_signature(signature),
+                );
                 continue;
             }
 
             // Verify third-party signature, if any.
             if let Some(atps) = &signature.atps {
-                let mut found = false;
+                let atps = atps.trim_end_matches('.');
                 // RFC5322.From has to match atps=
-                for from in &message.from {
-                    if let Some((_, domain)) = from.rsplit_once('@') {
-                        if domain.eq(atps) {
-                            found = true;
-                            break;
-                        }
-                    }
-                }
-
-                if found {
-                    let mut query_domain = match &signature.atpsh {
-                        Some(algorithm) => {
-                            let mut writer = Base32Writer::with_capacity(40);
-                            let output = algorithm.hash(signature.d.as_bytes());
-                            writer.write(output.as_ref());
-                            writer.finalize()
-                        }
-                        None => signature.d.to_string(),
-                    };
-                    query_domain.push_str("._atps.");
-                    query_domain.push_str(atps);
-                    query_domain.push('.');
+                let found = message.from.iter().any(|from| {
+                    split_address(from).is_some_and(|(_, domain)| domain.eq_ignore_ascii_case(atps))
+                });
 
+                if let Some(query_domain) = signature.atps_dns_name().filter(|_| found) {
+                    metrics.dns_lookup();
                     match self.txt_lookup::<Atps>(query_domain).await {
                         Ok(_) => {
                             // ATPS Verification successful
-                            output.push(DkimOutput::pass().with_atps().with_signature(signature));
+                            output.push(
+                                DkimOutput::pass()
+                                    .with_atps()
+                                    .with_key_record(key_record)
+                                    .with_signature(signature),
+                            );
+                            if opts.mode.is_final(signature) {
+                                break;
+                            }
                         }
                         Err(err) => {
                             output.push(
                                 DkimOutput::dns_error(err)
                                     .with_atps()
+                                    .with_key_record(key_record)
                                     .with_signature(signature),
                             );
                         }
@@ -159,7 +288,38 @@ impl Resolver {
             }
 
             // Verification successful
-            output.push(DkimOutput::pass().with_signature(signature));
+            output.push(
+                DkimOutput::pass()
+                    .with_key_record(key_record)
+                    .with_signature(signature),
+            );
+            if opts.mode.is_final(signature) {
+                break;
+            }
+        }
+
+        // Flag signatures that were verified past their expiration
+        if opts.ignore_expiration {
+            for dkim in &mut output {
+                if dkim
+                    .signature
+                    .is_some_and(|signature| signature.x != 0 && signature.x <= now)
+                {
+                    dkim.expired_at_verification = true;
+                }
+            }
+        }
+
+        // Keep the canonical form of failed signatures for debugging
+        if opts.debug {
+            for dkim in &mut output {
+                if let Some(signature) = dkim
+                    .signature
+                    .filter(|_| dkim.result != DkimResult::Pass && !dkim.is_skipped())
+                {
+                    dkim.canonical_form = Box::new(message.canonical_form(signature)).into();
+                }
+            }
         }
 
         // Handle reports
@@ -167,7 +327,7 @@ impl Resolver {
             for dkim in &mut output {
                 // Process signatures with errors that requested reports
                 let signature = if let Some(signature) = &dkim.signature {
-                    if signature.r && dkim.result != DkimResult::Pass {
+                    if signature.r && dkim.result != DkimResult::Pass && !dkim.is_skipped() {
                         signature
                     } else {
                         continue;
@@ -177,8 +337,9 @@ impl Resolver {
                 };
 
                 // Obtain ._domainkey TXT record
+                metrics.dns_lookup();
                 let record = if let Ok(record) = self
-                    .txt_lookup::<DomainKeyReport>(format!("_report._domainkey.{}.", signature.d))
+                    .txt_lookup::<DomainKeyReport>(signature.report_dns_name())
                     .await
                 {
                     if is_within_pct(record.rp) {
@@ -195,47 +356,25 @@ impl Resolver {
                     DkimResult::Neutral(err)
                     | DkimResult::Fail(err)
                     | DkimResult::PermError(err)
-                    | DkimResult::TempError(err) => {
-                        let send_report = match err {
-                            Error::CryptoError(_)
-                            | Error::Io(_)
-                            | Error::FailedVerification
-                            | Error::FailedBodyHashMatch
-                            | Error::FailedAuidMatch => (record.rr & RR_VERIFICATION) != 0,
-                            Error::Base64
-                            | Error::UnsupportedVersion
-                            | Error::UnsupportedAlgorithm
-                            | Error::UnsupportedCanonicalization
-                            | Error::UnsupportedKeyType
-                            | Error::IncompatibleAlgorithms => (record.rr & RR_SIGNATURE) != 0,
-                            Error::SignatureExpired => (record.rr & RR_EXPIRATION) != 0,
-                            Error::DnsError(_)
-                            | Error::DnsRecordNotFound(_)
-                            | Error::InvalidRecordType
-                            | Error::ParseError
-                            | Error::RevokedPublicKey => (record.rr & RR_DNS) != 0,
-                            Error::MissingParameters
-                            | Error::NoHeadersFound
-                            | Error::ArcChainTooLong
-                            | Error::ArcInvalidInstance(_)
-                            | Error::ArcInvalidCV
-                            | Error::ArcHasHeaderTag
-                            | Error::ArcBrokenChain
-                            | Error::SignatureLength
-                            | Error::NotAligned => (record.rr & RR_OTHER) != 0,
-                        };
-
-                        if send_report {
-                            format!("{}@{}", record.ra, signature.d).into()
-                        } else {
-                            None
-                        }
+                    | DkimResult::TempError(err)
+                        if record.wants_report(err) =>
+                    {
+                        record.address(&signature.d).into()
                     }
-                    DkimResult::None | DkimResult::Pass => None,
+                    _ => None,
                 };
             }
         }
 
+        metrics.finish(
+            "dkim",
+            output
+                .iter()
+                .find(|o| o.result == DkimResult::Pass)
+                .or_else(|| output.first())
+                .map_or(DkimResult::None.as_keyword(), |o| o.result.as_keyword()),
+        );
+
         output
     }
 }
@@ -245,6 +384,7 @@ impl<'x> AuthenticatedMessage<'x> {
         // Based on verify_dkim_ function
         // Iterate through possible DKIM headers
         let mut data = Vec::with_capacity(256);
+        let mut last_err = Error::NoHeadersFound;
         for header in &self.dkim_headers {
             // Ensure signature is not obviously invalid
             let signature = match &header.header {
@@ -252,14 +392,29 @@ impl<'x> AuthenticatedMessage<'x> {
                     if signature.x == 0 || (signature.x > signature.t) {
                         signature
                     } else {
+                        last_err = Error::SignatureExpired;
                         continue;
                     }
                 }
-                Err(_err) => {
+                Err(err) => {
+                    last_err = err.clone();
                     continue;
                 }
             };
 
+            // Ensure the body hash matches
+            let ha = HashAlgorithm::from(signature.a);
+            if let Some((_, _, _, bh)) = self
+                .body_hashes
+                .iter()
+                .find(|(c, h, l, _)| c == &signature.cb && h == &ha && l == &signature.l)
+            {
+                if bh != &signature.bh {
+                    last_err = Error::body_hash_mismatch(&signature.bh, bh);
+                    continue;
+                }
+            }
+
             // Get pre-hashed but canonically ordered headers, who's hash is signed
             let dkim_hdr_value = header.value.strip_signature();
             let headers = self.signed_headers(&signature.h, header.name, &dkim_hdr_value);
@@ -268,7 +423,107 @@ impl<'x> AuthenticatedMessage<'x> {
             return Ok(data);
         }
         // Return not ok
-        Err(Error::FailedBodyHashMatch)
+        Err(last_err)
+    }
+
+    /// Diagnoses canonicalization disagreements between signer and verifier by
+    /// returning the `(header, body)` canonicalization pairs under which the
+    /// signature, as parsed from this message, would have matched regardless
+    /// of its `c=` tag. Without a public key only the body hash can be
+    /// checked, so both header canonicalizations are returned for each
+    /// matching body hash. No DNS lookups are performed.
+    pub fn canonicalization_matches(
+        &self,
+        signature: &Signature,
+        key: Option<&DomainKey>,
+    ) -> Vec<(Canonicalization, Canonicalization)> {
+        let header = self
+            .dkim_headers
+            .iter()
+            .find(|header| header.header.as_ref().is_ok_and(|s| s == signature));
+        let dkim_hdr_value = header.map(|header| header.value.strip_signature());
+        let ha = HashAlgorithm::from(signature.a);
+        let mut matches = Vec::new();
+
+        for cb in [Canonicalization::Relaxed, Canonicalization::Simple] {
+            let bh = ha.hash(cb.canonical_body(self.raw_body(), signature.l));
+            if bh.as_ref() != signature.bh {
+                continue;
+            }
+
+            for ch in [Canonicalization::Relaxed, Canonicalization::Simple] {
+                let is_match = match (key, header, &dkim_hdr_value) {
+                    (Some(key), Some(header), Some(dkim_hdr_value)) => {
+                        let mut headers =
+                            self.signed_headers(&signature.h, header.name, dkim_hdr_value);
+                        key.verify(&mut headers, signature, ch).is_ok()
+                    }
+                    (Some(_), _, _) => false,
+                    (None, _, _) => true,
+                };
+                if is_match {
+                    matches.push((ch, cb));
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// Returns whether the body of this message matches the `bh=` tag of
+    /// `signature`, canonicalized and hashed according to the signature's
+    /// `c=`, `a=` and `l=` tags. The signature over the headers is not
+    /// verified, which tells a modified body apart from a wrong key or
+    /// modified headers. No DNS lookups are performed.
+    pub fn verify_body_hash(&self, signature: &Signature) -> bool {
+        HashAlgorithm::from(signature.a)
+            .hash(signature.cb.canonical_body(self.raw_body(), signature.l))
+            .as_ref()
+            == signature.bh
+    }
+
+    /// Returns the canonicalized header block and body exactly as they are
+    /// hashed when verifying `signature`, following its `h=`, `c=` and `l=`
+    /// tags. The header block ends with the signature's own DKIM-Signature
+    /// header with the `b=` value removed; if the signature was not parsed
+    /// from this message, that header is serialized from `signature` the way
+    /// a signer would. No DNS lookups are performed.
+    ///
+    /// Messages returned by [`DkimVerifier::finish`](crate::dkim::DkimVerifier::finish)
+    /// do not retain their body, so the canonicalized body is empty for them.
+    pub fn canonical_form(&self, signature: &Signature) -> (Vec<u8>, Vec<u8>) {
+        let mut headers = Vec::with_capacity(256);
+        if let Some(header) = self
+            .dkim_headers
+            .iter()
+            .find(|header| header.header.as_ref().is_ok_and(|s| s == signature))
+        {
+            let dkim_hdr_value = header.value.strip_signature();
+            signature.ch.canonicalize_headers(
+                self.signed_headers(&signature.h, header.name, &dkim_hdr_value),
+                &mut headers,
+            );
+        } else {
+            // Drop the empty placeholder for the DKIM-Signature header
+            let mut signed_headers = self
+                .signed_headers(&signature.h, b"", b"")
+                .collect::<Vec<_>>();
+            signed_headers.pop();
+            signature
+                .ch
+                .canonicalize_headers(signed_headers.into_iter(), &mut headers);
+            let mut unsigned = signature.clone();
+            unsigned.b.clear();
+            unsigned.write(&mut headers, false);
+        }
+
+        let mut body = Vec::with_capacity(self.raw_body().len());
+        signature
+            .cb
+            .canonical_body(self.raw_body(), signature.l)
+            .write(&mut body);
+
+        (headers, body)
     }
 
     pub fn signed_headers<'z: 'x>(
@@ -309,6 +564,19 @@ impl<'x> AuthenticatedMessage<'x> {
     }
 }
 
+impl Error {
+    /// Reports a body hash mismatch with both hashes encoded in base64.
+    pub(crate) fn body_hash_mismatch(expected: &[u8], computed: &[u8]) -> Self {
+        let encode = |hash: &[u8]| {
+            String::from_utf8(base64_encode(hash).unwrap_or_default()).unwrap_or_default()
+        };
+        Error::BodyHashMismatch {
+            expected: encode(expected),
+            computed: encode(computed),
+        }
+    }
+}
+
 impl Signature {
     #[allow(clippy::while_let_on_iterator)]
     pub(crate) fn validate_auid(&self, record: &DomainKey) -> bool {
@@ -393,9 +661,16 @@ mod test {
     };
 
     use crate::{
-        common::{parse::TxtRecordParser, verify::DomainKey},
-        dkim::verify::Verifier,
-        AuthenticatedMessage, DkimResult, Resolver,
+        common::{
+            crypto::Algorithm,
+            lru::DnsCache,
+            parse::TxtRecordParser,
+            resolver::DnsAnswers,
+            retry::DnsRetryPolicy,
+            verify::{DomainKey, VerifySignature},
+        },
+        dkim::{verify::Verifier, DkimOptions, DkimVerifier, Signature},
+        AuthenticatedMessage, DkimResult, Error, Resolver, Txt,
     };
 
     #[tokio::test]
@@ -417,9 +692,275 @@ mod test {
             let raw_message = raw_message.replace('\n', "\r\n");
             let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
 
-            let dkim = resolver.verify_dkim_(&message, 1667843664).await;
+            let dkim = resolver
+                .verify_dkim_(&message, 1667843664, None, &DkimOptions::default())
+                .await;
 
             assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+
+            // Headers and body stored separately, with and without the separator
+            let raw_message = raw_message.as_bytes();
+            let body_offset = message.body_offset() - 2;
+            for headers in [&raw_message[..body_offset], &raw_message[..body_offset + 2]] {
+                let parts =
+                    AuthenticatedMessage::from_parts(headers, &raw_message[body_offset + 2..])
+                        .unwrap();
+                assert_eq!(parts.body_hashes, message.body_hashes);
+                assert_eq!(parts.raw_body(), message.raw_body());
+
+                let dkim = resolver
+                    .verify_dkim_(&parts, 1667843664, None, &DkimOptions::default())
+                    .await;
+                assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_allowed_algorithms() {
+        let raw_message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha1; s=default; d=example.org; ",
+            "h=From; bh=2jmj7l5rSw0yVb/vlWAYkK/YBwk=; b=dGVzdA==\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; s=default; d=example.org; ",
+            "h=From; bh=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=; b=dGVzdA==\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+            "Hello world!\r\n",
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+        // All supported algorithms are allowed by default
+        let dkim = Resolver::new_system_conf()
+            .unwrap()
+            .verify_dkim(&message)
+            .await;
+        assert_eq!(dkim.len(), 2);
+        assert!(dkim.iter().all(|output| !output.is_skipped()));
+
+        // rsa-sha1 is rejected before any verification takes place
+        let opts =
+            DkimOptions::new().allowed_algorithms([Algorithm::RsaSha256, Algorithm::Ed25519Sha256]);
+        let dkim = Resolver::new_system_conf()
+            .unwrap()
+            .verify_dkim_with_opts(&message, &opts)
+            .await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Neutral(Error::AlgorithmNotAllowed)
+        );
+        assert!(dkim[0].is_skipped());
+        assert!(matches!(
+            dkim[1].result(),
+            DkimResult::Neutral(Error::BodyHashMismatch { .. })
+        ));
+        assert!(!dkim[1].is_skipped());
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_body_hash_length() {
+        let mut raw_message = String::new();
+        for (algorithm, bh) in [
+            ("rsa-sha256", "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="),
+            ("rsa-sha1", "2jmj7l5rSw0yVb/vlWAYkK/YBwk="),
+            // Truncated SHA-256 digest
+            ("rsa-sha256", "47DEQpj8HBSa+/TImW+5JCeuQeRk"),
+            ("ed25519-sha256", "2jmj7l5rSw0yVb/vlWAYkK/YBwk="),
+            // SHA-256 digest declared as SHA-1
+            ("rsa-sha1", "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="),
+        ] {
+            raw_message.push_str(&format!(
+                "DKIM-Signature: v=1; a={algorithm}; s=default; d=example.org; c=relaxed/relaxed; h=From; bh={bh}; b=dGVzdA==\r\n"
+            ));
+        }
+        raw_message.push_str("From: jdoe@example.org\r\n\r\n");
+
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let dkim = Resolver::new_system_conf()
+            .unwrap()
+            .verify_dkim(&message)
+            .await;
+        assert_eq!(
+            dkim.iter()
+                .map(|o| o.result() == &DkimResult::Neutral(Error::InvalidBodyHashLength))
+                .collect::<Vec<_>>(),
+            [false, false, true, true, true]
+        );
+        assert!(dkim[..2]
+            .iter()
+            .all(|o| !matches!(o.result(), DkimResult::Neutral(_))));
+    }
+
+    #[tokio::test]
+    async fn dkim_canonicalized_header_errors() {
+        let message = AuthenticatedMessage::parse(b"From: jdoe@example.org\r\n\r\n").unwrap();
+        assert_eq!(
+            message.get_canonicalized_header().await,
+            Err(Error::NoHeadersFound)
+        );
+
+        let raw_message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; s=default; d=example.org; ",
+            "c=relaxed/relaxed; h=From; ",
+            "bh=QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=; b=dGVzdA==\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        assert_eq!(
+            message.get_canonicalized_header().await,
+            Err(Error::BodyHashMismatch {
+                expected: "QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=".to_string(),
+                computed: "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string(),
+            })
+        );
+
+        let raw_message = raw_message.replace(
+            "QoiUNYyUV+1tZ/xUPRcE+gST2zAStvJx1OK078Ylm5s=",
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        assert!(message
+            .get_canonicalized_header()
+            .await
+            .unwrap()
+            .starts_with(b"from:jdoe@example.org\r\ndkim-signature:"));
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_body_length_exceeded() {
+        let raw_message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; s=default; d=example.org; ",
+            "c=relaxed/relaxed; h=From; l=1000000; ",
+            "bh=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=; b=dGVzdA==\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+            "Hi there\r\n",
+        );
+        let resolver = Resolver::new_system_conf().unwrap();
+
+        let message = AuthenticatedMessage::parse_with_opts(raw_message.as_bytes(), false).unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Fail(Error::BodyLengthExceeded)
+        );
+
+        let mut verifier = DkimVerifier::new().with_strict(false);
+        verifier.write(raw_message.as_bytes());
+        let message = verifier.finish().unwrap();
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Fail(Error::BodyLengthExceeded)
+        );
+    }
+
+    #[tokio::test]
+    async fn dkim_prefetch_keys() {
+        let raw_message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; s=default; d=_dns_error.example.org; ",
+            "c=relaxed/relaxed; h=From; bh=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=; ",
+            "b=dGVzdA==\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let new_resolver = || {
+            Resolver::new_system_conf().unwrap().with_dns_retry_policy(
+                DnsRetryPolicy::new().with_circuit_breaker(1, Duration::from_secs(60)),
+            )
+        };
+
+        // Without prefetching, the key is looked up during verification
+        let dkim = new_resolver().verify_dkim(&message).await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::TempError(Error::DnsError("".to_string()))
+        );
+
+        // Prefetching already looked the key up, so its failure has been
+        // recorded and further lookups are suspended by the time it is verified
+        let resolver = new_resolver();
+        resolver
+            .prefetch_dkim_keys(&message, &DkimOptions::default())
+            .await;
+        let dkim = resolver.verify_dkim(&message).await;
+        assert!(matches!(
+            dkim[0].result(),
+            DkimResult::TempError(Error::DnsError(err)) if err.contains("suspended")
+        ));
+
+        // Prefetched keys are cached, so verifying needs no further lookups
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("dkim");
+        test_file.push("001.txt");
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+        let answers = dns_records
+            .split('\n')
+            .filter_map(|r| r.split_once(' '))
+            .fold(DnsAnswers::new(), |answers, (name, value)| {
+                answers.with_txt(name, value)
+            });
+        let raw_message = raw_message.replace('\n', "\r\n");
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_min_ttl(Duration::from_secs(3600))
+            .with_dns_answers(answers);
+        resolver
+            .prefetch_dkim_keys(&message, &DkimOptions::default())
+            .await;
+        for header in &message.dkim_headers {
+            let key_name = header.header.as_ref().unwrap().domain_key();
+            assert!(
+                matches!(
+                    resolver.cache_txt.get(&key_name, Instant::now()),
+                    Some(Txt::DomainKey(_))
+                ),
+                "{key_name}"
+            );
+        }
+
+        // Without DNS answers any lookup would fail
+        let mut resolver = resolver.clone();
+        resolver.answers = None;
+        let dkim = resolver
+            .verify_dkim_(&message, 1667843664, None, &DkimOptions::default())
+            .await;
+        assert_eq!(dkim.len(), message.dkim_headers.len());
+        assert!(dkim.iter().all(|o| o.result() == &DkimResult::Pass));
+    }
+
+    #[test]
+    fn dkim_validate_auid() {
+        let key = "k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+        let strict = DomainKey::parse(format!("v=DKIM1; t=s; {key}").as_bytes()).unwrap();
+        let relaxed = DomainKey::parse(format!("v=DKIM1; {key}").as_bytes()).unwrap();
+
+        for (auid, expected_strict) in [
+            ("", true),
+            ("jdoe@example.org", true),
+            ("jo=2Ehn@example=2Eorg", true),
+            ("=40example=2Eorg", true),
+            ("jdoe@mail.example.org", false),
+            ("jdoe@mail=2Eexample.org", false),
+            ("jdoe@example", false),
+            ("jdoe@example%2Eorg", true),
+            ("jo%2Ehn@Example.ORG", true),
+            ("jdoe@mail%2Eexample.org", false),
+        ] {
+            let signature = Signature::parse(
+                format!(
+                    "v=1; a=rsa-sha256; s=default; d=example.org; i={auid}; h=From; bh={}; b=dGVzdA==",
+                    "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+            assert_eq!(signature.validate_auid(&strict), expected_strict, "{auid}");
+            assert!(signature.validate_auid(&relaxed), "{auid}");
         }
     }
 
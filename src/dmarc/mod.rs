@@ -8,11 +8,14 @@
  * except according to those terms.
  */
 
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, net::IpAddr, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{DmarcOutput, DmarcResult, Error, Version};
+use crate::{
+    report::PolicyOverrideReason, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error,
+    SpfOutput, SpfResult, Version,
+};
 
 pub mod parse;
 pub mod verify;
@@ -33,6 +36,8 @@ pub struct Dmarc {
     pub ruf: Vec<URI>,
     pub sp: Policy,
     pub t: bool,
+    pub unknown_tags: Vec<String>,
+    pub unsupported_rf: Vec<String>,
 }
 
 #[derive(Debug, Hash, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -42,7 +47,7 @@ pub struct URI {
     pub max_size: usize,
 }
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
 pub enum Alignment {
     Relaxed,
     Strict,
@@ -72,9 +77,51 @@ pub enum Policy {
     Unspecified,
 }
 
+/// Local policy override of the DMARC disposition, reported in
+/// aggregate reports as a `<reason>` of the evaluated policy.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    pub disposition: Policy,
+    pub reason: PolicyOverrideReason,
+}
+
+/// Combined assessment of the SPF, DKIM and DMARC results of a message,
+/// as returned by [`DmarcOutput::assess`]. Variants are ordered from the
+/// weakest to the strongest authentication, so assessments can be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Assessment {
+    /// DMARC failed and the domain requests rejection.
+    DmarcFailReject,
+    /// DMARC failed and the domain requests quarantine.
+    DmarcFailQuarantine,
+    /// DMARC failed and the domain requests no action, or is testing (`t=y`).
+    DmarcFailNone,
+    /// No mechanism passed and the domain publishes no DMARC policy.
+    None,
+    /// A temporary error prevented a conclusive result.
+    TempError,
+    /// SPF passed for a domain without a DMARC policy.
+    SpfPass,
+    /// DKIM passed for a domain without a DMARC policy.
+    DkimPass,
+    /// DMARC passed through an aligned SPF identifier only.
+    AlignedSpfPass,
+    /// DMARC passed through an aligned DKIM signature.
+    AlignedDkimPass,
+}
+
+/// Options controlling how the DMARC policy is evaluated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DmarcOptions {
+    pub(crate) require_dkim: bool,
+    pub(crate) reject_multiple_from: bool,
+    pub(crate) trusted_forwarders: Vec<(IpAddr, u8)>,
+    pub(crate) remote_ip: Option<IpAddr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
-pub(crate) enum Format {
+pub enum Format {
     Afrf = 1,
 }
 
@@ -120,6 +167,90 @@ impl Default for DmarcOutput {
             record: None,
             spf_result: DmarcResult::None,
             dkim_result: DmarcResult::None,
+            policy_override: None,
+            dkim_required: false,
+            record_error: None,
+            from_count: 0,
+            forwarder_exempt: false,
+            sampled_out: false,
+            spf_aligned: None,
+            dkim_aligned: None,
+        }
+    }
+}
+
+impl DmarcOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When enabled, DMARC only passes on an aligned DKIM signature and
+    /// SPF alignment alone is not sufficient. The SPF alignment result is
+    /// still reported.
+    pub fn require_dkim(mut self, require_dkim: bool) -> Self {
+        self.require_dkim = require_dkim;
+        self
+    }
+
+    /// When enabled, messages with more than one From header fail DMARC
+    /// with [`Error::MultipleFromHeaders`], so that the published policy is
+    /// applied instead of exempting or evaluating them. Domains without a
+    /// DMARC record are not affected, as there is no policy to apply.
+    pub fn reject_multiple_from(mut self, reject_multiple_from: bool) -> Self {
+        self.reject_multiple_from = reject_multiple_from;
+        self
+    }
+
+    /// Adds the network `addr/prefix_len` to the list of trusted forwarders.
+    /// When the connecting IP set with [`DmarcOptions::remote_ip`] belongs
+    /// to a trusted forwarder, a failed SPF alignment is not held against the
+    /// message: the DMARC SPF result is reset to `none`, so the verdict falls
+    /// back to DKIM alignment and the policy applies if that fails too. The
+    /// exemption is recorded in [`DmarcOutput::is_forwarder_exempt`] and
+    /// reported in aggregate reports with a `forwarded` reason.
+    pub fn trusted_forwarder(mut self, addr: IpAddr, prefix_len: u8) -> Self {
+        self.trusted_forwarders.push((addr, prefix_len));
+        self
+    }
+
+    /// Sets the IP address of the client that delivered the message.
+    pub fn remote_ip(mut self, remote_ip: IpAddr) -> Self {
+        self.remote_ip = remote_ip.into();
+        self
+    }
+
+    /// Returns `true` if `ip` belongs to one of the trusted forwarders.
+    /// IPv4-mapped IPv6 addresses are matched as IPv4.
+    pub fn is_trusted_forwarder(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+            ip => ip,
+        };
+        self.trusted_forwarders
+            .iter()
+            .any(|(addr, prefix_len)| match (addr, ip) {
+                (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                    let mask = u32::MAX
+                        .checked_shl(32u32.saturating_sub(*prefix_len as u32))
+                        .unwrap_or(0);
+                    u32::from(*addr) & mask == u32::from(ip) & mask
+                }
+                (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                    let mask = u128::MAX
+                        .checked_shl(128u32.saturating_sub(*prefix_len as u32))
+                        .unwrap_or(0);
+                    u128::from(*addr) & mask == u128::from(ip) & mask
+                }
+                _ => false,
+            })
+    }
+}
+
+impl Override {
+    pub fn new(disposition: Policy, reason: PolicyOverrideReason) -> Self {
+        Override {
+            disposition,
+            reason,
         }
     }
 }
@@ -145,6 +276,11 @@ impl DmarcOutput {
         self
     }
 
+    pub(crate) fn with_record_error(mut self, err: Error) -> Self {
+        self.record_error = err.into();
+        self
+    }
+
     pub fn domain(&self) -> &str {
         &self.domain
     }
@@ -157,6 +293,16 @@ impl DmarcOutput {
         self.policy
     }
 
+    /// Returns the policy to enforce, which is `none` when the record
+    /// is in testing mode (`t=y`) regardless of the requested policy.
+    pub fn effective_policy(&self) -> Policy {
+        if self.record.as_ref().is_some_and(|r| r.t) {
+            Policy::None
+        } else {
+            self.policy
+        }
+    }
+
     pub fn dkim_result(&self) -> &DmarcResult {
         &self.dkim_result
     }
@@ -165,6 +311,165 @@ impl DmarcOutput {
         &self.spf_result
     }
 
+    /// Returns how the SPF authenticated domain aligned with the `From`
+    /// domain: [`Alignment::Strict`] if both are identical,
+    /// [`Alignment::Relaxed`] if they only share the organizational domain,
+    /// or `None` if SPF did not pass or the domains are not aligned under
+    /// the record's `aspf=` mode.
+    ///
+    /// This is the kind of match that was found, not the `aspf=` mode of the
+    /// record: identical domains report [`Alignment::Strict`] even when the
+    /// record requests relaxed alignment, see [`Dmarc::spf_alignment`].
+    pub fn spf_aligned(&self) -> Option<Alignment> {
+        self.spf_aligned
+    }
+
+    /// Returns how the domain of the best passing DKIM signature aligned with
+    /// the `From` domain: [`Alignment::Strict`] if both are identical,
+    /// [`Alignment::Relaxed`] if they only share the organizational domain,
+    /// or `None` if no passing signature is aligned under the record's
+    /// `adkim=` mode.
+    ///
+    /// This is the kind of match that was found, not the `adkim=` mode of the
+    /// record: identical domains report [`Alignment::Strict`] even when the
+    /// record requests relaxed alignment, see [`Dmarc::dkim_alignment`].
+    pub fn dkim_aligned(&self) -> Option<Alignment> {
+        self.dkim_aligned
+    }
+
+    /// Returns `true` if the message passed DMARC, either by DKIM or,
+    /// unless an aligned DKIM pass was required, by SPF alignment.
+    pub fn is_pass(&self) -> bool {
+        self.dkim_result == DmarcResult::Pass
+            || (!self.dkim_required && self.spf_result == DmarcResult::Pass)
+    }
+
+    /// Returns the disposition applied to the message, as reported in the
+    /// `<disposition>` of aggregate reports. This is the disposition of the
+    /// local policy override if there is one, `none` if the message passed,
+    /// and otherwise the effective policy, relaxed by one step (`reject` to
+    /// `quarantine`, `quarantine` to `none`) when the message was excluded
+    /// from the policy by `pct=` sampling.
+    pub fn disposition(&self) -> Policy {
+        if let Some(policy_override) = &self.policy_override {
+            return match policy_override.disposition {
+                Policy::Unspecified => Policy::None,
+                disposition => disposition,
+            };
+        } else if self.is_pass() {
+            return Policy::None;
+        }
+
+        match (self.effective_policy(), self.sampled_out) {
+            (Policy::Reject, false) => Policy::Reject,
+            (Policy::Reject, true) | (Policy::Quarantine, false) => Policy::Quarantine,
+            _ => Policy::None,
+        }
+    }
+
+    /// Returns `true` if the message failed DMARC but was excluded from the
+    /// requested policy by `pct=` sampling (RFC 7489, Section 6.6.4).
+    pub fn is_sampled_out(&self) -> bool {
+        self.sampled_out
+    }
+
+    /// Returns `true` if the policy that applied to the `From` domain is
+    /// enforced, see [`Dmarc::is_enforced`].
+    pub fn is_enforced(&self) -> bool {
+        matches!(self.policy, Policy::Quarantine | Policy::Reject)
+            && self.record.as_ref().is_some_and(|r| r.pct > 0 && !r.t)
+    }
+
+    /// Returns `true` if the message meets the DMARC prerequisites for
+    /// displaying a BIMI indicator, which are:
+    ///
+    /// - The message passed DMARC with an aligned identifier.
+    /// - The DMARC record requests an enforcing policy (`quarantine` or
+    ///   `reject`) for both the domain (`p=`) and its subdomains (`sp=`),
+    ///   as does the policy that applied to the `From` domain.
+    /// - The policy applies to all messages (`pct=100`).
+    /// - The record is not in testing mode (`t=y`).
+    pub fn bimi_eligible(&self) -> bool {
+        let is_enforced = |policy: Policy| matches!(policy, Policy::Quarantine | Policy::Reject);
+        self.is_pass()
+            && is_enforced(self.policy)
+            && self
+                .record
+                .as_ref()
+                .is_some_and(|r| is_enforced(r.p) && is_enforced(r.sp) && r.pct == 100 && !r.t)
+    }
+
+    /// Combines this output with the SPF and DKIM results it was evaluated
+    /// from into a single [`Assessment`]. A DMARC failure is reported as a
+    /// temporary error when any of the underlying checks failed temporarily,
+    /// as the message may pass once retried.
+    pub fn assess(&self, spf_output: &SpfOutput, dkim_output: &[DkimOutput<'_>]) -> Assessment {
+        if self.is_pass() {
+            return if self.dkim_result == DmarcResult::Pass {
+                Assessment::AlignedDkimPass
+            } else {
+                Assessment::AlignedSpfPass
+            };
+        }
+
+        let is_temp_error = matches!(self.dkim_result, DmarcResult::TempError(_))
+            || matches!(self.spf_result, DmarcResult::TempError(_))
+            || spf_output.result == SpfResult::TempError
+            || dkim_output
+                .iter()
+                .any(|o| matches!(o.result, DkimResult::TempError(_)));
+
+        if self.record.is_some() {
+            if is_temp_error {
+                Assessment::TempError
+            } else {
+                match self.effective_policy() {
+                    Policy::Reject => Assessment::DmarcFailReject,
+                    Policy::Quarantine => Assessment::DmarcFailQuarantine,
+                    Policy::None | Policy::Unspecified => Assessment::DmarcFailNone,
+                }
+            }
+        } else if dkim_output.iter().any(|o| o.result == DkimResult::Pass) {
+            Assessment::DkimPass
+        } else if spf_output.result == SpfResult::Pass {
+            Assessment::SpfPass
+        } else if is_temp_error {
+            Assessment::TempError
+        } else {
+            Assessment::None
+        }
+    }
+
+    /// Returns `true` if an aligned DKIM pass was required for DMARC to pass.
+    pub fn is_dkim_required(&self) -> bool {
+        self.dkim_required
+    }
+
+    /// Returns `true` if the message was delivered by a trusted forwarder
+    /// and its failed SPF alignment was disregarded, see
+    /// [`DmarcOptions::trusted_forwarder`].
+    pub fn is_forwarder_exempt(&self) -> bool {
+        self.forwarder_exempt
+    }
+
+    /// Returns the local policy override applied, if any.
+    pub fn policy_override(&self) -> Option<&Override> {
+        self.policy_override.as_ref()
+    }
+
+    /// Returns the error found while parsing the DMARC record published for
+    /// the domain. Invalid records are treated as if no record was found.
+    pub fn record_error(&self) -> Option<&Error> {
+        self.record_error.as_ref()
+    }
+
+    /// Returns the number of addresses in the RFC5322.From header. Messages
+    /// with multiple From addresses are only evaluated when all of them
+    /// share the same domain, and may deserve extra scrutiny.
+    pub fn from_count(&self) -> usize {
+        self.from_count
+    }
+
     pub fn dmarc_record(&self) -> Option<&Dmarc> {
         self.record.as_deref()
     }
@@ -173,10 +478,22 @@ impl DmarcOutput {
         self.record.clone()
     }
 
+    /// Returns the aggregate report URIs (`rua=`) of the DMARC record,
+    /// or an empty list if no record was found.
+    pub fn rua(&self) -> &[URI] {
+        self.record.as_ref().map_or(&[], |r| r.rua.as_slice())
+    }
+
+    /// Returns the failure report URIs (`ruf=`) of the DMARC record,
+    /// or an empty list if no record was found.
+    pub fn ruf(&self) -> &[URI] {
+        self.record.as_ref().map_or(&[], |r| r.ruf.as_slice())
+    }
+
     pub fn requested_reports(&self) -> bool {
         self.record
             .as_ref()
-            .map_or(false, |r| !r.rua.is_empty() || !r.ruf.is_empty())
+            .is_some_and(|r| !r.rua.is_empty() || !r.ruf.is_empty())
     }
 
     /// Returns the failure reporting options
@@ -204,10 +521,68 @@ impl DmarcOutput {
 }
 
 impl Dmarc {
+    /// Returns the requested policy for the domain (`p=`).
+    pub fn policy(&self) -> Policy {
+        self.p
+    }
+
+    /// Returns the requested policy for subdomains (`sp=`), which
+    /// defaults to the domain policy.
+    pub fn subdomain_policy(&self) -> Policy {
+        self.sp
+    }
+
+    /// Returns the requested policy for non-existent subdomains (`np=`),
+    /// which defaults to the subdomain policy.
+    pub fn nonexistent_subdomain_policy(&self) -> Policy {
+        self.np
+    }
+
+    /// Returns the DKIM identifier alignment mode (`adkim=`).
+    pub fn dkim_alignment(&self) -> Alignment {
+        self.adkim
+    }
+
+    /// Returns the SPF identifier alignment mode (`aspf=`).
+    pub fn spf_alignment(&self) -> Alignment {
+        self.aspf
+    }
+
+    /// Returns the percentage of messages the policy applies to (`pct=`),
+    /// 100 when the tag is absent.
     pub fn pct(&self) -> u8 {
         self.pct
     }
 
+    /// Returns the names of the tags that were not recognized while parsing.
+    pub fn unknown_tags(&self) -> &[String] {
+        &self.unknown_tags
+    }
+
+    /// Returns the format to use for failure reports (`rf=`). Only AFRF is
+    /// supported, which is also used when the requested formats are unknown.
+    pub fn report_format(&self) -> Format {
+        Format::Afrf
+    }
+
+    /// Returns the report formats requested in `rf=` that are not supported.
+    pub fn unsupported_report_formats(&self) -> &[String] {
+        &self.unsupported_rf
+    }
+
+    /// Returns `true` if the domain policy (`p=`) is `quarantine` or `reject`
+    /// and applies to at least some messages. Records with `pct=0` or in
+    /// testing mode (`t=y`) are not enforced, as no failing message is
+    /// subject to the requested policy.
+    pub fn is_enforced(&self) -> bool {
+        matches!(self.p, Policy::Quarantine | Policy::Reject) && self.pct > 0 && !self.t
+    }
+
+    /// Returns `true` if the record is in testing mode (DMARCbis `t=y`).
+    pub fn is_testing(&self) -> bool {
+        self.t
+    }
+
     pub fn ruf(&self) -> &[URI] {
         &self.ruf
     }
@@ -226,3 +601,30 @@ impl Display for Policy {
         })
     }
 }
+
+/// Returns how `domain` aligns with the RFC5322.From domain under the given
+/// alignment mode, or `None` if it does not. Domains are compared without
+/// case or trailing dots. Under relaxed alignment a parent domain or a
+/// subdomain also aligns, as long as the parent is not a top-level domain.
+pub(crate) fn is_aligned(domain: &str, from_domain: &str, mode: Alignment) -> Option<Alignment> {
+    let domain = domain.trim_end_matches('.');
+    let from_domain = from_domain.trim_end_matches('.');
+    let is_subdomain = |child: &str, parent: &str| {
+        parent.contains('.')
+            && child.len() > parent.len() + 1
+            && child.as_bytes()[child.len() - parent.len() - 1] == b'.'
+            && child[child.len() - parent.len()..].eq_ignore_ascii_case(parent)
+    };
+
+    if domain.is_empty() {
+        None
+    } else if domain.eq_ignore_ascii_case(from_domain) {
+        Some(Alignment::Strict)
+    } else if mode == Alignment::Relaxed
+        && (is_subdomain(domain, from_domain) || is_subdomain(from_domain, domain))
+    {
+        Some(Alignment::Relaxed)
+    } else {
+        None
+    }
+}
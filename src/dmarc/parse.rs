@@ -13,7 +13,7 @@ use std::slice::Iter;
 use mail_parser::decoders::quoted_printable::quoted_printable_decode_char;
 
 use crate::{
-    common::parse::{ItemParser, TagParser, TxtRecordParser, N, T, V, Y},
+    common::parse::{tag_name, ItemParser, TagParser, TxtRecordParser, N, T, V, Y},
     Error, Version,
 };
 
@@ -44,7 +44,10 @@ impl TxtRecordParser for Dmarc {
             v: Version::V1,
             psd: Psd::Default,
             t: false,
+            unknown_tags: Vec::new(),
+            unsupported_rf: Vec::new(),
         };
+        let mut tag_start = record.as_slice();
 
         while let Some(key) = record.key() {
             match key {
@@ -64,10 +67,22 @@ impl TxtRecordParser for Dmarc {
                     dmarc.p = record.policy()?;
                 }
                 PCT => {
-                    dmarc.pct = std::cmp::min(100, record.number().ok_or(Error::ParseError)?) as u8;
+                    // Out of range values invalidate the record (RFC 7489, Section 6.3)
+                    dmarc.pct = match record.number() {
+                        Some(pct @ 0..=100) => pct as u8,
+                        _ => return Err(Error::ParseError),
+                    };
                 }
                 RF => {
-                    dmarc.rf = record.flags::<Format>() as u8;
+                    // Unsupported formats are recorded, falling back to AFRF
+                    let mut rf = 0;
+                    for format in record.items::<String>() {
+                        match Format::parse(format.as_bytes()) {
+                            Some(format) => rf |= u64::from(format),
+                            None => dmarc.unsupported_rf.push(format),
+                        }
+                    }
+                    dmarc.rf = if rf != 0 { rf } else { Format::Afrf.into() } as u8;
                 }
                 RI => {
                     dmarc.ri = record.number().ok_or(Error::ParseError)? as u32;
@@ -92,9 +107,13 @@ impl TxtRecordParser for Dmarc {
                     dmarc.t = record.value() == Y;
                 }
                 _ => {
+                    dmarc
+                        .unknown_tags
+                        .extend(tag_name(tag_start, record.as_slice()));
                     record.ignore();
                 }
             }
+            tag_start = record.as_slice();
         }
 
         if dmarc.sp == Policy::Unspecified {
@@ -161,6 +180,7 @@ impl DMARCParser for Iter<'_, u8> {
     }
 
     fn policy(&mut self) -> crate::Result<Policy> {
+        // Enumerated values are matched case-insensitively, "p=Reject" is "reject"
         let p = match self.next_skip_whitespaces().unwrap_or(0) {
             b'n' | b'N' if self.match_bytes(b"one") => Policy::None,
             b'q' | b'Q' if self.match_bytes(b"uarantine") => Policy::Quarantine,
@@ -180,6 +200,7 @@ impl DMARCParser for Iter<'_, u8> {
         let mut uri = Vec::with_capacity(16);
         let mut found_uri = false;
         let mut found_at = false;
+        let mut in_quote = false;
         let mut size: usize = 0;
 
         'outer: while let Some(&ch) = self.next() {
@@ -196,8 +217,13 @@ impl DMARCParser for Iter<'_, u8> {
                                             found_at = true;
                                             uri.push(ch);
                                         }
+                                        b'"' => {
+                                            in_quote = !in_quote;
+                                            uri.push(ch);
+                                        }
                                         _ => {
-                                            if !ch.is_ascii_whitespace() {
+                                            // Whitespace is only kept within quoted local parts
+                                            if in_quote || !ch.is_ascii_whitespace() {
                                                 uri.push(ch);
                                             }
                                         }
@@ -225,22 +251,6 @@ impl DMARCParser for Iter<'_, u8> {
                                     (size.saturating_mul(10)).saturating_add((ch - b'0') as usize);
                                 has_digits = true;
                             }
-                            b'k' | b'K' if !has_units && has_digits => {
-                                size = size.saturating_mul(1024);
-                                has_units = true;
-                            }
-                            b'm' | b'M' if !has_units && has_digits => {
-                                size = size.saturating_mul(1024 * 1024);
-                                has_units = true;
-                            }
-                            b'g' | b'G' if !has_units && has_digits => {
-                                size = size.saturating_mul(1024 * 1024 * 1024);
-                                has_units = true;
-                            }
-                            b't' | b'T' if !has_units && has_digits => {
-                                size = size.saturating_mul(1024 * 1024 * 1024 * 1024);
-                                has_units = true;
-                            }
                             b';' => {
                                 break 'outer;
                             }
@@ -254,16 +264,23 @@ impl DMARCParser for Iter<'_, u8> {
                                     }
                                     found_uri = false;
                                     found_at = false;
+                                    in_quote = false;
                                     uri.clear();
                                 }
                                 size = 0;
                                 break;
                             }
-                            _ => {
-                                if !ch.is_ascii_whitespace() {
-                                    return Err(Error::ParseError);
+                            _ => match size_unit(ch) {
+                                Some(unit) if !has_units && has_digits => {
+                                    size = size.saturating_mul(unit);
+                                    has_units = true;
                                 }
-                            }
+                                _ => {
+                                    if !ch.is_ascii_whitespace() {
+                                        return Err(Error::ParseError);
+                                    }
+                                }
+                            },
                         }
                     }
                 }
@@ -277,6 +294,7 @@ impl DMARCParser for Iter<'_, u8> {
                         }
                         found_uri = false;
                         found_at = false;
+                        in_quote = false;
                         uri.clear();
                     }
                     size = 0;
@@ -292,8 +310,12 @@ impl DMARCParser for Iter<'_, u8> {
                     found_at = true;
                     uri.push(ch);
                 }
+                b'"' => {
+                    in_quote = !in_quote;
+                    uri.push(ch);
+                }
                 _ => {
-                    if !ch.is_ascii_whitespace() {
+                    if in_quote || !ch.is_ascii_whitespace() {
                         uri.push(ch);
                     }
                 }
@@ -335,6 +357,73 @@ const RF: u64 = (b'r' as u64) | (b'f' as u64) << 8;
 const RI: u64 = (b'r' as u64) | (b'i' as u64) << 8;
 const RUA: u64 = (b'r' as u64) | (b'u' as u64) << 8 | (b'a' as u64) << 16;
 const RUF: u64 = (b'r' as u64) | (b'u' as u64) << 8 | (b'f' as u64) << 16;
+impl URI {
+    /// Parses a single DMARC report URI, such as `mailto:dmarc@example.org!10m`,
+    /// resolving the optional `!size` suffix to a number of bytes. The address
+    /// of `mailto:` URIs is stored without the scheme, as when parsing the `rua=`
+    /// and `ruf=` tags, while `http:` and `https:` URIs are stored in full.
+    pub fn parse(value: &str) -> crate::Result<Self> {
+        let value = value.trim();
+        let (scheme, _) = value.split_once(':').ok_or(Error::ParseError)?;
+
+        if scheme.eq_ignore_ascii_case("mailto") {
+            let mut uris = value.as_bytes().iter().uris()?;
+            match uris.pop() {
+                Some(uri) if uris.is_empty() => Ok(uri),
+                _ => Err(Error::ParseError),
+            }
+        } else if scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("http") {
+            let (uri, max_size) = match value.rsplit_once('!') {
+                Some((uri, size)) => (uri, parse_max_size(size).ok_or(Error::ParseError)?),
+                None => (value, 0),
+            };
+            if uri.len() > scheme.len() + 1
+                && !uri
+                    .bytes()
+                    .any(|ch| ch.is_ascii_whitespace() || matches!(ch, b',' | b';'))
+            {
+                Ok(URI {
+                    uri: uri.to_string(),
+                    max_size,
+                })
+            } else {
+                Err(Error::ParseError)
+            }
+        } else {
+            Err(Error::ParseError)
+        }
+    }
+}
+
+fn parse_max_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) = match size.as_bytes().last().copied().and_then(size_unit) {
+        Some(unit) => (&size[..size.len() - 1], unit),
+        None => (size, 1),
+    };
+    if !digits.is_empty() && digits.bytes().all(|ch| ch.is_ascii_digit()) {
+        Some(
+            digits
+                .bytes()
+                .fold(0usize, |size, ch| {
+                    size.saturating_mul(10).saturating_add((ch - b'0') as usize)
+                })
+                .saturating_mul(multiplier),
+        )
+    } else {
+        None
+    }
+}
+
+fn size_unit(ch: u8) -> Option<usize> {
+    match ch {
+        b'k' | b'K' => Some(1024),
+        b'm' | b'M' => Some(1024 * 1024),
+        b'g' | b'G' => Some(1024 * 1024 * 1024),
+        b't' | b'T' => Some(1024 * 1024 * 1024 * 1024),
+        _ => None,
+    }
+}
+
 const SP: u64 = (b's' as u64) | (b'p' as u64) << 8;
 const PSD: u64 = (b'p' as u64) | (b's' as u64) << 8 | (b'd' as u64) << 16;
 
@@ -343,9 +432,181 @@ mod test {
     use crate::{
         common::parse::TxtRecordParser,
         dmarc::{Alignment, Dmarc, Format, Policy, Psd, Report, URI},
-        Version,
+        Error, Version,
     };
 
+    #[test]
+    fn parse_dmarc_rf() {
+        for (record, expected_unsupported) in [
+            ("v=DMARC1; p=reject; rf=afrf", vec![]),
+            ("v=DMARC1; p=reject", vec![]),
+            ("v=DMARC1; p=reject; rf=iodef", vec!["iodef"]),
+            ("v=DMARC1; p=reject; rf=iodef:afrf", vec!["iodef"]),
+        ] {
+            let dmarc = Dmarc::parse(record.as_bytes()).unwrap();
+            assert_eq!(dmarc.rf, Format::Afrf as u8, "{record}");
+            assert_eq!(dmarc.report_format(), Format::Afrf, "{record}");
+            assert_eq!(dmarc.unsupported_report_formats(), expected_unsupported);
+        }
+    }
+
+    #[test]
+    fn parse_dmarc_pct() {
+        for (record, expected) in [
+            ("v=DMARC1; p=reject", Ok(100)),
+            ("v=DMARC1; p=reject; pct=100", Ok(100)),
+            ("v=DMARC1; p=reject; pct=0", Ok(0)),
+            ("v=DMARC1; p=reject; pct=150", Err(Error::ParseError)),
+            ("v=DMARC1; p=reject; pct=fifty", Err(Error::ParseError)),
+            ("v=DMARC1; p=reject; pct=", Err(Error::ParseError)),
+        ] {
+            assert_eq!(
+                Dmarc::parse(record.as_bytes()).map(|dmarc| dmarc.pct()),
+                expected,
+                "{record}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_dmarc_whitespace_and_order() {
+        let expected = Dmarc::parse(
+            b"v=DMARC1; p=reject; sp=quarantine; adkim=s; pct=50; rua=mailto:x@example.org",
+        )
+        .unwrap();
+        assert_eq!(expected.policy(), Policy::Reject);
+        assert_eq!(expected.subdomain_policy(), Policy::Quarantine);
+        assert_eq!(expected.dkim_alignment(), Alignment::Strict);
+        assert_eq!(expected.pct(), 50);
+        assert_eq!(expected.rua(), [URI::new("x@example.org", 0)]);
+
+        for record in [
+            "v=DMARC1;p=reject;sp=quarantine;adkim=s;pct=50;rua=mailto:x@example.org",
+            "v=DMARC1;p=reject;sp=quarantine;adkim=s;pct=50;rua=mailto:x@example.org;",
+            "v=DMARC1; p=reject; sp=quarantine; adkim=s; pct=50; rua=mailto:x@example.org; ",
+            "v=DMARC1;\tp=reject;\tsp=quarantine;\tadkim=s;\tpct=50;\trua=mailto:x@example.org",
+            "v = DMARC1 ; p = reject ; sp = quarantine ; adkim = s ; pct = 50 ; rua = mailto:x@example.org",
+            "  v=DMARC1;  p=reject;sp=quarantine ;\t adkim=s;pct=50;   rua=mailto:x@example.org",
+            "v=DMARC1;rua=mailto:x@example.org;p=reject;pct=50;adkim=s;sp=quarantine",
+            "v=DMARC1; pct=50; adkim=s; rua=mailto:x@example.org; sp=quarantine; p=reject;",
+        ] {
+            assert_eq!(Dmarc::parse(record.as_bytes()).unwrap(), expected, "{record}");
+        }
+
+        for record in [
+            "p=reject; v=DMARC1",
+            "rua=mailto:x@example.org; v=DMARC1; p=reject",
+            "p=reject",
+            "v=DMARC2; p=reject",
+            "v=spf1 -all",
+            "",
+        ] {
+            assert_eq!(
+                Dmarc::parse(record.as_bytes()),
+                Err(Error::InvalidRecordType),
+                "{record}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_dmarc_value_case() {
+        for (record, p, sp, np, aspf, adkim) in [
+            (
+                "v=DMARC1; p=Reject; sp=QUARANTINE; np=nOnE; aspf=S; adkim=S",
+                Policy::Reject,
+                Policy::Quarantine,
+                Policy::None,
+                Alignment::Strict,
+                Alignment::Strict,
+            ),
+            (
+                "V=dmarc1; P=QuaRANtine; SP=rejecT; ASPF=R; ADKIM=s",
+                Policy::Quarantine,
+                Policy::Reject,
+                Policy::Reject,
+                Alignment::Relaxed,
+                Alignment::Strict,
+            ),
+        ] {
+            let dmarc = Dmarc::parse(record.as_bytes())
+                .unwrap_or_else(|err| panic!("{record:?} : {err:?}"));
+            assert_eq!(dmarc.p, p, "{record}");
+            assert_eq!(dmarc.sp, sp, "{record}");
+            assert_eq!(dmarc.np, np, "{record}");
+            assert_eq!(dmarc.aspf, aspf, "{record}");
+            assert_eq!(dmarc.adkim, adkim, "{record}");
+        }
+
+        // Unknown values are still rejected regardless of case
+        assert_eq!(
+            Dmarc::parse(b"v=DMARC1; p=Rejected"),
+            Err(Error::ParseError)
+        );
+    }
+
+    #[test]
+    fn parse_dmarc_quoted_uri() {
+        for (record, expected_rua) in [
+            (
+                "v=DMARC1; p=none; rua=mailto:\"weird@local\"@example.org",
+                vec![URI::new("\"weird@local\"@example.org", 0)],
+            ),
+            (
+                "v=DMARC1; p=none; rua=mailto:%22john%20doe%22@example.org!10m, mailto:jdoe@example.org",
+                vec![
+                    URI::new("\"john doe\"@example.org", 10 * 1024 * 1024),
+                    URI::new("jdoe@example.org", 0),
+                ],
+            ),
+            (
+                "v=DMARC1; p=none; rua=mailto:\"john doe\"@example.org",
+                vec![URI::new("\"john doe\"@example.org", 0)],
+            ),
+        ] {
+            let dmarc = Dmarc::parse(record.as_bytes()).unwrap();
+            assert_eq!(dmarc.rua(), expected_rua, "{record}");
+        }
+    }
+
+    #[test]
+    fn parse_dmarc_uri() {
+        for (value, expected) in [
+            (
+                "mailto:dmarc@example.org",
+                Ok(URI::new("dmarc@example.org", 0)),
+            ),
+            (
+                "mailto:dmarc@example.org!10m",
+                Ok(URI::new("dmarc@example.org", 10 * 1024 * 1024)),
+            ),
+            (
+                "MAILTO:Dmarc@Example.org!512",
+                Ok(URI::new("dmarc@example.org", 512)),
+            ),
+            (
+                "https://reports.example.org/dmarc",
+                Ok(URI::new("https://reports.example.org/dmarc", 0)),
+            ),
+            (
+                "https://reports.example.org/dmarc!2k",
+                Ok(URI::new("https://reports.example.org/dmarc", 2 * 1024)),
+            ),
+            ("dmarc@example.org", Err(Error::ParseError)),
+            ("mailto:example.org", Err(Error::ParseError)),
+            (
+                "mailto:a@example.org,mailto:b@example.org",
+                Err(Error::ParseError),
+            ),
+            ("mailto:dmarc@example.org!10x", Err(Error::ParseError)),
+            ("https://example.org/dmarc!m", Err(Error::ParseError)),
+            ("https:", Err(Error::ParseError)),
+            ("ftp://example.org/dmarc", Err(Error::ParseError)),
+        ] {
+            assert_eq!(URI::parse(value), expected, "{value}");
+        }
+    }
+
     #[test]
     fn parse_dmarc() {
         for (record, expected_result) in [
@@ -366,6 +627,8 @@ mod test {
                     psd: Psd::Default,
                     t: false,
                     v: Version::V1,
+                    unknown_tags: vec![],
+                    unsupported_rf: vec![],
                 },
             ),
             (
@@ -388,6 +651,8 @@ mod test {
                     psd: Psd::Default,
                     t: false,
                     v: Version::V1,
+                    unknown_tags: vec![],
+                    unsupported_rf: vec![],
                 },
             ),
             (
@@ -413,6 +678,8 @@ mod test {
                     psd: Psd::Default,
                     t: false,
                     v: Version::V1,
+                    unknown_tags: vec![],
+                    unsupported_rf: vec![],
                 },
             ),
             (
@@ -435,6 +702,8 @@ mod test {
                     psd: Psd::Default,
                     t: false,
                     v: Version::V1,
+                    unknown_tags: vec![],
+                    unsupported_rf: vec![],
                 },
             ),
             (
@@ -461,6 +730,8 @@ mod test {
                     psd: Psd::Default,
                     t: false,
                     v: Version::V1,
+                    unknown_tags: vec!["ignore_me".into()],
+                    unsupported_rf: vec![],
                 },
             ),
             (
@@ -486,6 +757,29 @@ mod test {
                     psd: Psd::Yes,
                     t: true,
                     v: Version::V1,
+                    unknown_tags: vec![],
+                    unsupported_rf: vec![],
+                },
+            ),
+            (
+                "v=DMARC1; p=reject; pct=50; t=n",
+                Dmarc {
+                    adkim: Alignment::Relaxed,
+                    aspf: Alignment::Relaxed,
+                    fo: Report::All,
+                    np: Policy::Reject,
+                    p: Policy::Reject,
+                    pct: 50,
+                    rf: Format::Afrf as u8,
+                    ri: 86400,
+                    rua: vec![],
+                    ruf: vec![],
+                    sp: Policy::Reject,
+                    psd: Psd::Default,
+                    t: false,
+                    v: Version::V1,
+                    unknown_tags: vec![],
+                    unsupported_rf: vec![],
                 },
             ),
         ] {
@@ -10,15 +10,22 @@
 
 use std::sync::Arc;
 
+use futures_util::{stream, StreamExt};
+
 use crate::{
-    AuthenticatedMessage, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error, Resolver,
-    SpfOutput, SpfResult,
+    common::{address::split_address, metrics::Metrics, resolver::MAX_CONCURRENT_LOOKUPS},
+    is_within_pct, AuthenticatedMessage, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error,
+    Resolver, SpfOutput, SpfResult,
 };
 
-use super::{Alignment, Dmarc, URI};
+use super::{is_aligned, Alignment, Dmarc, DmarcOptions, Override, Policy, URI};
 
 impl Resolver {
-    /// Verifies the DMARC policy of an RFC5322.From domain
+    /// Verifies the DMARC policy of an RFC5322.From domain.
+    ///
+    /// An empty `mail_from_domain` denotes a null reverse-path, in which case
+    /// SPF alignment is checked against the HELO identity of `spf_output`,
+    /// or fails if there is none.
     pub async fn verify_dmarc(
         &self,
         message: &AuthenticatedMessage<'_>,
@@ -26,13 +33,135 @@ impl Resolver {
         mail_from_domain: &str,
         spf_output: &SpfOutput,
     ) -> DmarcOutput {
-        // Extract RFC5322.From
+        self.verify_dmarc_with_opts(
+            message,
+            dkim_output,
+            mail_from_domain,
+            spf_output,
+            &DmarcOptions::default(),
+            |_| None,
+        )
+        .await
+    }
+
+    /// Verifies the DMARC policy of an RFC5322.From domain, allowing
+    /// local policy to override the resulting disposition. The hook
+    /// receives the evaluated output and returns the override to apply, if any.
+    pub async fn verify_dmarc_with_override(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+        dkim_output: &[DkimOutput<'_>],
+        mail_from_domain: &str,
+        spf_output: &SpfOutput,
+        policy_override: impl FnOnce(&DmarcOutput) -> Option<Override>,
+    ) -> DmarcOutput {
+        self.verify_dmarc_with_opts(
+            message,
+            dkim_output,
+            mail_from_domain,
+            spf_output,
+            &DmarcOptions::default(),
+            policy_override,
+        )
+        .await
+    }
+
+    /// Verifies the DMARC policy of an RFC5322.From domain using the
+    /// provided evaluation options and local policy override hook.
+    ///
+    /// Only the lookup of the `_dmarc` record affects the verdict: a DNS
+    /// error sets both results to `temperror`, while a record that fails to
+    /// parse is exposed through [`DmarcOutput::record_error`] and evaluated
+    /// as if no record was published. Report addresses are not validated
+    /// here, see [`Resolver::try_verify_dmarc_report_address`], so failures
+    /// while authorizing external reporting URIs never change the policy
+    /// or alignment results. When no aligned signature passes but one
+    /// failed with a temporary error, the DKIM result is `temperror` so
+    /// that the message can be deferred instead of rejected.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, message, dkim_output, spf_output, opts, policy_override)
+        )
+    )]
+    pub async fn verify_dmarc_with_opts(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+        dkim_output: &[DkimOutput<'_>],
+        mail_from_domain: &str,
+        spf_output: &SpfOutput,
+        opts: &DmarcOptions,
+        policy_override: impl FnOnce(&DmarcOutput) -> Option<Override>,
+    ) -> DmarcOutput {
+        let mut metrics = Metrics::new();
+        let mut output = self
+            .verify_dmarc_(
+                message,
+                dkim_output,
+                mail_from_domain,
+                spf_output,
+                &mut metrics,
+            )
+            .await;
+        output.dkim_required = opts.require_dkim;
+        output.from_count = message.from.len();
+        if opts.reject_multiple_from
+            && output.record.is_some()
+            && message.has_multiple_from_headers()
+        {
+            output.spf_result = DmarcResult::Fail(Error::MultipleFromHeaders);
+            output.dkim_result = DmarcResult::Fail(Error::MultipleFromHeaders);
+            output.spf_aligned = None;
+            output.dkim_aligned = None;
+        } else if output.record.is_some()
+            && output.spf_result != DmarcResult::Pass
+            && opts
+                .remote_ip
+                .is_some_and(|ip| opts.is_trusted_forwarder(ip))
+        {
+            // Forwarding breaks SPF, leave the verdict to DKIM alignment
+            output.spf_result = DmarcResult::None;
+            output.spf_aligned = None;
+            output.forwarder_exempt = true;
+        }
+        output.sampled_out = !output.is_pass()
+            && matches!(
+                output.effective_policy(),
+                Policy::Quarantine | Policy::Reject
+            )
+            && output
+                .record
+                .as_ref()
+                .is_some_and(|r| !is_within_pct(r.pct));
+        output.policy_override = policy_override(&output);
+        metrics.finish(
+            "dmarc",
+            if output.spf_result == DmarcResult::Pass && !output.dkim_required {
+                output.spf_result.as_keyword()
+            } else {
+                output.dkim_result.as_keyword()
+            },
+        );
+        output
+    }
+
+    async fn verify_dmarc_(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+        dkim_output: &[DkimOutput<'_>],
+        mail_from_domain: &str,
+        spf_output: &SpfOutput,
+        metrics: &mut Metrics,
+    ) -> DmarcOutput {
+        // Extract RFC5322.From, domains are compared without a trailing dot
         let mut from_domain = "";
         for from in &message.from {
-            if let Some((_, domain)) = from.rsplit_once('@') {
+            if let Some((_, domain)) = split_address(from) {
+                let domain = domain.trim_end_matches('.');
                 if from_domain.is_empty() {
                     from_domain = domain;
-                } else if from_domain != domain {
+                } else if !from_domain.eq_ignore_ascii_case(domain) {
                     // Multi-valued RFC5322.From header fields with multiple
                     // domains MUST be exempt from DMARC checking.
                     return DmarcOutput::default();
@@ -44,9 +173,14 @@ impl Resolver {
         }
 
         // Obtain DMARC policy
-        let dmarc = match self.dmarc_tree_walk(from_domain).await {
+        let dmarc = match self.dmarc_tree_walk(from_domain, metrics).await {
             Ok(Some(dmarc)) => dmarc,
             Ok(None) => return DmarcOutput::default().with_domain(from_domain),
+            Err(err @ Error::ParseError) => {
+                return DmarcOutput::default()
+                    .with_domain(from_domain)
+                    .with_record_error(err)
+            }
             Err(err) => {
                 let err = DmarcResult::from(err);
                 return DmarcOutput::default()
@@ -62,93 +196,140 @@ impl Resolver {
             domain: from_domain.to_string(),
             policy: dmarc.p,
             record: None,
+            policy_override: None,
+            dkim_required: false,
+            record_error: None,
+            from_count: 0,
+            forwarder_exempt: false,
+            sampled_out: false,
+            spf_aligned: None,
+            dkim_aligned: None,
         };
 
+        // With a null reverse-path SPF authenticates the HELO identity
+        // instead (RFC 7489, Section 3.1.2), without one SPF cannot align
+        let mail_from_domain = match mail_from_domain.trim_end_matches('.') {
+            "" => spf_output.helo().trim_end_matches('.'),
+            domain => domain,
+        };
         let has_dkim_pass = dkim_output.iter().any(|o| o.result == DkimResult::Pass);
         if spf_output.result == SpfResult::Pass || has_dkim_pass {
             // Check SPF alignment
-            let from_subdomain = format!(".{from_domain}");
             if spf_output.result == SpfResult::Pass {
-                output.spf_result = if mail_from_domain == from_domain {
-                    DmarcResult::Pass
-                } else if dmarc.aspf == Alignment::Relaxed
-                    && mail_from_domain.ends_with(&from_subdomain)
-                    || from_domain.ends_with(&format!(".{mail_from_domain}"))
-                {
-                    output.policy = dmarc.sp;
-                    DmarcResult::Pass
-                } else {
-                    DmarcResult::Fail(Error::NotAligned)
+                output.spf_aligned = is_aligned(mail_from_domain, from_domain, dmarc.aspf);
+                output.spf_result = match output.spf_aligned {
+                    Some(Alignment::Strict) => DmarcResult::Pass,
+                    Some(Alignment::Relaxed) => {
+                        output.policy = dmarc.sp;
+                        DmarcResult::Pass
+                    }
+                    None => DmarcResult::Fail(Error::NotAligned),
                 };
             }
 
             // Check DKIM alignment
             if has_dkim_pass {
-                output.dkim_result = if dkim_output.iter().any(|o| {
-                    o.result == DkimResult::Pass && o.signature.as_ref().unwrap().d.eq(from_domain)
-                }) {
-                    DmarcResult::Pass
-                } else if dmarc.adkim == Alignment::Relaxed
-                    && dkim_output.iter().any(|o| {
-                        o.result == DkimResult::Pass
-                            && (o.signature.as_ref().unwrap().d.ends_with(&from_subdomain)
-                                || from_domain
-                                    .ends_with(&format!(".{}", o.signature.as_ref().unwrap().d)))
-                    })
-                {
-                    output.policy = dmarc.sp;
-                    DmarcResult::Pass
-                } else {
-                    if dkim_output.iter().any(|o| {
-                        o.result == DkimResult::Pass
-                            && (o.signature.as_ref().unwrap().d.ends_with(&from_subdomain)
-                                || from_domain
-                                    .ends_with(&format!(".{}", o.signature.as_ref().unwrap().d)))
-                    }) {
+                let aligned = |mode| {
+                    dkim_output
+                        .iter()
+                        .filter(|o| o.result == DkimResult::Pass)
+                        .filter_map(|o| is_aligned(&o.signature.unwrap().d, from_domain, mode))
+                        .min_by_key(|alignment| *alignment == Alignment::Relaxed)
+                };
+                output.dkim_aligned = aligned(dmarc.adkim);
+                output.dkim_result = match output.dkim_aligned {
+                    Some(Alignment::Strict) => DmarcResult::Pass,
+                    Some(Alignment::Relaxed) => {
                         output.policy = dmarc.sp;
+                        DmarcResult::Pass
+                    }
+                    None => {
+                        if aligned(Alignment::Relaxed).is_some() {
+                            output.policy = dmarc.sp;
+                        }
+                        DmarcResult::Fail(Error::NotAligned)
                     }
-                    DmarcResult::Fail(Error::NotAligned)
                 };
             }
         }
 
+        // An aligned signature that failed temporarily could still pass,
+        // so DKIM alignment is undetermined rather than failed
+        if output.dkim_result != DmarcResult::Pass {
+            if let Some(err) = dkim_output
+                .iter()
+                .find_map(|o| match (&o.result, o.signature) {
+                    (DkimResult::TempError(err), Some(signature)) => {
+                        is_aligned(&signature.d, from_domain, dmarc.adkim).and(Some(err))
+                    }
+                    _ => None,
+                })
+            {
+                output.dkim_result = DmarcResult::TempError(err.clone());
+            }
+        }
+
         output.with_record(dmarc)
     }
 
-    /// Validates the external report e-mail addresses of a DMARC record
+    /// Validates the external report e-mail addresses of a DMARC record.
+    /// Authorization records of external domains are looked up concurrently,
+    /// and the authorized addresses are returned in their original order.
+    /// Returns `None` if a DNS error prevented the validation.
     pub async fn verify_dmarc_report_address<'x>(
         &self,
         domain: &str,
         addresses: &'x [URI],
     ) -> Option<Vec<&'x URI>> {
-        let mut result = Vec::with_capacity(addresses.len());
-        for address in addresses {
-            if address.uri.ends_with(domain)
+        self.try_verify_dmarc_report_address(domain, addresses)
+            .await
+            .ok()
+    }
+
+    /// Validates the external report e-mail addresses of a DMARC record,
+    /// returning the DNS error that prevented the validation, if any. Such
+    /// errors only affect reporting and should not alter the DMARC verdict.
+    pub async fn try_verify_dmarc_report_address<'x>(
+        &self,
+        domain: &str,
+        addresses: &'x [URI],
+    ) -> crate::Result<Vec<&'x URI>> {
+        let mut lookups = stream::iter(addresses.iter().map(|address| async move {
+            let is_authorized = address.uri.ends_with(domain)
                 || match self
                     .txt_lookup::<Dmarc>(format!(
                         "{}._report._dmarc.{}.",
                         domain,
-                        address
-                            .uri
-                            .rsplit_once('@')
+                        split_address(&address.uri)
                             .map(|(_, d)| d)
                             .unwrap_or_default()
                     ))
                     .await
                 {
                     Ok(_) => true,
-                    Err(Error::DnsError(_)) => return None,
+                    Err(err @ Error::DnsError(_)) => return Err(err),
                     _ => false,
-                }
-            {
+                };
+            Ok((address, is_authorized))
+        }))
+        .buffered(MAX_CONCURRENT_LOOKUPS);
+
+        let mut result = Vec::with_capacity(addresses.len());
+        while let Some(lookup) = lookups.next().await {
+            let (address, is_authorized) = lookup?;
+            if is_authorized {
                 result.push(address);
             }
         }
 
-        result.into()
+        Ok(result)
     }
 
-    async fn dmarc_tree_walk(&self, domain: &str) -> crate::Result<Option<Arc<Dmarc>>> {
+    async fn dmarc_tree_walk(
+        &self,
+        domain: &str,
+        metrics: &mut Metrics,
+    ) -> crate::Result<Option<Arc<Dmarc>>> {
         let labels = domain.split('.').collect::<Vec<_>>();
         let mut x = labels.len();
         if x == 1 {
@@ -165,11 +346,14 @@ impl Resolver {
             domain.push('.');
 
             // Query DMARC
+            metrics.dns_lookup();
             match self.txt_lookup::<Dmarc>(domain).await {
                 Ok(dmarc) => {
                     return Ok(Some(dmarc));
                 }
+                // No DMARC record published, continue with the parent domain
                 Err(Error::DnsRecordNotFound(_)) | Err(Error::InvalidRecordType) => (),
+                // A record is present but malformed, which stops the walk
                 Err(err) => return Err(err),
             }
 
@@ -195,7 +379,8 @@ mod test {
     use crate::{
         common::parse::TxtRecordParser,
         dkim::Signature,
-        dmarc::{Dmarc, Policy, URI},
+        dmarc::{is_aligned, Alignment, Assessment, Dmarc, DmarcOptions, Override, Policy, URI},
+        report::{ActionDisposition, PolicyOverride, PolicyOverrideReason, Record},
         AuthenticatedMessage, DkimOutput, DkimResult, DmarcResult, Error, Resolver, SpfOutput,
         SpfResult,
     };
@@ -296,6 +481,38 @@ mod test {
                 DmarcResult::Pass,
                 Policy::Quarantine,
             ),
+            // Strict - Pass with trailing dots
+            (
+                "_dmarc.example.org.",
+                concat!(
+                    "v=DMARC1; p=reject; sp=quarantine; np=None; aspf=s; adkim=s; fo=1;",
+                    "rua=mailto:dmarc-feedback@example.org"
+                ),
+                "From: hello@example.org\r\n\r\n",
+                "example.org.",
+                "example.org.",
+                DkimResult::Pass,
+                SpfResult::Pass,
+                DmarcResult::Pass,
+                DmarcResult::Pass,
+                Policy::Reject,
+            ),
+            // Relaxed - Pass with trailing dots
+            (
+                "_dmarc.example.org.",
+                concat!(
+                    "v=DMARC1; p=reject; sp=quarantine; np=None; aspf=r; adkim=r; fo=1;",
+                    "rua=mailto:dmarc-feedback@example.org"
+                ),
+                "From: hello@example.org.\r\n\r\n",
+                "mail.example.org",
+                "mail.example.org.",
+                DkimResult::Pass,
+                SpfResult::Pass,
+                DmarcResult::Pass,
+                DmarcResult::Pass,
+                Policy::Quarantine,
+            ),
             // Failed mechanisms
             (
                 "_dmarc.example.org.",
@@ -325,18 +542,8 @@ mod test {
                 d: signature_domain.into(),
                 ..Default::default()
             };
-            let dkim = DkimOutput {
-                result: dkim,
-                signature: (&signature).into(),
-                report: None,
-                is_atps: false,
-            };
-            let spf = SpfOutput {
-                result: spf,
-                domain: mail_from_domain.to_string(),
-                report: None,
-                explanation: None,
-            };
+            let dkim = dkim_output(dkim, &signature);
+            let spf = spf_output(spf, mail_from_domain);
             let result = resolver
                 .verify_dmarc(&auth_message, &[dkim], mail_from_domain, &spf)
                 .await;
@@ -347,29 +554,1133 @@ mod test {
     }
 
     #[tokio::test]
-    async fn dmarc_verify_report_address() {
+    async fn dmarc_verify_override() {
         let resolver = Resolver::new_system_conf().unwrap();
-        #[cfg(any(test, feature = "test"))]
         resolver.txt_add(
-            "example.org._report._dmarc.external.org.",
-            Dmarc::parse(b"v=DMARC1").unwrap(),
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; rua=mailto:dmarc-feedback@example.org").unwrap(),
             Instant::now() + Duration::new(3200, 0),
         );
-        let uris = vec![
-            URI::new("dmarc@example.org", 0),
-            URI::new("dmarc@external.org", 0),
-            URI::new("domain@other.org", 0),
-        ];
 
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@example.org\r\n\r\n").unwrap();
+        let spf = spf_output(SpfResult::Fail, "example.org");
+        let result = resolver
+            .verify_dmarc_with_override(&auth_message, &[], "example.org", &spf, |output| {
+                assert_eq!(output.policy(), Policy::Reject);
+                Some(Override::new(
+                    Policy::None,
+                    PolicyOverrideReason::new(PolicyOverride::MailingList)
+                        .with_comment("list.example.org"),
+                ))
+            })
+            .await;
+        assert_eq!(result.policy(), Policy::Reject);
         assert_eq!(
-            resolver
-                .verify_dmarc_report_address("example.org", &uris)
-                .await
-                .unwrap(),
-            vec![
-                &URI::new("dmarc@example.org", 0),
-                &URI::new("dmarc@external.org", 0),
-            ]
+            result.policy_override().map(|o| o.disposition),
+            Some(Policy::None)
+        );
+
+        let record = Record::new().with_dmarc_output(&result);
+        assert_eq!(record.action_disposition(), ActionDisposition::None);
+        assert_eq!(
+            record.policy_override_reason(),
+            &[PolicyOverrideReason::new(PolicyOverride::MailingList)
+                .with_comment("list.example.org")]
+        );
+
+        // No override
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "example.org", &spf)
+            .await;
+        assert_eq!(result.policy_override(), None);
+        assert_eq!(
+            Record::new()
+                .with_dmarc_output(&result)
+                .action_disposition(),
+            ActionDisposition::Reject
+        );
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_testing() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; t=y").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        resolver.txt_add(
+            "_dmarc.example.com.",
+            Dmarc::parse(b"v=DMARC1; p=reject; t=n").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        for (domain, expected_policy, expected_disposition) in [
+            ("example.org", Policy::None, ActionDisposition::None),
+            ("example.com", Policy::Reject, ActionDisposition::Reject),
+        ] {
+            let message = format!("From: hello@{domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let spf = spf_output(SpfResult::Fail, domain);
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], domain, &spf)
+                .await;
+            assert_eq!(result.policy(), Policy::Reject);
+            assert_eq!(result.effective_policy(), expected_policy);
+            assert_eq!(
+                Record::new()
+                    .with_dmarc_output(&result)
+                    .action_disposition(),
+                expected_disposition
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_invalid_record() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.sub.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=invalidvalue"),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        let auth_message =
+            AuthenticatedMessage::parse(b"From: hello@sub.example.org\r\n\r\n").unwrap();
+        let spf = spf_output(SpfResult::Fail, "sub.example.org");
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "sub.example.org", &spf)
+            .await;
+
+        // The organizational domain record must not be used
+        assert_eq!(result.domain(), "sub.example.org");
+        assert_eq!(result.dmarc_record(), None);
+        assert_eq!(result.record_error(), Some(&Error::ParseError));
+        assert_eq!(result.policy(), Policy::None);
+        assert_eq!(result.spf_result(), &DmarcResult::None);
+        assert_eq!(result.dkim_result(), &DmarcResult::None);
+    }
+
+    #[tokio::test]
+    async fn dmarc_is_enforced() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (domain, record, expected) in [
+            ("reject.org", "v=DMARC1; p=reject", true),
+            ("quarantine.org", "v=DMARC1; p=quarantine; pct=1", true),
+            ("none.org", "v=DMARC1; p=none", false),
+            ("pct.org", "v=DMARC1; p=reject; pct=0", false),
+            ("testing.org", "v=DMARC1; p=quarantine; t=y", false),
+            ("sp.org", "v=DMARC1; p=reject; sp=none", true),
+        ] {
+            let dmarc = Dmarc::parse(record.as_bytes()).unwrap();
+            assert_eq!(dmarc.is_enforced(), expected, "{record}");
+            resolver.txt_add(
+                format!("_dmarc.{domain}."),
+                dmarc,
+                Instant::now() + Duration::new(3200, 0),
+            );
+
+            let spf = spf_output(SpfResult::Fail, domain);
+            let message = format!("From: hello@{domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], domain, &spf)
+                .await;
+            assert_eq!(result.is_enforced(), expected, "{record}");
+        }
+
+        // The subdomain policy applies to messages from subdomains
+        let spf = spf_output(SpfResult::Pass, "mail.sp.org");
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@sp.org\r\n\r\n").unwrap();
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "mail.sp.org", &spf)
+            .await;
+        assert_eq!(result.policy(), Policy::None);
+        assert!(!result.is_enforced());
+
+        // Domains without a DMARC record are never enforced
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@example.org\r\n\r\n").unwrap();
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "example.org", &spf)
+            .await;
+        assert!(!result.is_enforced());
+    }
+
+    #[tokio::test]
+    async fn dmarc_disposition() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (domain, record, spf_result, expected) in [
+            (
+                "pass.org",
+                "v=DMARC1; p=reject",
+                SpfResult::Pass,
+                Policy::None,
+            ),
+            (
+                "reject.org",
+                "v=DMARC1; p=reject",
+                SpfResult::Fail,
+                Policy::Reject,
+            ),
+            (
+                "quarantine.org",
+                "v=DMARC1; p=quarantine",
+                SpfResult::Fail,
+                Policy::Quarantine,
+            ),
+            (
+                "sampled.org",
+                "v=DMARC1; p=reject; pct=0",
+                SpfResult::Fail,
+                Policy::Quarantine,
+            ),
+            (
+                "sampledq.org",
+                "v=DMARC1; p=quarantine; pct=0",
+                SpfResult::Fail,
+                Policy::None,
+            ),
+            (
+                "sampledn.org",
+                "v=DMARC1; p=none; pct=0",
+                SpfResult::Fail,
+                Policy::None,
+            ),
+            (
+                "testing.org",
+                "v=DMARC1; p=reject; t=y",
+                SpfResult::Fail,
+                Policy::None,
+            ),
+        ] {
+            resolver.txt_add(
+                format!("_dmarc.{domain}."),
+                Dmarc::parse(record.as_bytes()).unwrap(),
+                Instant::now() + Duration::new(3200, 0),
+            );
+
+            let spf = spf_output(spf_result, domain);
+            let message = format!("From: hello@{domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], domain, &spf)
+                .await;
+            assert_eq!(result.disposition(), expected, "{record}");
+            assert_eq!(
+                result.is_sampled_out(),
+                record.contains("pct=0") && !record.contains("p=none"),
+                "{record}"
+            );
+
+            let report = Record::new().with_dmarc_output(&result);
+            assert_eq!(
+                report.action_disposition(),
+                if result.is_pass() {
+                    ActionDisposition::Pass
+                } else {
+                    expected.into()
+                },
+                "{record}"
+            );
+            if result.is_sampled_out() {
+                assert_eq!(
+                    report.policy_override_reason(),
+                    &[PolicyOverrideReason::new(PolicyOverride::SampledOut)]
+                );
+            }
+        }
+
+        // Local policy overrides take precedence
+        let spf = spf_output(SpfResult::Fail, "reject.org");
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@reject.org\r\n\r\n").unwrap();
+        let result = resolver
+            .verify_dmarc_with_override(&auth_message, &[], "reject.org", &spf, |_| {
+                Some(Override::new(
+                    Policy::Quarantine,
+                    PolicyOverrideReason::new(PolicyOverride::LocalPolicy),
+                ))
+            })
+            .await;
+        assert_eq!(result.disposition(), Policy::Quarantine);
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_strict_spf_alignment() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (domain, record) in [
+            ("strict.org", "v=DMARC1; p=reject; aspf=s"),
+            ("relaxed.org", "v=DMARC1; p=reject"),
+        ] {
+            resolver.txt_add(
+                format!("_dmarc.{domain}."),
+                Dmarc::parse(record.as_bytes()).unwrap(),
+                Instant::now() + Duration::new(3200, 0),
+            );
+        }
+
+        // A From domain below the SPF domain only aligns in relaxed mode,
+        // in either direction
+        for (from_domain, mail_from_domain, expected) in [
+            (
+                "sub.strict.org",
+                "strict.org",
+                DmarcResult::Fail(Error::NotAligned),
+            ),
+            (
+                "strict.org",
+                "sub.strict.org",
+                DmarcResult::Fail(Error::NotAligned),
+            ),
+            ("strict.org", "strict.org", DmarcResult::Pass),
+            ("sub.relaxed.org", "relaxed.org", DmarcResult::Pass),
+            ("relaxed.org", "sub.relaxed.org", DmarcResult::Pass),
+        ] {
+            let message = format!("From: hello@{from_domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let spf = spf_output(SpfResult::Pass, mail_from_domain);
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], mail_from_domain, &spf)
+                .await;
+            assert_eq!(
+                result.spf_result(),
+                &expected,
+                "{from_domain} {mail_from_domain}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_alignment_mode() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.relaxed.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
         );
+        resolver.txt_add(
+            "_dmarc.strict.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; aspf=s; adkim=s").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let signature = |d: &str| {
+            Signature::parse(
+                format!(
+                    "v=1; a=rsa-sha256; s=default; d={d}; c=relaxed/relaxed; h=from; bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=; b=dGVzdA=="
+                )
+                .as_bytes(),
+            )
+            .unwrap()
+        };
+
+        for (from_domain, mail_from_domain, signing_domain, spf_aligned, dkim_aligned) in [
+            (
+                "relaxed.org",
+                "relaxed.org",
+                "relaxed.org",
+                Some(Alignment::Strict),
+                Some(Alignment::Strict),
+            ),
+            (
+                "relaxed.org",
+                "bounces.relaxed.org",
+                "mail.relaxed.org",
+                Some(Alignment::Relaxed),
+                Some(Alignment::Relaxed),
+            ),
+            ("relaxed.org", "example.org", "example.org", None, None),
+            (
+                "strict.org",
+                "strict.org",
+                "mail.strict.org",
+                Some(Alignment::Strict),
+                None,
+            ),
+            (
+                "strict.org",
+                "bounces.strict.org",
+                "strict.org",
+                None,
+                Some(Alignment::Strict),
+            ),
+        ] {
+            let message = format!("From: hello@{from_domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let signature = signature(signing_domain);
+            let dkim = [DkimOutput::pass().with_signature(&signature)];
+            let spf = spf_output(SpfResult::Pass, mail_from_domain);
+            let result = resolver
+                .verify_dmarc(&auth_message, &dkim, mail_from_domain, &spf)
+                .await;
+            let case = format!("{from_domain} {mail_from_domain} {signing_domain}");
+            assert_eq!(result.spf_aligned(), spf_aligned, "{case}");
+            assert_eq!(result.dkim_aligned(), dkim_aligned, "{case}");
+            assert_eq!(
+                result.spf_result() == &DmarcResult::Pass,
+                spf_aligned.is_some()
+            );
+            assert_eq!(
+                result.dkim_result() == &DmarcResult::Pass,
+                dkim_aligned.is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn dmarc_is_aligned() {
+        for (domain, from_domain, mode, expected) in [
+            (
+                "example.org",
+                "example.org",
+                Alignment::Strict,
+                Some(Alignment::Strict),
+            ),
+            (
+                "Example.ORG.",
+                "example.org",
+                Alignment::Strict,
+                Some(Alignment::Strict),
+            ),
+            ("mail.example.org", "example.org", Alignment::Strict, None),
+            (
+                "mail.example.org",
+                "example.org",
+                Alignment::Relaxed,
+                Some(Alignment::Relaxed),
+            ),
+            (
+                "example.org",
+                "MAIL.Example.org.",
+                Alignment::Relaxed,
+                Some(Alignment::Relaxed),
+            ),
+            ("badexample.org", "example.org", Alignment::Relaxed, None),
+            ("org", "example.org", Alignment::Relaxed, None),
+            ("example.org", "org", Alignment::Relaxed, None),
+            ("", "example.org", Alignment::Relaxed, None),
+        ] {
+            assert_eq!(
+                is_aligned(domain, from_domain, mode),
+                expected,
+                "{domain} {from_domain} {mode:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_bimi_eligible() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (domain, record, spf_result, expected) in [
+            ("reject.org", "v=DMARC1; p=reject", SpfResult::Pass, true),
+            (
+                "quarantine.org",
+                "v=DMARC1; p=quarantine",
+                SpfResult::Pass,
+                true,
+            ),
+            ("fail.org", "v=DMARC1; p=reject", SpfResult::Fail, false),
+            ("none.org", "v=DMARC1; p=none", SpfResult::Pass, false),
+            (
+                "sp.org",
+                "v=DMARC1; p=reject; sp=none",
+                SpfResult::Pass,
+                false,
+            ),
+            (
+                "pct.org",
+                "v=DMARC1; p=reject; pct=50",
+                SpfResult::Pass,
+                false,
+            ),
+            (
+                "testing.org",
+                "v=DMARC1; p=reject; t=y",
+                SpfResult::Pass,
+                false,
+            ),
+        ] {
+            resolver.txt_add(
+                format!("_dmarc.{domain}."),
+                Dmarc::parse(record.as_bytes()).unwrap(),
+                Instant::now() + Duration::new(3200, 0),
+            );
+            let message = format!("From: hello@{domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let spf = spf_output(spf_result, domain);
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], domain, &spf)
+                .await;
+            assert_eq!(result.bimi_eligible(), expected, "{record}");
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_multiple_from() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let spf = spf_output(SpfResult::Pass, "example.org");
+
+        for (from, expected_domain, expected_result, expected_count) in [
+            ("a@example.org", "example.org", DmarcResult::Pass, 1),
+            (
+                "a@example.org, b@example.org",
+                "example.org",
+                DmarcResult::Pass,
+                2,
+            ),
+            (
+                "a@example.org, b@Example.Org, c@example.org",
+                "example.org",
+                DmarcResult::Pass,
+                3,
+            ),
+            // Multiple domains are exempt from DMARC checking
+            ("a@example.org, b@example.com", "", DmarcResult::None, 2),
+        ] {
+            let message = format!("From: {from}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], "example.org", &spf)
+                .await;
+            assert_eq!(result.domain(), expected_domain, "{from}");
+            assert_eq!(result.spf_result(), &expected_result, "{from}");
+            assert_eq!(result.from_count(), expected_count, "{from}");
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_multiple_from_headers() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=quarantine").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let spf = spf_output(SpfResult::Pass, "example.org");
+
+        let single =
+            AuthenticatedMessage::parse(b"From: a@example.org\r\nTo: b@example.org\r\n\r\n")
+                .unwrap();
+        let list =
+            AuthenticatedMessage::parse(b"From: a@example.org, b@example.org\r\n\r\n").unwrap();
+        let injected = AuthenticatedMessage::parse(
+            b"From: a@example.org\r\nSubject: Hi\r\nfrom: ceo@example.org\r\n\r\n",
+        )
+        .unwrap();
+        assert!(!single.has_multiple_from_headers());
+        assert!(!list.has_multiple_from_headers());
+        assert!(injected.has_multiple_from_headers());
+        assert_eq!(injected.froms(), ["a@example.org", "ceo@example.org"]);
+
+        // Detection alone does not change the evaluation
+        let result = resolver
+            .verify_dmarc(&injected, &[], "example.org", &spf)
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::Pass);
+
+        let opts = DmarcOptions::new().reject_multiple_from(true);
+        for (message, expected_result) in [
+            (&single, DmarcResult::Pass),
+            (&list, DmarcResult::Pass),
+            (&injected, DmarcResult::Fail(Error::MultipleFromHeaders)),
+        ] {
+            let result = resolver
+                .verify_dmarc_with_opts(message, &[], "example.org", &spf, &opts, |_| None)
+                .await;
+            assert_eq!(result.spf_result(), &expected_result);
+            assert_eq!(result.is_pass(), expected_result == DmarcResult::Pass);
+            assert_eq!(result.policy(), Policy::Quarantine);
+        }
+
+        // Without a DMARC record there is no policy to apply
+        let unpublished = AuthenticatedMessage::parse(
+            b"From: a@example.net\r\nSubject: Hi\r\nfrom: ceo@example.net\r\n\r\n",
+        )
+        .unwrap();
+        let result = resolver
+            .verify_dmarc_with_opts(&unpublished, &[], "example.net", &spf, &opts, |_| None)
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::None);
+        assert_eq!(result.dkim_result(), &DmarcResult::None);
+        assert_eq!(result.disposition(), Policy::None);
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_display_name() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; aspf=s").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let spf = spf_output(SpfResult::Pass, "example.org");
+
+        for from in [
+            "\"Ops, Inc.\" <ops@example.org>",
+            "\"ops@evil.org\" <ops@example.org>",
+            "ops@evil.org <ops@example.org>",
+            "Ops (ops@evil.org) <ops@example.org>",
+            "ops@example.org (Ops, Inc. <ops@evil.org>)",
+            "\"john@doe\"@Example.Org",
+            "\"john doe@evil.org\"@example.org",
+        ] {
+            let message = format!("From: {from}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], "example.org", &spf)
+                .await;
+            assert_eq!(result.domain(), "example.org", "{from}");
+            assert_eq!(result.spf_result(), &DmarcResult::Pass, "{from}");
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_assessment() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        resolver.txt_add(
+            "_dmarc.example.com.",
+            Dmarc::parse(b"v=DMARC1; p=quarantine; t=y").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let aligned = Signature {
+            d: "example.org".into(),
+            ..Default::default()
+        };
+        let unaligned = Signature {
+            d: "example.net".into(),
+            ..Default::default()
+        };
+
+        for (from_domain, mail_from_domain, dkim, spf, expected) in [
+            (
+                "example.org",
+                "example.org",
+                Some((DkimResult::Pass, &aligned)),
+                SpfResult::Pass,
+                Assessment::AlignedDkimPass,
+            ),
+            (
+                "example.org",
+                "example.org",
+                Some((DkimResult::Pass, &unaligned)),
+                SpfResult::Pass,
+                Assessment::AlignedSpfPass,
+            ),
+            (
+                "example.org",
+                "example.net",
+                Some((DkimResult::Pass, &unaligned)),
+                SpfResult::Pass,
+                Assessment::DmarcFailReject,
+            ),
+            (
+                "example.org",
+                "example.net",
+                Some((
+                    DkimResult::TempError(Error::DnsError("timeout".to_string())),
+                    &aligned,
+                )),
+                SpfResult::Fail,
+                Assessment::TempError,
+            ),
+            (
+                "example.com",
+                "example.net",
+                None,
+                SpfResult::Pass,
+                Assessment::DmarcFailNone,
+            ),
+            (
+                "example.net",
+                "example.org",
+                Some((DkimResult::Pass, &aligned)),
+                SpfResult::Fail,
+                Assessment::DkimPass,
+            ),
+            (
+                "example.net",
+                "example.net",
+                None,
+                SpfResult::Pass,
+                Assessment::SpfPass,
+            ),
+            (
+                "example.net",
+                "example.net",
+                None,
+                SpfResult::SoftFail,
+                Assessment::None,
+            ),
+        ] {
+            let message = format!("From: hello@{from_domain}\r\n\r\n");
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let dkim = dkim
+                .into_iter()
+                .map(|(result, signature)| dkim_output(result, signature))
+                .collect::<Vec<_>>();
+            let spf = spf_output(spf, mail_from_domain);
+            let result = resolver
+                .verify_dmarc(&auth_message, &dkim, mail_from_domain, &spf)
+                .await;
+            assert_eq!(result.assess(&spf, &dkim), expected, "{from_domain}");
+        }
+
+        assert!(Assessment::AlignedSpfPass > Assessment::DkimPass);
+        assert!(Assessment::DmarcFailReject < Assessment::None);
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_dkim_temperror() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@example.org\r\n\r\n").unwrap();
+        let aligned = Signature {
+            d: "mail.example.org".into(),
+            ..Default::default()
+        };
+        let unaligned = Signature {
+            d: "example.net".into(),
+            ..Default::default()
+        };
+        let temp_error = DkimResult::TempError(Error::DnsError("timeout".to_string()));
+
+        for (dkim, spf, expected_dkim, expected_pass) in [
+            (
+                vec![(temp_error.clone(), &aligned)],
+                SpfResult::Fail,
+                DmarcResult::TempError(Error::DnsError("timeout".to_string())),
+                false,
+            ),
+            (
+                vec![(temp_error.clone(), &unaligned)],
+                SpfResult::Fail,
+                DmarcResult::None,
+                false,
+            ),
+            (
+                vec![
+                    (temp_error.clone(), &aligned),
+                    (DkimResult::Pass, &unaligned),
+                ],
+                SpfResult::Fail,
+                DmarcResult::TempError(Error::DnsError("timeout".to_string())),
+                false,
+            ),
+            (
+                vec![
+                    (temp_error.clone(), &unaligned),
+                    (DkimResult::Pass, &aligned),
+                ],
+                SpfResult::Fail,
+                DmarcResult::Pass,
+                true,
+            ),
+            (
+                vec![(temp_error.clone(), &aligned)],
+                SpfResult::Pass,
+                DmarcResult::TempError(Error::DnsError("timeout".to_string())),
+                true,
+            ),
+        ] {
+            let dkim = dkim
+                .into_iter()
+                .map(|(result, signature)| dkim_output(result, signature))
+                .collect::<Vec<_>>();
+            let spf = spf_output(spf, "example.org");
+            let result = resolver
+                .verify_dmarc(&auth_message, &dkim, "example.org", &spf)
+                .await;
+            assert_eq!(result.dkim_result(), &expected_dkim);
+            assert_eq!(result.is_pass(), expected_pass);
+            if !expected_pass && matches!(expected_dkim, DmarcResult::TempError(_)) {
+                assert_eq!(result.assess(&spf, &dkim), Assessment::TempError);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_require_dkim() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; rua=mailto:dmarc-feedback@example.org").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@example.org\r\n\r\n").unwrap();
+        let spf = spf_output(SpfResult::Pass, "example.org");
+        let signature = Signature {
+            d: "example.org".into(),
+            ..Default::default()
+        };
+        let dkim = dkim_output(DkimResult::Pass, &signature);
+        let opts = DmarcOptions::new().require_dkim(true);
+
+        // SPF-only alignment does not pass
+        let result = resolver
+            .verify_dmarc_with_opts(&auth_message, &[], "example.org", &spf, &opts, |_| None)
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::Pass);
+        assert_eq!(result.dkim_result(), &DmarcResult::None);
+        assert!(result.is_dkim_required());
+        assert!(!result.is_pass());
+        assert_eq!(
+            Record::new()
+                .with_dmarc_output(&result)
+                .action_disposition(),
+            ActionDisposition::Reject
+        );
+
+        // Aligned DKIM pass
+        let result = resolver
+            .verify_dmarc_with_opts(
+                &auth_message,
+                std::slice::from_ref(&dkim),
+                "example.org",
+                &spf,
+                &opts,
+                |_| None,
+            )
+            .await;
+        assert!(result.is_pass());
+        assert_eq!(
+            Record::new()
+                .with_dmarc_output(&result)
+                .action_disposition(),
+            ActionDisposition::Pass
+        );
+
+        // Default options accept SPF-only alignment
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "example.org", &spf)
+            .await;
+        assert!(!result.is_dkim_required());
+        assert!(result.is_pass());
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_trusted_forwarder() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; rua=mailto:dmarc-feedback@example.org").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        let auth_message = AuthenticatedMessage::parse(b"From: hello@example.org\r\n\r\n").unwrap();
+        let spf = spf_output(SpfResult::Fail, "example.org");
+        let signature = Signature {
+            d: "example.org".into(),
+            ..Default::default()
+        };
+        let dkim = dkim_output(DkimResult::Pass, &signature);
+        let opts = DmarcOptions::new()
+            .trusted_forwarder("192.0.2.0".parse().unwrap(), 24)
+            .trusted_forwarder("2001:db8::".parse().unwrap(), 32);
+
+        assert!(opts.is_trusted_forwarder("192.0.2.25".parse().unwrap()));
+        assert!(opts.is_trusted_forwarder("::ffff:192.0.2.25".parse().unwrap()));
+        assert!(opts.is_trusted_forwarder("2001:db8:1::1".parse().unwrap()));
+        assert!(!opts.is_trusted_forwarder("192.0.3.1".parse().unwrap()));
+        assert!(!opts.is_trusted_forwarder("2001:db9::1".parse().unwrap()));
+
+        // Forwarded message passing on DKIM alignment alone
+        let result = resolver
+            .verify_dmarc_with_opts(
+                &auth_message,
+                std::slice::from_ref(&dkim),
+                "example.org",
+                &spf,
+                &opts.clone().remote_ip("192.0.2.25".parse().unwrap()),
+                |_| None,
+            )
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::None);
+        assert!(result.is_forwarder_exempt());
+        assert!(result.is_pass());
+        let record = Record::new().with_dmarc_output(&result);
+        assert_eq!(record.action_disposition(), ActionDisposition::Pass);
+        assert_eq!(
+            record.policy_override_reason(),
+            [PolicyOverrideReason::new(PolicyOverride::Forwarded)]
+        );
+
+        // Without an aligned DKIM signature the policy still applies
+        let result = resolver
+            .verify_dmarc_with_opts(
+                &auth_message,
+                &[],
+                "example.org",
+                &spf,
+                &opts.clone().remote_ip("192.0.2.25".parse().unwrap()),
+                |_| None,
+            )
+            .await;
+        assert!(result.is_forwarder_exempt());
+        assert!(!result.is_pass());
+        assert_eq!(result.disposition(), Policy::Reject);
+        let record = Record::new().with_dmarc_output(&result);
+        assert_eq!(record.action_disposition(), ActionDisposition::Reject);
+        assert_eq!(
+            record.policy_override_reason(),
+            [PolicyOverrideReason::new(PolicyOverride::Forwarded)]
+        );
+
+        // An unaligned SPF pass from a forwarder is disregarded as well
+        let spf_pass = SpfOutput {
+            result: SpfResult::Pass,
+            domain: "forwarder.net".to_string(),
+            ..spf.clone()
+        };
+        let result = resolver
+            .verify_dmarc_with_opts(
+                &auth_message,
+                &[],
+                "forwarder.net",
+                &spf_pass,
+                &opts.clone().remote_ip("192.0.2.25".parse().unwrap()),
+                |_| None,
+            )
+            .await;
+        assert!(result.is_forwarder_exempt());
+        assert_eq!(result.spf_result(), &DmarcResult::None);
+        assert_eq!(result.disposition(), Policy::Reject);
+
+        // Unknown clients are not exempted
+        for opts in [
+            opts.clone().remote_ip("198.51.100.1".parse().unwrap()),
+            opts.clone(),
+        ] {
+            let result = resolver
+                .verify_dmarc_with_opts(
+                    &auth_message,
+                    std::slice::from_ref(&dkim),
+                    "example.org",
+                    &spf,
+                    &opts,
+                    |_| None,
+                )
+                .await;
+            assert!(!result.is_forwarder_exempt());
+            assert!(result.is_pass());
+            assert!(Record::new()
+                .with_dmarc_output(&result)
+                .policy_override_reason()
+                .is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_null_sender() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(b"v=DMARC1; p=reject; aspf=r").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+
+        let auth_message =
+            AuthenticatedMessage::parse(b"From: MAILER-DAEMON@example.org\r\n\r\n").unwrap();
+        let helo_spf = |helo: &str| SpfOutput {
+            helo: helo.to_string(),
+            ..spf_output(SpfResult::Pass, helo)
+        };
+        let signature = Signature {
+            d: "example.org".into(),
+            ..Default::default()
+        };
+        let dkim = dkim_output(DkimResult::Pass, &signature);
+
+        // SPF aligns through the HELO identity
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "", &helo_spf("mx.example.org"))
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::Pass);
+        assert!(result.is_pass());
+
+        // An unrelated HELO identity does not align
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "", &helo_spf("mx.example.net"))
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::Fail(Error::NotAligned));
+        assert!(!result.is_pass());
+
+        // Without a HELO identity SPF does not align and DKIM decides
+        let result = resolver
+            .verify_dmarc(&auth_message, &[], "", &helo_spf(""))
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::Fail(Error::NotAligned));
+        assert!(!result.is_pass());
+        let result = resolver
+            .verify_dmarc(
+                &auth_message,
+                std::slice::from_ref(&dkim),
+                "",
+                &helo_spf(""),
+            )
+            .await;
+        assert_eq!(result.spf_result(), &DmarcResult::Fail(Error::NotAligned));
+        assert_eq!(result.dkim_result(), &DmarcResult::Pass);
+        assert!(result.is_pass());
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_report_address() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        #[cfg(any(test, feature = "test"))]
+        resolver.txt_add(
+            "example.org._report._dmarc.external.org.",
+            Dmarc::parse(b"v=DMARC1").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let uris = vec![
+            URI::new("dmarc@example.org", 0),
+            URI::new("dmarc@external.org", 0),
+            URI::new("domain@other.org", 0),
+        ];
+
+        assert_eq!(
+            resolver
+                .verify_dmarc_report_address("example.org", &uris)
+                .await
+                .unwrap(),
+            vec![
+                &URI::new("dmarc@example.org", 0),
+                &URI::new("dmarc@external.org", 0),
+            ]
+        );
+
+        // Order is preserved when looking up more addresses than the concurrency limit
+        let uris = (0..20)
+            .map(|n| {
+                if n % 3 == 0 {
+                    URI::new("dmarc@external.org", n)
+                } else {
+                    URI::new(format!("dmarc@other{n}.org"), n)
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resolver
+                .verify_dmarc_report_address("example.org", &uris)
+                .await
+                .unwrap(),
+            uris.iter().step_by(3).collect::<Vec<_>>()
+        );
+
+        // DNS errors abort the validation
+        let uris = vec![
+            URI::new("dmarc@external.org", 0),
+            URI::new("dmarc@_dns_error.org", 0),
+        ];
+        assert_eq!(
+            resolver
+                .verify_dmarc_report_address("example.org", &uris)
+                .await,
+            None
+        );
+        assert!(matches!(
+            resolver
+                .try_verify_dmarc_report_address("example.org", &uris)
+                .await,
+            Err(Error::DnsError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dmarc_verify_report_uris() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "_dmarc.example.org.",
+            Dmarc::parse(
+                b"v=DMARC1; p=reject; rua=mailto:agg@example.org!10m,mailto:agg@external.org; ruf=mailto:fail@example.org",
+            )
+            .unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let spf = spf_output(SpfResult::Pass, "example.org");
+
+        let message = AuthenticatedMessage::parse(b"From: a@example.org\r\n\r\n").unwrap();
+        let output = resolver
+            .verify_dmarc(&message, &[], "example.org", &spf)
+            .await;
+        assert_eq!(
+            output.rua(),
+            [
+                URI::new("agg@example.org", 10 * 1024 * 1024),
+                URI::new("agg@external.org", 0)
+            ]
+        );
+        assert_eq!(output.ruf(), [URI::new("fail@example.org", 0)]);
+        assert_eq!(
+            resolver
+                .verify_dmarc_report_address(output.domain(), output.rua())
+                .await
+                .unwrap(),
+            vec![&URI::new("agg@example.org", 10 * 1024 * 1024)]
+        );
+
+        // Failing to authorize report addresses does not affect the verdict
+        resolver.txt_add(
+            "_dmarc.example.net.",
+            Dmarc::parse(b"v=DMARC1; p=reject; rua=mailto:agg@_dns_error.org").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        let message = AuthenticatedMessage::parse(b"From: a@example.net\r\n\r\n").unwrap();
+        let output = resolver
+            .verify_dmarc(&message, &[], "example.net", &spf)
+            .await;
+        assert!(output.is_pass());
+        assert_eq!(output.policy(), Policy::Reject);
+        assert!(matches!(
+            resolver
+                .try_verify_dmarc_report_address(output.domain(), output.rua())
+                .await,
+            Err(Error::DnsError(_))
+        ));
+
+        // No record, no URIs
+        let message = AuthenticatedMessage::parse(b"From: a@example.com\r\n\r\n").unwrap();
+        let output = resolver
+            .verify_dmarc(&message, &[], "example.com", &spf)
+            .await;
+        assert!(output.rua().is_empty());
+        assert!(output.ruf().is_empty());
+    }
+
+    fn spf_output(result: SpfResult, domain: &str) -> SpfOutput {
+        SpfOutput {
+            result,
+            domain: domain.to_string(),
+            report: None,
+            explanation: None,
+            mail_from: "".to_string(),
+            helo: "".to_string(),
+            helo_result: None,
+        }
+    }
+
+    fn dkim_output(result: DkimResult, signature: &Signature) -> DkimOutput<'_> {
+        DkimOutput {
+            result,
+            ..DkimOutput::pass().with_signature(signature)
+        }
     }
 }
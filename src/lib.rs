@@ -122,7 +122,8 @@
 //!     let dkim_result = resolver.verify_dkim(&authenticated_message).await;
 //!
 //!     // Build Authenticated-Results header
-//!     let auth_results = AuthenticationResults::new("mx.mydomain.org")
+//!     let auth_results = AuthenticationResults::try_new("mx.mydomain.org")
+//!         .unwrap()
 //!         .with_dkim_result(&dkim_result, "sender@example.org")
 //!         .with_arc_result(&arc_result, "127.0.0.1".parse().unwrap());
 //!
@@ -263,11 +264,19 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::Arc,
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use arc::Set;
-use common::{crypto::HashAlgorithm, headers::Header, lru::LruCache, verify::DomainKey};
+use common::{
+    clock::Clock,
+    crypto::HashAlgorithm,
+    headers::Header,
+    lru::LruCache,
+    resolver::DnsAnswers,
+    retry::{DnsFailures, DnsRetryPolicy},
+    verify::DomainKey,
+};
 use dkim::{Atps, Canonicalization, DomainKeyReport};
 use dmarc::Dmarc;
 use hickory_resolver::{
@@ -297,6 +306,13 @@ pub struct Resolver {
     pub(crate) cache_ipv4: LruCache<String, Arc<Vec<Ipv4Addr>>>,
     pub(crate) cache_ipv6: LruCache<String, Arc<Vec<Ipv6Addr>>>,
     pub(crate) cache_ptr: LruCache<IpAddr, Arc<Vec<String>>>,
+    pub(crate) cache_spf: LruCache<(IpAddr, String), SpfOutput>,
+    pub(crate) spf_cache_ttl: Duration,
+    pub(crate) min_ttl: Duration,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) answers: Option<Arc<DnsAnswers>>,
+    pub(crate) dns_retry: DnsRetryPolicy,
+    pub(crate) dns_failures: DnsFailures,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -334,19 +350,25 @@ pub struct MX {
 }
 
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct AuthenticatedMessage<'x> {
     pub headers: Vec<(&'x [u8], &'x [u8])>,
     pub from: Vec<String>,
     pub raw_message: &'x [u8],
     pub body_offset: usize,
+    pub(crate) body: &'x [u8],
+    pub(crate) body_len: Vec<(Canonicalization, u64)>,
     pub body_hashes: Vec<(Canonicalization, HashAlgorithm, u64, Vec<u8>)>,
     pub dkim_headers: Vec<Header<'x, crate::Result<dkim::Signature>>>,
     pub ams_headers: Vec<Header<'x, crate::Result<arc::Signature>>>,
     pub as_headers: Vec<Header<'x, crate::Result<arc::Seal>>>,
     pub aar_headers: Vec<Header<'x, crate::Result<arc::Results>>>,
     pub received_headers_count: usize,
+    pub from_headers_count: usize,
     pub date_header_present: bool,
     pub message_id_header_present: bool,
+    pub(crate) date: Option<&'x [u8]>,
+    pub(crate) message_id: Option<&'x [u8]>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -378,12 +400,23 @@ pub struct DkimOutput<'x> {
     signature: Option<&'x dkim::Signature>,
     report: Option<String>,
     is_atps: bool,
+    expired_at_verification: bool,
+    warnings: Vec<DkimWarning>,
+    canonical_form: Option<Box<(Vec<u8>, Vec<u8>)>>,
+    key_record: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DkimWarning {
+    /// Signature tag names were not lowercase and had to be normalized.
+    MixedCaseTags,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ArcOutput<'x> {
     result: DkimResult,
     set: Vec<Set<'x>>,
+    key_record: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -403,6 +436,9 @@ pub struct SpfOutput {
     domain: String,
     report: Option<String>,
     explanation: Option<String>,
+    mail_from: String,
+    helo: String,
+    helo_result: Option<SpfResult>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -412,6 +448,14 @@ pub struct DmarcOutput {
     domain: String,
     policy: dmarc::Policy,
     record: Option<Arc<Dmarc>>,
+    policy_override: Option<dmarc::Override>,
+    dkim_required: bool,
+    record_error: Option<crate::Error>,
+    from_count: usize,
+    forwarder_exempt: bool,
+    sampled_out: bool,
+    spf_aligned: Option<dmarc::Alignment>,
+    dkim_aligned: Option<dmarc::Alignment>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -455,12 +499,14 @@ pub enum Error {
     UnsupportedAlgorithm,
     UnsupportedCanonicalization,
     UnsupportedKeyType,
+    #[deprecated(note = "body hash mismatches are reported as `Error::BodyHashMismatch`")]
     FailedBodyHashMatch,
     FailedVerification,
     FailedAuidMatch,
     RevokedPublicKey,
     IncompatibleAlgorithms,
     SignatureExpired,
+    SignatureTimeInFuture,
     SignatureLength,
     DnsError(String),
     DnsRecordNotFound(ResponseCode),
@@ -471,11 +517,25 @@ pub enum Error {
     ArcBrokenChain,
     NotAligned,
     InvalidRecordType,
+    DuplicateTag,
+    AlgorithmMismatch,
+    SignatureLimitExceeded,
+    AlgorithmNotAllowed,
+    UnsupportedQueryMethod,
+    MultipleFromHeaders,
+    InvalidBodyHashLength,
+    BodyLengthExceeded,
+    InvalidAuthservId,
+    BodyHashMismatch {
+        expected: String,
+        computed: String,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl Display for Error {
+    #[allow(deprecated)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::ParseError => write!(f, "Parse error"),
@@ -502,6 +562,7 @@ impl Display for Error {
             ),
             Error::FailedVerification => write!(f, "Signature verification failed"),
             Error::SignatureExpired => write!(f, "Signature expired"),
+            Error::SignatureTimeInFuture => write!(f, "Signature timestamp is in the future"),
             Error::SignatureLength => write!(f, "Insecure 'l=' tag found in Signature"),
             Error::FailedAuidMatch => write!(f, "AUID does not match domain name"),
             Error::ArcInvalidInstance(i) => {
@@ -515,32 +576,105 @@ impl Display for Error {
             Error::DnsError(err) => write!(f, "DNS resolution error: {err}"),
             Error::DnsRecordNotFound(code) => write!(f, "DNS record not found: {code}"),
             Error::NotAligned => write!(f, "Policy not aligned"),
+            Error::DuplicateTag => write!(f, "Duplicate tag found in record"),
+            Error::AlgorithmMismatch => {
+                write!(f, "Signature algorithm does not match the public key type")
+            }
+            Error::SignatureLimitExceeded => {
+                write!(f, "Too many DKIM signatures, signature not verified")
+            }
+            Error::AlgorithmNotAllowed => {
+                write!(f, "Signature algorithm not allowed, signature not verified")
+            }
+            Error::MultipleFromHeaders => write!(f, "Multiple From headers found"),
+            Error::InvalidBodyHashLength => {
+                write!(f, "Body hash length does not match the signature algorithm")
+            }
+            Error::BodyLengthExceeded => {
+                write!(f, "Signed body length (l=) exceeds the length of the body")
+            }
+            Error::InvalidAuthservId => {
+                write!(f, "Invalid authserv-id in Authentication-Results header")
+            }
+            Error::UnsupportedQueryMethod => {
+                write!(f, "Unsupported query method in DKIM Signature")
+            }
+            Error::BodyHashMismatch { expected, computed } => write!(
+                f,
+                "Calculated body hash {computed} does not match signature hash {expected}"
+            ),
+        }
+    }
+}
+
+impl SpfResult {
+    /// Returns the result keyword defined in RFC 7208, Section 2.6.
+    pub fn as_keyword(&self) -> &'static str {
+        match self {
+            SpfResult::Pass => "pass",
+            SpfResult::Fail => "fail",
+            SpfResult::SoftFail => "softfail",
+            SpfResult::Neutral => "neutral",
+            SpfResult::TempError => "temperror",
+            SpfResult::PermError => "permerror",
+            SpfResult::None => "none",
+        }
+    }
+}
+
+impl IprevResult {
+    /// Returns the result keyword defined in RFC 8601, Section 2.7.3.
+    pub fn as_keyword(&self) -> &'static str {
+        match self {
+            IprevResult::Pass => "pass",
+            IprevResult::Fail(_) => "fail",
+            IprevResult::TempError(_) => "temperror",
+            IprevResult::PermError(_) => "permerror",
+            IprevResult::None => "none",
+        }
+    }
+}
+
+impl DkimResult {
+    /// Returns the result keyword defined in RFC 8601, Section 2.7.1.
+    pub fn as_keyword(&self) -> &'static str {
+        match self {
+            DkimResult::Pass => "pass",
+            DkimResult::Neutral(_) => "neutral",
+            DkimResult::Fail(_) => "fail",
+            DkimResult::PermError(_) => "permerror",
+            DkimResult::TempError(_) => "temperror",
+            DkimResult::None => "none",
+        }
+    }
+}
+
+impl DmarcResult {
+    /// Returns the result keyword defined in RFC 7489, Section 11.2.
+    pub fn as_keyword(&self) -> &'static str {
+        match self {
+            DmarcResult::Pass => "pass",
+            DmarcResult::Fail(_) => "fail",
+            DmarcResult::TempError(_) => "temperror",
+            DmarcResult::PermError(_) => "permerror",
+            DmarcResult::None => "none",
         }
     }
 }
 
 impl Display for SpfResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match self {
-            SpfResult::Pass => "Pass",
-            SpfResult::Fail => "Fail",
-            SpfResult::SoftFail => "SoftFail",
-            SpfResult::Neutral => "Neutral",
-            SpfResult::TempError => "TempError",
-            SpfResult::PermError => "PermError",
-            SpfResult::None => "None",
-        })
+        f.write_str(self.as_keyword())
     }
 }
 
 impl Display for IprevResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            IprevResult::Pass => f.write_str("pass"),
-            IprevResult::Fail(err) => write!(f, "fail; {err}"),
-            IprevResult::TempError(err) => write!(f, "temp error; {err}"),
-            IprevResult::PermError(err) => write!(f, "perm error; {err}"),
-            IprevResult::None => f.write_str("none"),
+            IprevResult::Fail(err) | IprevResult::TempError(err) | IprevResult::PermError(err) => {
+                write!(f, "{}; {err}", self.as_keyword())
+            }
+            IprevResult::Pass | IprevResult::None => f.write_str(self.as_keyword()),
         }
     }
 }
@@ -548,12 +682,11 @@ impl Display for IprevResult {
 impl Display for DkimResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            DkimResult::Pass => f.write_str("pass"),
-            DkimResult::Fail(err) => write!(f, "fail; {err}"),
-            DkimResult::Neutral(err) => write!(f, "neutral; {err}"),
-            DkimResult::TempError(err) => write!(f, "temp error; {err}"),
-            DkimResult::PermError(err) => write!(f, "perm error; {err}"),
-            DkimResult::None => f.write_str("none"),
+            DkimResult::Neutral(err)
+            | DkimResult::Fail(err)
+            | DkimResult::TempError(err)
+            | DkimResult::PermError(err) => write!(f, "{}; {err}", self.as_keyword()),
+            DkimResult::Pass | DkimResult::None => f.write_str(self.as_keyword()),
         }
     }
 }
@@ -561,11 +694,10 @@ impl Display for DkimResult {
 impl Display for DmarcResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            DmarcResult::Pass => f.write_str("pass"),
-            DmarcResult::Fail(err) => write!(f, "fail; {err}"),
-            DmarcResult::TempError(err) => write!(f, "temp error; {err}"),
-            DmarcResult::PermError(err) => write!(f, "perm error; {err}"),
-            DmarcResult::None => f.write_str("none"),
+            DmarcResult::Fail(err) | DmarcResult::TempError(err) | DmarcResult::PermError(err) => {
+                write!(f, "{}; {err}", self.as_keyword())
+            }
+            DmarcResult::Pass | DmarcResult::None => f.write_str(self.as_keyword()),
         }
     }
 }
@@ -603,6 +735,9 @@ impl Default for SpfOutput {
             domain: Default::default(),
             report: Default::default(),
             explanation: Default::default(),
+            mail_from: Default::default(),
+            helo: Default::default(),
+            helo_result: None,
         }
     }
 }
@@ -634,6 +769,13 @@ impl Clone for Resolver {
             cache_ipv4: Mutex::new(self.cache_ipv4.lock().clone()),
             cache_ipv6: Mutex::new(self.cache_ipv6.lock().clone()),
             cache_ptr: Mutex::new(self.cache_ptr.lock().clone()),
+            cache_spf: Mutex::new(self.cache_spf.lock().clone()),
+            spf_cache_ttl: self.spf_cache_ttl,
+            min_ttl: self.min_ttl,
+            clock: self.clock.clone(),
+            answers: self.answers.clone(),
+            dns_retry: self.dns_retry.clone(),
+            dns_failures: self.dns_failures.clone(),
         }
     }
 }
@@ -105,7 +105,7 @@ impl<'x> Feedback<'x> {
     ) -> io::Result<String> {
         let mut buf = Vec::new();
         self.write_rfc5322(from, to, subject, &mut buf)?;
-        String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        String::from_utf8(buf).map_err(io::Error::other)
     }
 
     pub fn to_arf(&self) -> String {
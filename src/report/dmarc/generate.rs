@@ -89,7 +89,7 @@ impl Report {
     ) -> io::Result<String> {
         let mut buf = Vec::new();
         self.write_rfc5322(submitter, from, to, &mut buf)?;
-        String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        String::from_utf8(buf).map_err(io::Error::other)
     }
 
     pub fn to_xml(&self) -> String {
@@ -428,10 +428,14 @@ fn escape_xml(text: &str) -> Cow<'_, str> {
 
 #[cfg(test)]
 mod test {
-    use crate::report::{
-        ActionDisposition, Alignment, DKIMAuthResult, Disposition, DkimResult, DmarcResult,
-        PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult, SPFDomainScope,
-        SpfResult,
+    use crate::{
+        dkim::Signature,
+        report::{
+            ActionDisposition, Alignment, AuthResult, DKIMAuthResult, Disposition, DkimResult,
+            DmarcResult, PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult,
+            SPFDomainScope, SpfResult,
+        },
+        DkimOutput, Error, SpfOutput,
     };
 
     #[test]
@@ -530,4 +534,78 @@ mod test {
 
         assert_eq!(report, parsed_report);
     }
+
+    #[test]
+    fn dmarc_report_auth_results() {
+        let signature_pass = Signature {
+            d: "example.org".into(),
+            s: "default".into(),
+            ..Default::default()
+        };
+        let signature_fail = Signature {
+            d: "example.com".into(),
+            s: "sel2".into(),
+            ..Default::default()
+        };
+        let dkim_output = [
+            DkimOutput::pass().with_signature(&signature_pass),
+            DkimOutput::fail(Error::FailedVerification).with_signature(&signature_fail),
+            // Signatures that failed to parse are not reported
+            DkimOutput::neutral(Error::ParseError),
+        ];
+        let expected_dkim = vec![
+            DKIMAuthResult::new()
+                .with_domain("example.org")
+                .with_selector("default")
+                .with_result(DkimResult::Pass),
+            DKIMAuthResult::new()
+                .with_domain("example.com")
+                .with_selector("sel2")
+                .with_result(DkimResult::Fail)
+                .with_human_result("Signature verification failed"),
+        ];
+
+        for (spf_output, expected_scope) in [
+            (
+                SpfOutput {
+                    result: crate::SpfResult::Pass,
+                    domain: "example.org".to_string(),
+                    report: None,
+                    explanation: None,
+                    mail_from: "jdoe@example.org".to_string(),
+                    helo: "mx.example.org".to_string(),
+                    helo_result: None,
+                },
+                SPFDomainScope::MailFrom,
+            ),
+            (
+                SpfOutput {
+                    result: crate::SpfResult::Pass,
+                    domain: "mx.example.org".to_string(),
+                    report: None,
+                    explanation: None,
+                    mail_from: "postmaster@mx.example.org".to_string(),
+                    helo: "mx.example.org".to_string(),
+                    helo_result: None,
+                },
+                SPFDomainScope::Helo,
+            ),
+        ] {
+            let auth_results = AuthResult::from_outputs(&dkim_output, &spf_output);
+            assert_eq!(auth_results.dkim(), expected_dkim);
+            assert_eq!(
+                auth_results.spf(),
+                [SPFAuthResult::new()
+                    .with_domain(spf_output.domain())
+                    .with_scope(expected_scope)
+                    .with_result(SpfResult::Pass)]
+            );
+            assert_eq!(
+                Record::new()
+                    .with_auth_results(auth_results.clone())
+                    .auth_results(),
+                &auth_results
+            );
+        }
+    }
 }
@@ -10,6 +10,7 @@
 
 pub mod generate;
 pub mod parse;
+pub mod schedule;
 
 use std::fmt::Write;
 use std::net::IpAddr;
@@ -17,9 +18,9 @@ use std::net::IpAddr;
 use crate::{
     dmarc::Dmarc,
     report::{
-        ActionDisposition, Alignment, DKIMAuthResult, Disposition, DkimResult, DmarcResult,
-        PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult, SPFDomainScope,
-        SpfResult,
+        ActionDisposition, Alignment, AuthResult, DKIMAuthResult, Disposition, DkimResult,
+        DmarcResult, PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult,
+        SPFDomainScope, SpfResult,
     },
     ArcOutput, DkimOutput, DmarcOutput, SpfOutput,
 };
@@ -200,66 +201,56 @@ impl Record {
     }
 
     pub fn with_dkim_output(mut self, dkim_output: &[DkimOutput]) -> Self {
-        for dkim in dkim_output {
-            if let Some(signature) = &dkim.signature {
-                let (result, human_result) = match &dkim.result {
-                    crate::DkimResult::Pass => (DkimResult::Pass, None),
-                    crate::DkimResult::Neutral(err) => {
-                        (DkimResult::Neutral, err.to_string().into())
-                    }
-                    crate::DkimResult::Fail(err) => (DkimResult::Fail, err.to_string().into()),
-                    crate::DkimResult::PermError(err) => {
-                        (DkimResult::PermError, err.to_string().into())
-                    }
-                    crate::DkimResult::TempError(err) => {
-                        (DkimResult::TempError, err.to_string().into())
-                    }
-                    crate::DkimResult::None => (DkimResult::None, None),
-                };
-
-                self.auth_results.dkim.push(DKIMAuthResult {
-                    domain: signature.d.to_string(),
-                    selector: signature.s.to_string(),
-                    result,
-                    human_result,
-                });
-            }
-        }
+        self.auth_results.dkim.extend(
+            dkim_output
+                .iter()
+                .filter_map(DKIMAuthResult::from_dkim_output),
+        );
         self
     }
 
     pub fn with_spf_output(mut self, spf_output: &SpfOutput, scope: SPFDomainScope) -> Self {
-        self.auth_results.spf.push(SPFAuthResult {
-            domain: spf_output.domain.to_string(),
-            scope,
-            result: match spf_output.result {
-                crate::SpfResult::Pass => SpfResult::Pass,
-                crate::SpfResult::Fail => SpfResult::Fail,
-                crate::SpfResult::SoftFail => SpfResult::SoftFail,
-                crate::SpfResult::Neutral => SpfResult::Neutral,
-                crate::SpfResult::TempError => SpfResult::TempError,
-                crate::SpfResult::PermError => SpfResult::PermError,
-                crate::SpfResult::None => SpfResult::None,
-            },
-            human_result: None,
-        });
+        self.auth_results
+            .spf
+            .push(SPFAuthResult::from(spf_output).with_scope(scope));
         self
     }
 
+    /// Replaces the `<auth_results>` of this record, see
+    /// [`AuthResult::from_outputs`].
+    pub fn with_auth_results(mut self, auth_results: AuthResult) -> Self {
+        self.auth_results = auth_results;
+        self
+    }
+
+    pub fn auth_results(&self) -> &AuthResult {
+        &self.auth_results
+    }
+
     pub fn with_dmarc_output(mut self, dmarc_output: &DmarcOutput) -> Self {
-        self.row.policy_evaluated.disposition = if dmarc_output.dkim_result
-            == crate::DmarcResult::Pass
-            || dmarc_output.spf_result == crate::DmarcResult::Pass
-        {
-            ActionDisposition::Pass
-        } else {
-            match dmarc_output.policy {
-                crate::dmarc::Policy::None => ActionDisposition::None,
-                crate::dmarc::Policy::Quarantine => ActionDisposition::Quarantine,
-                crate::dmarc::Policy::Reject => ActionDisposition::Reject,
-                crate::dmarc::Policy::Unspecified => ActionDisposition::None,
-            }
-        };
+        if dmarc_output.forwarder_exempt {
+            self.row
+                .policy_evaluated
+                .reason
+                .push(PolicyOverrideReason::new(PolicyOverride::Forwarded));
+        }
+        if let Some(policy_override) = &dmarc_output.policy_override {
+            self.row
+                .policy_evaluated
+                .reason
+                .push(policy_override.reason.clone());
+        } else if dmarc_output.sampled_out {
+            self.row
+                .policy_evaluated
+                .reason
+                .push(PolicyOverrideReason::new(PolicyOverride::SampledOut));
+        }
+        self.row.policy_evaluated.disposition =
+            if dmarc_output.policy_override.is_none() && dmarc_output.is_pass() {
+                ActionDisposition::Pass
+            } else {
+                dmarc_output.disposition().into()
+            };
         self.row.policy_evaluated.dkim = (&dmarc_output.dkim_result).into();
         self.row.policy_evaluated.spf = (&dmarc_output.spf_result).into();
         self
@@ -408,11 +399,59 @@ impl PolicyPublished {
     }
 }
 
+impl AuthResult {
+    pub fn new() -> Self {
+        AuthResult::default()
+    }
+
+    /// Builds the `<auth_results>` of an aggregate report record with one
+    /// `<dkim>` row per verified signature, which includes its domain,
+    /// selector and result, and one `<spf>` row whose scope is `helo` when
+    /// `spf_output` is the result of a HELO check and `mfrom` otherwise.
+    /// Signatures that could not be parsed have no domain and are omitted.
+    pub fn from_outputs(dkim_output: &[DkimOutput], spf_output: &SpfOutput) -> Self {
+        AuthResult {
+            dkim: dkim_output
+                .iter()
+                .filter_map(DKIMAuthResult::from_dkim_output)
+                .collect(),
+            spf: vec![SPFAuthResult::from(spf_output)],
+        }
+    }
+
+    pub fn dkim(&self) -> &[DKIMAuthResult] {
+        &self.dkim
+    }
+
+    pub fn spf(&self) -> &[SPFAuthResult] {
+        &self.spf
+    }
+}
+
 impl DKIMAuthResult {
     pub fn new() -> Self {
         DKIMAuthResult::default()
     }
 
+    fn from_dkim_output(dkim: &DkimOutput) -> Option<Self> {
+        let signature = dkim.signature?;
+        let (result, human_result) = match &dkim.result {
+            crate::DkimResult::Pass => (DkimResult::Pass, None),
+            crate::DkimResult::Neutral(err) => (DkimResult::Neutral, err.to_string().into()),
+            crate::DkimResult::Fail(err) => (DkimResult::Fail, err.to_string().into()),
+            crate::DkimResult::PermError(err) => (DkimResult::PermError, err.to_string().into()),
+            crate::DkimResult::TempError(err) => (DkimResult::TempError, err.to_string().into()),
+            crate::DkimResult::None => (DkimResult::None, None),
+        };
+
+        Some(DKIMAuthResult {
+            domain: signature.d.to_string(),
+            selector: signature.s.to_string(),
+            result,
+            human_result,
+        })
+    }
+
     pub fn domain(&self) -> &str {
         &self.domain
     }
@@ -514,6 +553,43 @@ impl PolicyOverrideReason {
     }
 }
 
+impl From<&SpfOutput> for SPFAuthResult {
+    fn from(spf_output: &SpfOutput) -> Self {
+        SPFAuthResult {
+            domain: spf_output.domain.to_string(),
+            scope: spf_output.into(),
+            result: match spf_output.result {
+                crate::SpfResult::Pass => SpfResult::Pass,
+                crate::SpfResult::Fail => SpfResult::Fail,
+                crate::SpfResult::SoftFail => SpfResult::SoftFail,
+                crate::SpfResult::Neutral => SpfResult::Neutral,
+                crate::SpfResult::TempError => SpfResult::TempError,
+                crate::SpfResult::PermError => SpfResult::PermError,
+                crate::SpfResult::None => SpfResult::None,
+            },
+            human_result: None,
+        }
+    }
+}
+
+impl From<&SpfOutput> for SPFDomainScope {
+    fn from(spf_output: &SpfOutput) -> Self {
+        if spf_output.helo.is_empty() && spf_output.mail_from.is_empty() {
+            SPFDomainScope::Unspecified
+        } else if spf_output.domain.eq_ignore_ascii_case(&spf_output.helo)
+            && (spf_output.mail_from.is_empty()
+                || spf_output
+                    .mail_from
+                    .strip_prefix("postmaster@")
+                    .is_some_and(|domain| domain.eq_ignore_ascii_case(&spf_output.helo)))
+        {
+            SPFDomainScope::Helo
+        } else {
+            SPFDomainScope::MailFrom
+        }
+    }
+}
+
 impl From<&crate::DmarcResult> for DmarcResult {
     fn from(result: &crate::DmarcResult) -> Self {
         match result {
@@ -542,3 +618,14 @@ impl From<&crate::dmarc::Policy> for Disposition {
         }
     }
 }
+
+impl From<crate::dmarc::Policy> for ActionDisposition {
+    fn from(policy: crate::dmarc::Policy) -> Self {
+        match policy {
+            crate::dmarc::Policy::None => ActionDisposition::None,
+            crate::dmarc::Policy::Quarantine => ActionDisposition::Quarantine,
+            crate::dmarc::Policy::Reject => ActionDisposition::Reject,
+            crate::dmarc::Policy::Unspecified => ActionDisposition::None,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::{
+    dmarc::Dmarc,
+    report::{PolicyPublished, Record, Report, ReportBucket, ReportScheduler, ScheduledReports},
+};
+
+const DAY: u64 = 86400;
+const MIN_INTERVAL: u64 = 3600;
+
+impl ReportScheduler {
+    pub fn new(org_name: impl Into<String>, email: impl Into<String>) -> Self {
+        ReportScheduler {
+            org_name: org_name.into(),
+            email: email.into(),
+            extra_contact_info: None,
+            buckets: Vec::new(),
+        }
+    }
+
+    pub fn with_extra_contact_info(mut self, extra_contact_info: impl Into<String>) -> Self {
+        self.extra_contact_info = Some(extra_contact_info.into());
+        self
+    }
+
+    /// Adds an evaluation record for a domain and its DMARC policy.
+    /// Records are ignored if the policy does not request aggregate reports.
+    /// Identical records within the same reporting interval are merged and
+    /// their counts added.
+    pub fn add_record(&mut self, domain: &str, dmarc: &Dmarc, record: Record, timestamp: u64) {
+        if dmarc.rua.is_empty() {
+            return;
+        }
+
+        let (begin, end) = report_interval(dmarc.ri, timestamp);
+        let bucket = if let Some(bucket) = self.buckets.iter_mut().find(|b| {
+            b.report.report_metadata.date_range.begin == begin && b.report.domain() == domain
+        }) {
+            bucket
+        } else {
+            let mut report = Report::new()
+                .with_org_name(&self.org_name)
+                .with_email(&self.email)
+                .with_report_id(format!("{domain}.{begin}"))
+                .with_date_range_begin(begin)
+                .with_date_range_end(end)
+                .with_policy_published(PolicyPublished::from_record(domain, dmarc));
+            if let Some(extra_contact_info) = &self.extra_contact_info {
+                report = report.with_extra_contact_info(extra_contact_info);
+            }
+            self.buckets.push(ReportBucket {
+                rua: dmarc.rua.clone(),
+                report,
+            });
+            self.buckets.last_mut().unwrap()
+        };
+
+        let count = std::cmp::max(record.row.count, 1);
+        if let Some(existing) = bucket.report.record.iter_mut().find(|r| {
+            r.row.source_ip == record.row.source_ip
+                && r.row.policy_evaluated == record.row.policy_evaluated
+                && r.identifiers == record.identifiers
+                && r.auth_results == record.auth_results
+                && r.extensions == record.extensions
+        }) {
+            existing.row.count = std::cmp::max(existing.row.count, 1).saturating_add(count);
+        } else {
+            bucket.report.add_record(record.with_count(count));
+        }
+    }
+
+    /// Removes and returns the reports whose interval ended at or before
+    /// `now`, grouped by reporting address. Reports for domains sharing
+    /// a reporting address are delivered together.
+    pub fn due_reports(&mut self, now: u64) -> Vec<ScheduledReports> {
+        let mut due = Vec::new();
+        let mut pending = Vec::with_capacity(self.buckets.len());
+        for bucket in std::mem::take(&mut self.buckets) {
+            if bucket.report.date_range_end() <= now {
+                due.push(bucket);
+            } else {
+                pending.push(bucket);
+            }
+        }
+        self.buckets = pending;
+
+        due.sort_by(|a, b| {
+            (a.report.date_range_begin(), a.report.domain())
+                .cmp(&(b.report.date_range_begin(), b.report.domain()))
+        });
+
+        let mut scheduled: Vec<ScheduledReports> = Vec::new();
+        for bucket in due {
+            for uri in bucket.rua {
+                if let Some(entry) = scheduled.iter_mut().find(|s| s.uri == uri) {
+                    entry.reports.push(bucket.report.clone());
+                } else {
+                    scheduled.push(ScheduledReports {
+                        uri,
+                        reports: vec![bucket.report.clone()],
+                    });
+                }
+            }
+        }
+
+        scheduled
+    }
+
+    /// Returns the time at which the next report becomes due, if any.
+    pub fn next_due(&self) -> Option<u64> {
+        self.buckets.iter().map(|b| b.report.date_range_end()).min()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+}
+
+/// Returns the reporting interval containing `timestamp`. Intervals are
+/// aligned to midnight UTC and never span more than one day. Requested
+/// intervals shorter than one hour are rounded up.
+fn report_interval(ri: u32, timestamp: u64) -> (u64, u64) {
+    let ri = (ri as u64).clamp(MIN_INTERVAL, DAY);
+    let day = timestamp - (timestamp % DAY);
+    let begin = day + ((timestamp - day) / ri) * ri;
+    (begin, std::cmp::min(begin + ri, day + DAY))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        common::parse::TxtRecordParser,
+        dmarc::{Dmarc, URI},
+        report::{ActionDisposition, Record, ReportScheduler},
+    };
+
+    use super::report_interval;
+
+    #[test]
+    fn dmarc_report_interval() {
+        // 2023-01-01T00:00:00Z
+        let midnight = 1672531200;
+
+        for (ri, timestamp, expected) in [
+            (86400, midnight + 5000, (midnight, midnight + 86400)),
+            (3600, midnight + 5000, (midnight + 3600, midnight + 7200)),
+            (60, midnight + 5000, (midnight + 3600, midnight + 7200)),
+            (7 * 86400, midnight + 5000, (midnight, midnight + 86400)),
+            (
+                10 * 3600,
+                midnight + 80000,
+                (midnight + 72000, midnight + 86400),
+            ),
+        ] {
+            assert_eq!(report_interval(ri, timestamp), expected, "ri={ri}");
+        }
+    }
+
+    #[test]
+    fn dmarc_report_scheduler() {
+        let midnight = 1672531200;
+        let daily =
+            Dmarc::parse(b"v=DMARC1; p=reject; rua=mailto:dmarc@reports.example.net").unwrap();
+        let hourly = Dmarc::parse(
+            b"v=DMARC1; p=none; ri=3600; rua=mailto:dmarc@reports.example.net,mailto:dmarc@example.com",
+        )
+        .unwrap();
+        let no_rua = Dmarc::parse(b"v=DMARC1; p=none").unwrap();
+
+        let record = Record::new()
+            .with_source_ip("192.0.2.1".parse().unwrap())
+            .with_action_disposition(ActionDisposition::Pass)
+            .with_header_from("example.org");
+
+        let mut scheduler = ReportScheduler::new("Example Receiver", "noreply@receiver.org");
+        scheduler.add_record("example.org", &daily, record.clone(), midnight + 100);
+        scheduler.add_record("example.org", &daily, record.clone(), midnight + 60000);
+        scheduler.add_record(
+            "example.org",
+            &daily,
+            record.clone().with_source_ip("192.0.2.2".parse().unwrap()),
+            midnight + 200,
+        );
+        scheduler.add_record("example.com", &hourly, record.clone(), midnight + 100);
+        scheduler.add_record("example.com", &hourly, record.clone(), midnight + 4000);
+        scheduler.add_record("example.net", &no_rua, record.clone(), midnight + 100);
+        assert_eq!(scheduler.next_due(), Some(midnight + 3600));
+
+        // First hourly report is due
+        let due = scheduler.due_reports(midnight + 3600);
+        assert_eq!(due.len(), 2);
+        for scheduled in &due {
+            assert_eq!(scheduled.reports.len(), 1);
+            let report = &scheduled.reports[0];
+            assert_eq!(report.domain(), "example.com");
+            assert_eq!(report.org_name(), "Example Receiver");
+            assert_eq!(report.date_range_begin(), midnight);
+            assert_eq!(report.date_range_end(), midnight + 3600);
+            assert_eq!(report.records().len(), 1);
+        }
+        assert!(scheduler.due_reports(midnight + 3600).is_empty());
+
+        // Daily report shares its reporting address with the hourly one
+        let due = scheduler.due_reports(midnight + 86400);
+        assert!(scheduler.is_empty());
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].uri, URI::new("dmarc@reports.example.net", 0));
+        assert_eq!(
+            due[0]
+                .reports
+                .iter()
+                .map(|r| (r.domain(), r.date_range_begin()))
+                .collect::<Vec<_>>(),
+            vec![("example.org", midnight), ("example.com", midnight + 3600)]
+        );
+        assert_eq!(due[1].uri, URI::new("dmarc@example.com", 0));
+        assert_eq!(due[1].reports.len(), 1);
+
+        let daily_report = &due[0].reports[0];
+        assert_eq!(daily_report.records().len(), 2);
+        assert_eq!(daily_report.records()[0].count(), 2);
+        assert_eq!(daily_report.records()[1].count(), 1);
+    }
+}
@@ -16,6 +16,8 @@ use std::{borrow::Cow, net::IpAddr};
 
 use serde::{Deserialize, Serialize};
 
+use crate::dmarc::URI;
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct DateRange {
     begin: u64,
@@ -199,6 +201,29 @@ pub struct Report {
 
 impl Eq for Report {}
 
+/// Collects DMARC evaluation records and groups them into aggregate
+/// reports by policy domain and reporting interval.
+#[derive(Debug, Clone, Default)]
+pub struct ReportScheduler {
+    org_name: String,
+    email: String,
+    extra_contact_info: Option<String>,
+    buckets: Vec<ReportBucket>,
+}
+
+#[derive(Debug, Clone)]
+struct ReportBucket {
+    rua: Vec<URI>,
+    report: Report,
+}
+
+/// Aggregate reports due for delivery to a reporting address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledReports {
+    pub uri: URI,
+    pub reports: Vec<Report>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     MailParseError,
@@ -292,13 +317,16 @@ pub enum FeedbackType {
 }
 
 impl From<&crate::DkimResult> for AuthFailureType {
+    #[allow(deprecated)]
     fn from(value: &crate::DkimResult) -> Self {
         match value {
             crate::DkimResult::Neutral(err)
             | crate::DkimResult::Fail(err)
             | crate::DkimResult::PermError(err)
             | crate::DkimResult::TempError(err) => match err {
-                crate::Error::FailedBodyHashMatch => AuthFailureType::BodyHash,
+                crate::Error::FailedBodyHashMatch | crate::Error::BodyHashMismatch { .. } => {
+                    AuthFailureType::BodyHash
+                }
                 crate::Error::RevokedPublicKey => AuthFailureType::Revoked,
                 _ => AuthFailureType::Signature,
             },
@@ -10,6 +10,8 @@
 
 use std::{borrow::Cow, net::IpAddr, time::SystemTime};
 
+use crate::common::address::domain_separator;
+
 use super::{Macro, Variable, Variables};
 
 impl Macro {
@@ -91,6 +93,10 @@ impl<'x> Variables<'x> {
         vars
     }
 
+    pub fn set_current_time(&mut self, value: u64) {
+        self.vars[Variable::CurrentTime as usize] = value.to_string().into_bytes().into();
+    }
+
     pub fn set_ip(&mut self, value: &IpAddr) {
         let (v, i, c) = match value {
             IpAddr::V4(ip) => (
@@ -118,20 +124,17 @@ impl<'x> Variables<'x> {
 
     pub fn set_sender(&mut self, value: impl Into<Cow<'x, [u8]>>) {
         let value = value.into();
-        for (pos, ch) in value.as_ref().iter().enumerate() {
-            if ch == &b'@' {
-                if pos > 0 {
-                    self.vars[Variable::SenderLocalPart as usize] = match &value {
-                        Cow::Borrowed(value) => (&value[..pos]).into(),
-                        Cow::Owned(value) => value[..pos].to_vec().into(),
-                    };
-                }
-                self.vars[Variable::SenderDomainPart as usize] = match &value {
-                    Cow::Borrowed(value) => (value.get(pos + 1..).unwrap_or_default()).into(),
-                    Cow::Owned(value) => (value.get(pos + 1..).unwrap_or_default()).to_vec().into(),
+        if let Some(pos) = domain_separator(value.as_ref()) {
+            if pos > 0 {
+                self.vars[Variable::SenderLocalPart as usize] = match &value {
+                    Cow::Borrowed(value) => (&value[..pos]).into(),
+                    Cow::Owned(value) => value[..pos].to_vec().into(),
                 };
-                break;
             }
+            self.vars[Variable::SenderDomainPart as usize] = match &value {
+                Cow::Borrowed(value) => (value.get(pos + 1..).unwrap_or_default()).into(),
+                Cow::Owned(value) => (value.get(pos + 1..).unwrap_or_default()).to_vec().into(),
+            };
         }
 
         self.vars[Variable::Sender as usize] = value;
@@ -153,6 +156,13 @@ impl<'x> Variables<'x> {
         self.vars[Variable::Domain as usize] = value.into();
     }
 
+    /// Returns `true` if any of the given variables was expanded.
+    pub(crate) fn uses_any(&self, names: &[Variable]) -> bool {
+        names
+            .iter()
+            .any(|name| self.used.get() & (1 << *name as u16) != 0)
+    }
+
     pub fn get(
         &self,
         name: Variable,
@@ -162,6 +172,7 @@ impl<'x> Variables<'x> {
         fqdn: bool,
         delimiters: u64,
     ) -> Cow<'_, [u8]> {
+        self.used.set(self.used.get() | (1 << name as u16));
         let var = self.vars[name as usize].as_ref();
         if var.is_empty()
             || (num_parts == 0 && !reverse && !escape && delimiters == 1u64 << (b'.' - b'+'))
@@ -228,6 +239,26 @@ mod test {
 
     use crate::spf::{parse::SPFParser, Variables};
 
+    #[test]
+    fn expand_macro_quoted_local_part() {
+        for (sender, local_part, domain) in [
+            (
+                "\"weird@local\"@example.org",
+                "\"weird@local\"",
+                "example.org",
+            ),
+            ("\"john doe\"@example.org", "\"john doe\"", "example.org"),
+            ("strong-bad@example.org", "strong-bad", "example.org"),
+        ] {
+            let mut vars = Variables::new();
+            vars.set_sender(sender.as_bytes());
+            for (macro_string, expansion) in [("%{l}", local_part), ("%{o}", domain)] {
+                let (m, _) = macro_string.as_bytes().iter().macro_string(false).unwrap();
+                assert_eq!(m.eval(&vars, "", false), expansion, "{sender}");
+            }
+        }
+    }
+
     #[test]
     fn expand_macro() {
         let mut vars = Variables::new();
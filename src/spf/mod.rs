@@ -14,10 +14,11 @@ pub mod verify;
 
 use std::{
     borrow::Cow,
+    cell::Cell,
     net::{Ipv4Addr, Ipv6Addr},
 };
 
-use crate::{is_within_pct, SpfOutput, SpfResult, Version};
+use crate::{is_within_pct, Error, SpfOutput, SpfResult, Version};
 
 /*
       "+" pass
@@ -79,6 +80,25 @@ pub struct Directive {
     pub mechanism: Mechanism,
 }
 
+/// Step of an SPF evaluation, as recorded by
+/// [`Resolver::verify_spf_with_trace`](crate::Resolver::verify_spf_with_trace).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SpfTraceEvent {
+    /// The SPF record of `domain` was fetched for the initial query, an
+    /// `include:` or a `redirect=`. `error` is set if the lookup failed.
+    Record {
+        domain: String,
+        error: Option<Error>,
+    },
+    /// A directive of the record published by `domain` was evaluated.
+    /// An `include:` is reported after the directives of the included record.
+    Directive {
+        domain: String,
+        directive: Directive,
+        matched: bool,
+    },
+}
+
 /*
       s = <sender>
       l = local-part of <sender>
@@ -114,6 +134,7 @@ pub enum Variable {
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Variables<'x> {
     vars: [Cow<'x, [u8]>; 11],
+    used: Cell<u16>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -146,6 +167,61 @@ pub(crate) const RR_FAIL: u8 = 0x02;
 pub(crate) const RR_SOFTFAIL: u8 = 0x04;
 pub(crate) const RR_NEUTRAL_NONE: u8 = 0x08;
 
+pub(crate) const MAX_SPF_TERMS: usize = 128;
+pub(crate) const MAX_SPF_RECORD_LENGTH: usize = 4096;
+
+/// Options controlling how SPF policies are evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpfOptions {
+    pub(crate) max_terms: usize,
+    pub(crate) max_record_length: usize,
+    pub(crate) helo_fallback: bool,
+}
+
+impl Default for SpfOptions {
+    fn default() -> Self {
+        Self {
+            max_terms: MAX_SPF_TERMS,
+            max_record_length: MAX_SPF_RECORD_LENGTH,
+            helo_fallback: false,
+        }
+    }
+}
+
+impl SpfOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the size of the SPF records evaluated, guarding against a
+    /// single oversized record regardless of the DNS lookup limit. Records
+    /// with more than `max_terms` mechanisms and modifiers, or longer than
+    /// `max_length` bytes, evaluate to `permerror` (defaults to 128 terms
+    /// and 4096 bytes). The limits are checked each time a record is
+    /// evaluated, so cached records are subject to them as well.
+    pub fn record_limits(mut self, max_terms: usize, max_length: usize) -> Self {
+        self.max_terms = max_terms;
+        self.max_record_length = max_length;
+        self
+    }
+
+    /// When enabled, the HELO identity is also checked when the MAIL FROM
+    /// domain publishes no SPF record, and its result is recorded in
+    /// [`SpfOutput::helo_result`]. The MAIL FROM result remains the result
+    /// of the output, as DMARC aligns SPF with the MAIL FROM domain only
+    /// (disabled by default).
+    pub fn helo_fallback(mut self, helo_fallback: bool) -> Self {
+        self.helo_fallback = helo_fallback;
+        self
+    }
+
+    /// Whether SPF results evaluated with these options may be shared
+    /// through the SPF result cache.
+    pub(crate) fn is_cacheable(&self) -> bool {
+        self.max_terms == MAX_SPF_TERMS && self.max_record_length == MAX_SPF_RECORD_LENGTH
+    }
+}
+
 impl Directive {
     pub fn new(qualifier: Qualifier, mechanism: Mechanism) -> Self {
         Directive {
@@ -168,6 +244,114 @@ impl Mechanism {
             Mechanism::Exists { macro_string } => macro_string.needs_ptr(),
         }
     }
+
+    /// Length of the mechanism in its shortest text form.
+    fn text_len(&self) -> usize {
+        let cidr_len = |mask: u32, max: u32| {
+            if mask != max {
+                1 + mask.to_string().len()
+            } else {
+                0
+            }
+        };
+        let dual_cidr_len = |ip4_mask: u32, ip6_mask: u128| {
+            cidr_len(ip4_mask.count_ones(), 32)
+                + if ip6_mask != u128::MAX {
+                    1 + cidr_len(ip6_mask.count_ones(), 128)
+                } else {
+                    0
+                }
+        };
+        let domain_spec_len = |macro_string: &Macro| match macro_string {
+            Macro::None => 0,
+            macro_string => 1 + macro_string.text_len(),
+        };
+
+        match self {
+            Mechanism::All => "all".len(),
+            Mechanism::Include { macro_string } => "include".len() + domain_spec_len(macro_string),
+            Mechanism::A {
+                macro_string,
+                ip4_mask,
+                ip6_mask,
+            } => "a".len() + domain_spec_len(macro_string) + dual_cidr_len(*ip4_mask, *ip6_mask),
+            Mechanism::Mx {
+                macro_string,
+                ip4_mask,
+                ip6_mask,
+            } => "mx".len() + domain_spec_len(macro_string) + dual_cidr_len(*ip4_mask, *ip6_mask),
+            Mechanism::Ptr { macro_string } => "ptr".len() + domain_spec_len(macro_string),
+            Mechanism::Ip4 { addr, mask } => {
+                "ip4:".len() + addr.to_string().len() + cidr_len(mask.count_ones(), 32)
+            }
+            Mechanism::Ip6 { addr, mask } => {
+                "ip6:".len() + addr.to_string().len() + cidr_len(mask.count_ones(), 128)
+            }
+            Mechanism::Exists { macro_string } => "exists".len() + domain_spec_len(macro_string),
+        }
+    }
+}
+
+impl Macro {
+    /// Length of the macro string in its shortest text form.
+    fn text_len(&self) -> usize {
+        match self {
+            Macro::Literal(literal) => literal.len(),
+            Macro::Variable {
+                num_parts,
+                reverse,
+                delimiters,
+                ..
+            } => {
+                "%{x}".len()
+                    + if *num_parts > 0 {
+                        num_parts.to_string().len()
+                    } else {
+                        0
+                    }
+                    + usize::from(*reverse)
+                    + if *delimiters != 1u64 << (b'.' - b'+') {
+                        delimiters.count_ones() as usize
+                    } else {
+                        0
+                    }
+            }
+            Macro::List(list) => list.iter().map(Macro::text_len).sum(),
+            Macro::None => 0,
+        }
+    }
+}
+
+impl Spf {
+    /// Returns `false` if the record has more than `max_terms` mechanisms and
+    /// modifiers, or if its shortest text form is longer than `max_length`
+    /// bytes. The length is derived from the parsed terms, so it does not
+    /// depend on how the record was obtained.
+    pub(crate) fn is_within_limits(&self, max_terms: usize, max_length: usize) -> bool {
+        let num_terms = self.directives.len()
+            + usize::from(self.redirect.is_some())
+            + usize::from(self.exp.is_some());
+        let length = "v=spf1".len()
+            + self
+                .directives
+                .iter()
+                .map(|directive| {
+                    1 + usize::from(directive.qualifier != Qualifier::Pass)
+                        + directive.mechanism.text_len()
+                })
+                .sum::<usize>()
+            + self
+                .redirect
+                .as_ref()
+                .map_or(0, |redirect| " redirect=".len() + redirect.text_len())
+            + self
+                .exp
+                .as_ref()
+                .map_or(0, |exp| " exp=".len() + exp.text_len())
+            + self.ra.as_ref().map_or(0, |ra| " ra=".len() + ra.len());
+
+        num_terms <= max_terms && length <= max_length
+    }
 }
 
 impl TryFrom<&str> for SpfResult {
@@ -209,28 +393,43 @@ impl SpfOutput {
             report: None,
             explanation: None,
             domain,
+            mail_from: String::new(),
+            helo: String::new(),
+            helo_result: None,
         }
     }
 
+    pub(crate) fn with_identities(mut self, mail_from: &str, helo: &str) -> Self {
+        self.mail_from = mail_from.to_string();
+        self.helo = helo.to_string();
+        self
+    }
+
     pub(crate) fn with_result(mut self, result: SpfResult) -> Self {
         self.result = result;
         self
     }
 
+    pub(crate) fn with_helo_result(mut self, result: SpfResult) -> Self {
+        self.helo_result = Some(result);
+        self
+    }
+
     pub(crate) fn with_report(mut self, spf: &Spf) -> Self {
         match &spf.ra {
-            Some(ra) if is_within_pct(spf.rp) => {
-                if match self.result {
-                    SpfResult::Fail => (spf.rr & RR_FAIL) != 0,
-                    SpfResult::SoftFail => (spf.rr & RR_SOFTFAIL) != 0,
-                    SpfResult::Neutral | SpfResult::None => (spf.rr & RR_NEUTRAL_NONE) != 0,
-                    SpfResult::TempError | SpfResult::PermError => {
-                        (spf.rr & RR_TEMP_PERM_ERROR) != 0
-                    }
-                    SpfResult::Pass => false,
-                } {
-                    self.report = format!("{}@{}", String::from_utf8_lossy(ra), self.domain).into();
-                }
+            Some(ra)
+                if is_within_pct(spf.rp)
+                    && match self.result {
+                        SpfResult::Fail => (spf.rr & RR_FAIL) != 0,
+                        SpfResult::SoftFail => (spf.rr & RR_SOFTFAIL) != 0,
+                        SpfResult::Neutral | SpfResult::None => (spf.rr & RR_NEUTRAL_NONE) != 0,
+                        SpfResult::TempError | SpfResult::PermError => {
+                            (spf.rr & RR_TEMP_PERM_ERROR) != 0
+                        }
+                        SpfResult::Pass => false,
+                    } =>
+            {
+                self.report = format!("{}@{}", String::from_utf8_lossy(ra), self.domain).into();
             }
             _ => (),
         }
@@ -250,6 +449,24 @@ impl SpfOutput {
         &self.domain
     }
 
+    /// Returns the envelope sender that was evaluated, including the
+    /// local-part. For HELO checks this is `postmaster@<helo>`.
+    pub fn mail_from(&self) -> &str {
+        &self.mail_from
+    }
+
+    /// Returns the HELO/EHLO name that was evaluated.
+    pub fn helo(&self) -> &str {
+        &self.helo
+    }
+
+    /// Returns the result of the HELO identity check performed because the
+    /// MAIL FROM domain has no SPF record, see
+    /// [`SpfOptions::helo_fallback`].
+    pub fn helo_result(&self) -> Option<SpfResult> {
+        self.helo_result
+    }
+
     pub fn explanation(&self) -> Option<&str> {
         self.explanation.as_deref()
     }
@@ -28,7 +28,7 @@ impl TxtRecordParser for Spf {
         let mut record = bytes.iter();
         if !matches!(record.key(), Some(k) if k == V)
             || !record.match_bytes(b"spf1")
-            || record.next().map_or(false, |v| !v.is_ascii_whitespace())
+            || record.next().is_some_and(|v| !v.is_ascii_whitespace())
         {
             return Err(Error::InvalidRecordType);
         }
@@ -76,14 +76,14 @@ impl TxtRecordParser for Spf {
                         if term == A {
                             Mechanism::A {
                                 macro_string,
-                                ip4_mask: u32::MAX << (32 - ip4_cidr_length),
-                                ip6_mask: u128::MAX << (128 - ip6_cidr_length),
+                                ip4_mask: ip4_mask(ip4_cidr_length),
+                                ip6_mask: ip6_mask(ip6_cidr_length),
                             }
                         } else {
                             Mechanism::Mx {
                                 macro_string,
-                                ip4_mask: u32::MAX << (32 - ip4_cidr_length),
-                                ip6_mask: u128::MAX << (128 - ip6_cidr_length),
+                                ip4_mask: ip4_mask(ip4_cidr_length),
+                                ip6_mask: ip6_mask(ip6_cidr_length),
                             }
                         },
                     ));
@@ -129,7 +129,7 @@ impl TxtRecordParser for Spf {
                         qualifier,
                         Mechanism::Ip4 {
                             addr,
-                            mask: u32::MAX << (32 - cidr_length),
+                            mask: ip4_mask(cidr_length),
                         },
                     ));
                 }
@@ -148,7 +148,7 @@ impl TxtRecordParser for Spf {
                         qualifier,
                         Mechanism::Ip6 {
                             addr,
-                            mask: u128::MAX << (128 - cidr_length),
+                            mask: ip6_mask(cidr_length),
                         },
                     ));
                 }
@@ -244,6 +244,16 @@ const RA: u64 = (b'a' as u64) << 8 | (b'r' as u64);
 const RP: u64 = (b'p' as u64) << 8 | (b'r' as u64);
 const RR: u64 = (b'r' as u64) << 8 | (b'r' as u64);
 
+#[inline(always)]
+fn ip4_mask(cidr_length: u8) -> u32 {
+    u32::MAX.checked_shl(32 - cidr_length as u32).unwrap_or(0)
+}
+
+#[inline(always)]
+fn ip6_mask(cidr_length: u8) -> u128 {
+    u128::MAX.checked_shl(128 - cidr_length as u32).unwrap_or(0)
+}
+
 pub(crate) trait SPFParser: Sized {
     fn next_term(&mut self) -> Option<(u64, Qualifier, u8)>;
     fn macro_string(&mut self, is_exp: bool) -> crate::Result<(Macro, u8)>;
@@ -1426,7 +1436,7 @@ mod test {
                 },
             ),
             (
-                concat!("v=spf1 mx:example.org -all ra=postmaster rp=15 rr=e:f:s:n"),
+                "v=spf1 mx:example.org -all ra=postmaster rp=15 rr=e:f:s:n",
                 Spf {
                     version: Version::V1,
                     ra: b"postmaster".to_vec().into(),
@@ -1448,7 +1458,7 @@ mod test {
                 },
             ),
             (
-                concat!("v=spf1 ip6:fe80:0000:0000::0000:0000:0000:1 -all"),
+                "v=spf1 ip6:fe80:0000:0000::0000:0000:0000:1 -all",
                 Spf {
                     version: Version::V1,
                     ra: None,
@@ -10,12 +10,23 @@
 
 use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
     time::Instant,
 };
 
-use crate::{Error, Resolver, SpfOutput, SpfResult};
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
 
-use super::{Macro, Mechanism, Qualifier, Spf, Variables};
+use crate::{
+    common::{
+        address::split_address,
+        lru::DnsCache,
+        metrics::Metrics,
+        resolver::{DnsAnswers, IntoFqdn},
+    },
+    Error, Resolver, SpfOutput, SpfResult,
+};
+
+use super::{Macro, Mechanism, Qualifier, Spf, SpfOptions, SpfTraceEvent, Variable, Variables};
 
 #[allow(clippy::iter_skip_zero)]
 impl Resolver {
@@ -26,18 +37,8 @@ impl Resolver {
         helo_domain: &str,
         host_domain: &str,
     ) -> SpfOutput {
-        if helo_domain.has_valid_labels() {
-            self.check_host(
-                ip,
-                helo_domain,
-                helo_domain,
-                host_domain,
-                &format!("postmaster@{helo_domain}"),
-            )
+        self.verify_spf_helo_(ip, helo_domain, host_domain, &SpfOptions::default(), None)
             .await
-        } else {
-            SpfOutput::new(helo_domain.to_string()).with_result(SpfResult::None)
-        }
     }
 
     /// Verifies the SPF MAIL FROM identity
@@ -48,16 +49,31 @@ impl Resolver {
         host_domain: &str,
         sender: &str,
     ) -> SpfOutput {
-        self.check_host(
+        self.verify_spf_sender_with_opts(
             ip,
-            sender.rsplit_once('@').map_or(helo_domain, |(_, d)| d),
             helo_domain,
             host_domain,
             sender,
+            &SpfOptions::default(),
         )
         .await
     }
 
+    /// Verifies the SPF MAIL FROM identity using the given [`SpfOptions`],
+    /// also checking the HELO identity if the MAIL FROM domain has no SPF
+    /// record and [`SpfOptions::helo_fallback`] is enabled.
+    pub async fn verify_spf_sender_with_opts(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+        opts: &SpfOptions,
+    ) -> SpfOutput {
+        self.verify_spf_sender_(ip, helo_domain, host_domain, sender, opts, None, None)
+            .await
+    }
+
     /// Verifies both the SPF EHLO and MAIL FROM identities
     pub async fn verify_spf(
         &self,
@@ -65,20 +81,180 @@ impl Resolver {
         helo_domain: &str,
         host_domain: &str,
         mail_from: &str,
+    ) -> SpfOutput {
+        self.verify_spf_with_opts(
+            ip,
+            helo_domain,
+            host_domain,
+            mail_from,
+            &SpfOptions::default(),
+        )
+        .await
+    }
+
+    /// Verifies both the SPF EHLO and MAIL FROM identities using the given
+    /// [`SpfOptions`].
+    pub async fn verify_spf_with_opts(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+        opts: &SpfOptions,
+    ) -> SpfOutput {
+        self.verify_spf_(ip, helo_domain, host_domain, mail_from, opts, None)
+            .await
+    }
+
+    /// Verifies both the SPF EHLO and MAIL FROM identities like
+    /// [`Resolver::verify_spf_with_opts`], also returning every record
+    /// fetched and every directive evaluated, in order. Cached SPF results
+    /// are not used so that the trace is always complete.
+    pub async fn verify_spf_with_trace(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+        opts: &SpfOptions,
+    ) -> (SpfOutput, Vec<SpfTraceEvent>) {
+        let mut trace = Vec::new();
+        let output = self
+            .verify_spf_(
+                ip,
+                helo_domain,
+                host_domain,
+                mail_from,
+                opts,
+                Some(&mut trace),
+            )
+            .await;
+        (output, trace)
+    }
+
+    async fn verify_spf_helo_(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        opts: &SpfOptions,
+        trace: Option<&mut Vec<SpfTraceEvent>>,
+    ) -> SpfOutput {
+        if helo_domain.has_valid_labels() {
+            self.check_host_traced(
+                ip,
+                helo_domain,
+                helo_domain,
+                host_domain,
+                &format!("postmaster@{helo_domain}"),
+                opts,
+                trace,
+            )
+            .await
+        } else {
+            SpfOutput::new(helo_domain.to_string())
+                .with_result(SpfResult::None)
+                .with_identities(&format!("postmaster@{helo_domain}"), helo_domain)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn verify_spf_sender_(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+        opts: &SpfOptions,
+        helo_result: Option<SpfResult>,
+        mut trace: Option<&mut Vec<SpfTraceEvent>>,
+    ) -> SpfOutput {
+        let domain = split_address(sender).map_or(helo_domain, |(_, d)| d);
+        let output = self
+            .check_host_traced(
+                ip,
+                domain,
+                helo_domain,
+                host_domain,
+                sender,
+                opts,
+                trace.as_deref_mut(),
+            )
+            .await;
+
+        // A domain without an SPF record says nothing about the sender,
+        // record the HELO result unless it is the domain just checked
+        if opts.helo_fallback
+            && output.result == SpfResult::None
+            && !domain.eq_ignore_ascii_case(helo_domain)
+        {
+            let helo_result = match helo_result {
+                Some(helo_result) => helo_result,
+                None => {
+                    self.verify_spf_helo_(ip, helo_domain, host_domain, opts, trace)
+                        .await
+                        .result
+                }
+            };
+            output.with_helo_result(helo_result)
+        } else {
+            output
+        }
+    }
+
+    async fn verify_spf_(
+        &self,
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+        opts: &SpfOptions,
+        mut trace: Option<&mut Vec<SpfTraceEvent>>,
     ) -> SpfOutput {
         // Verify HELO identity
-        let output = self.verify_spf_helo(ip, helo_domain, host_domain).await;
+        let output = self
+            .verify_spf_helo_(ip, helo_domain, host_domain, opts, trace.as_deref_mut())
+            .await;
         if matches!(output.result(), SpfResult::Pass) {
             // Verify MAIL FROM identity
-            self.verify_spf_sender(ip, helo_domain, host_domain, mail_from)
-                .await
+            self.verify_spf_sender_(
+                ip,
+                helo_domain,
+                host_domain,
+                mail_from,
+                opts,
+                Some(SpfResult::Pass),
+                trace,
+            )
+            .await
         } else {
             output
         }
     }
 
-    #[allow(clippy::while_let_on_iterator)]
-    #[allow(clippy::iter_skip_zero)]
+    /// Verifies both the SPF EHLO and MAIL FROM identities resolving all
+    /// queries from `answers`, without network access. Names missing from
+    /// `answers` are treated as non-existent.
+    pub async fn verify_spf_offline(
+        ip: IpAddr,
+        helo_domain: &str,
+        host_domain: &str,
+        mail_from: &str,
+        answers: &DnsAnswers,
+    ) -> SpfOutput {
+        match Resolver::with_capacity(ResolverConfig::default(), ResolverOpts::default(), 0) {
+            Ok(resolver) => {
+                resolver
+                    .with_dns_answers(answers.clone())
+                    .verify_spf(ip, helo_domain, host_domain, mail_from)
+                    .await
+            }
+            Err(_) => SpfOutput::new(helo_domain.to_string())
+                .with_result(SpfResult::TempError)
+                .with_identities(mail_from, helo_domain),
+        }
+    }
+
     pub async fn check_host(
         &self,
         ip: IpAddr,
@@ -87,12 +263,54 @@ impl Resolver {
         host_domain: &str,
         sender: &str,
     ) -> SpfOutput {
-        let output = SpfOutput::new(domain.to_string());
-        if domain.is_empty() || domain.len() > 255 || !domain.has_valid_labels() {
-            return output.with_result(SpfResult::None);
+        self.check_host_traced(
+            ip,
+            domain,
+            helo_domain,
+            host_domain,
+            sender,
+            &SpfOptions::default(),
+            None,
+        )
+        .await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, trace), fields(ip = %ip))
+    )]
+    #[allow(clippy::too_many_arguments)]
+    async fn check_host_traced(
+        &self,
+        ip: IpAddr,
+        domain: &str,
+        helo_domain: &str,
+        host_domain: &str,
+        sender: &str,
+        opts: &SpfOptions,
+        mut trace: Option<&mut Vec<SpfTraceEvent>>,
+    ) -> SpfOutput {
+        let mut metrics = Metrics::new();
+        let mut lookup_limit = LookupLimit::new();
+
+        // IPv4-mapped IPv6 addresses are evaluated as IPv4
+        let ip = ip.to_canonical();
+        let now = self.clock.instant();
+        let cache_key =
+            (!self.spf_cache_ttl.is_zero() && self.answers.is_none() && opts.is_cacheable())
+                .then(|| (ip, domain.trim_end_matches('.').to_lowercase()));
+        if let Some(output) = cache_key
+            .as_ref()
+            .filter(|_| trace.is_none())
+            .and_then(|key| self.cache_spf.get(key, now))
+        {
+            let output = output.with_identities(sender, helo_domain);
+            metrics.finish("spf", output.result.as_keyword());
+            return output;
         }
+
         let mut vars = Variables::new();
-        let mut has_p_var = false;
+        vars.set_current_time(self.unix_time());
         vars.set_ip(&ip);
         if !sender.is_empty() {
             vars.set_sender(sender.as_bytes());
@@ -103,8 +321,67 @@ impl Resolver {
         vars.set_host_domain(host_domain.as_bytes());
         vars.set_helo_domain(helo_domain.as_bytes());
 
-        let mut lookup_limit = LookupLimit::new();
-        let mut spf_record = match self.txt_lookup::<Spf>(domain).await {
+        let mut expiry = cache_key.as_ref().map(|_| now + self.spf_cache_ttl);
+        let output = self
+            .check_host_(
+                ip,
+                domain,
+                &mut vars,
+                opts,
+                &mut lookup_limit,
+                &mut expiry,
+                &mut trace,
+            )
+            .await;
+
+        // Only cache results that depend on nothing but the IP and domain
+        if let (Some(key), Some(valid_until)) = (cache_key, expiry) {
+            if output.result != SpfResult::TempError
+                && !vars.uses_any(&[
+                    Variable::Sender,
+                    Variable::SenderLocalPart,
+                    Variable::SenderDomainPart,
+                    Variable::HeloDomain,
+                    Variable::HostDomain,
+                    Variable::CurrentTime,
+                ])
+            {
+                self.cache_spf.insert(key, output.clone(), valid_until, now);
+            }
+        }
+
+        let output = output.with_identities(sender, helo_domain);
+        metrics.set_dns_lookups(lookup_limit.num_lookups);
+        metrics.finish("spf", output.result.as_keyword());
+        output
+    }
+
+    #[allow(clippy::while_let_on_iterator)]
+    #[allow(clippy::iter_skip_zero)]
+    #[allow(clippy::too_many_arguments)]
+    async fn check_host_(
+        &self,
+        ip: IpAddr,
+        domain: &str,
+        vars: &mut Variables<'_>,
+        opts: &SpfOptions,
+        lookup_limit: &mut LookupLimit,
+        expiry: &mut Option<Instant>,
+        trace: &mut Option<&mut Vec<SpfTraceEvent>>,
+    ) -> SpfOutput {
+        let output = SpfOutput::new(domain.to_string());
+        if domain.is_empty() || domain.len() > 255 || !domain.has_valid_labels() {
+            return output.with_result(SpfResult::None);
+        }
+        let mut has_p_var = false;
+
+        let spf_record = self.spf_record_lookup(domain, opts).await;
+        self.track_expiry(expiry, SpfLookup::Txt(domain));
+        trace_event(trace, || SpfTraceEvent::Record {
+            domain: domain.to_string(),
+            error: spf_record.as_ref().err().cloned(),
+        });
+        let mut spf_record = match spf_record {
             Ok(spf_record) => spf_record,
             Err(err) => return output.with_result(err.into()),
         };
@@ -131,6 +408,7 @@ impl Resolver {
                     {
                         vars.set_validated_domain(ptr);
                     }
+                    self.track_expiry(expiry, SpfLookup::Ptr(ip));
                     has_p_var = true;
                 }
 
@@ -148,15 +426,12 @@ impl Resolver {
                                 .with_result(SpfResult::PermError)
                                 .with_report(&spf_record);
                         }
-                        match self
-                            .ip_matches(
-                                macro_string.eval(&vars, &domain, true).as_ref(),
-                                ip,
-                                *ip4_mask,
-                                *ip6_mask,
-                            )
-                            .await
-                        {
+                        let target_name = macro_string.eval(vars, &domain, true);
+                        let result = self
+                            .ip_matches(target_name.as_ref(), ip, *ip4_mask, *ip6_mask)
+                            .await;
+                        self.track_expiry(expiry, SpfLookup::Ip(target_name.as_ref(), ip));
+                        match result {
                             Ok(true) => true,
                             Ok(false) | Err(Error::DnsRecordNotFound(_)) => false,
                             Err(_) => {
@@ -178,10 +453,10 @@ impl Resolver {
                         }
 
                         let mut matches = false;
-                        match self
-                            .mx_lookup(macro_string.eval(&vars, &domain, true).as_ref())
-                            .await
-                        {
+                        let target_name = macro_string.eval(vars, &domain, true);
+                        let result = self.mx_lookup(target_name.as_ref()).await;
+                        self.track_expiry(expiry, SpfLookup::Mx(target_name.as_ref()));
+                        match result {
                             Ok(records) => {
                                 for (mx_num, exchange) in records
                                     .iter()
@@ -194,8 +469,10 @@ impl Resolver {
                                             .with_report(&spf_record);
                                     }
 
-                                    match self.ip_matches(exchange, ip, *ip4_mask, *ip6_mask).await
-                                    {
+                                    let result =
+                                        self.ip_matches(exchange, ip, *ip4_mask, *ip6_mask).await;
+                                    self.track_expiry(expiry, SpfLookup::Ip(exchange, ip));
+                                    match result {
                                         Ok(true) => {
                                             matches = true;
                                             break;
@@ -225,8 +502,14 @@ impl Resolver {
                                 .with_report(&spf_record);
                         }
 
-                        let target_name = macro_string.eval(&vars, &domain, true);
-                        match self.txt_lookup::<Spf>(target_name.as_ref()).await {
+                        let target_name = macro_string.eval(vars, &domain, true);
+                        let result = self.spf_record_lookup(target_name.as_ref(), opts).await;
+                        self.track_expiry(expiry, SpfLookup::Txt(target_name.as_ref()));
+                        trace_event(trace, || SpfTraceEvent::Record {
+                            domain: target_name.to_string(),
+                            error: result.as_ref().err().cloned(),
+                        });
+                        match result {
                             Ok(included_spf) => {
                                 let new_domain = target_name.to_string();
                                 include_stack.push((
@@ -262,16 +545,19 @@ impl Resolver {
                                 .with_report(&spf_record);
                         }
 
-                        let target_addr = macro_string.eval(&vars, &domain, true).to_lowercase();
+                        let target_addr = macro_string.eval(vars, &domain, true).to_lowercase();
                         let target_sub_addr = format!(".{target_addr}");
                         let mut matches = false;
 
-                        if let Ok(records) = self.ptr_lookup(ip).await {
+                        let result = self.ptr_lookup(ip).await;
+                        self.track_expiry(expiry, SpfLookup::Ptr(ip));
+                        if let Ok(records) = result {
                             for record in records.iter() {
                                 if lookup_limit.can_lookup() {
-                                    if let Ok(true) =
-                                        self.ip_matches(record, ip, u32::MAX, u128::MAX).await
-                                    {
+                                    let result =
+                                        self.ip_matches(record, ip, u32::MAX, u128::MAX).await;
+                                    self.track_expiry(expiry, SpfLookup::Ip(record, ip));
+                                    if let Ok(true) = result {
                                         matches = record == &target_addr
                                             || record
                                                 .strip_suffix('.')
@@ -293,29 +579,82 @@ impl Resolver {
                                 .with_report(&spf_record);
                         }
 
-                        if let Ok(result) = self
-                            .exists(macro_string.eval(&vars, &domain, true).as_ref())
-                            .await
-                        {
-                            result
-                        } else {
-                            return output
-                                .with_result(SpfResult::TempError)
-                                .with_report(&spf_record);
+                        // Matches if any A record exists, regardless of its
+                        // address and even for IPv6 connections (RFC 7208, Section 5.7)
+                        let target_name = macro_string.eval(vars, &domain, true);
+                        let result = self.ipv4_lookup(target_name.as_ref()).await;
+                        self.track_expiry(expiry, SpfLookup::Ipv4(target_name.as_ref()));
+                        match result {
+                            Ok(records) => !records.is_empty(),
+                            Err(Error::DnsRecordNotFound(_)) => false,
+                            Err(_) => {
+                                return output
+                                    .with_result(SpfResult::TempError)
+                                    .with_report(&spf_record);
+                            }
                         }
                     }
                 };
 
+                trace_event(trace, || SpfTraceEvent::Directive {
+                    domain: domain.clone(),
+                    directive: directive.clone(),
+                    matched: matches,
+                });
                 if matches {
                     result = Some((&directive.qualifier).into());
                     break;
                 }
             }
 
+            // Follow redirect, which also applies to included records
+            if let (Some(macro_string), None) = (&spf_record.redirect, &result) {
+                if !lookup_limit.can_lookup() {
+                    return output
+                        .with_result(SpfResult::PermError)
+                        .with_report(&spf_record);
+                }
+
+                let target_name = macro_string.eval(vars, &domain, true);
+                let result = self.spf_record_lookup(target_name.as_ref(), opts).await;
+                self.track_expiry(expiry, SpfLookup::Txt(target_name.as_ref()));
+                trace_event(trace, || SpfTraceEvent::Record {
+                    domain: target_name.to_string(),
+                    error: result.as_ref().err().cloned(),
+                });
+                match result {
+                    Ok(redirect_spf) => {
+                        let new_domain = target_name.to_string();
+                        spf_record = redirect_spf;
+                        directives = spf_record.directives.iter().enumerate().skip(0);
+                        domain = new_domain;
+                        vars.set_domain(domain.as_bytes().to_vec());
+                        continue;
+                    }
+                    Err(
+                        Error::DnsRecordNotFound(_) | Error::InvalidRecordType | Error::ParseError,
+                    ) => {
+                        return output
+                            .with_result(SpfResult::PermError)
+                            .with_report(&spf_record)
+                    }
+                    Err(_) => {
+                        return output
+                            .with_result(SpfResult::TempError)
+                            .with_report(&spf_record)
+                    }
+                }
+            }
+
             if let Some((prev_record, prev_pos, prev_domain)) = include_stack.pop() {
                 spf_record = prev_record;
                 directives = spf_record.directives.iter().enumerate().skip(prev_pos);
                 let (_, directive) = directives.next().unwrap();
+                trace_event(trace, || SpfTraceEvent::Directive {
+                    domain: prev_domain.clone(),
+                    directive: directive.clone(),
+                    matched: matches!(result, Some(SpfResult::Pass)),
+                });
 
                 if matches!(result, Some(SpfResult::Pass)) {
                     result = Some((&directive.qualifier).into());
@@ -326,63 +665,64 @@ impl Resolver {
                     result = None;
                 }
             } else {
-                // Follow redirect
-                if let (Some(macro_string), None) = (&spf_record.redirect, &result) {
-                    if !lookup_limit.can_lookup() {
-                        return output
-                            .with_result(SpfResult::PermError)
-                            .with_report(&spf_record);
-                    }
-
-                    let target_name = macro_string.eval(&vars, &domain, true);
-                    match self.txt_lookup::<Spf>(target_name.as_ref()).await {
-                        Ok(redirect_spf) => {
-                            let new_domain = target_name.to_string();
-                            spf_record = redirect_spf;
-                            directives = spf_record.directives.iter().enumerate().skip(0);
-                            domain = new_domain;
-                            vars.set_domain(domain.as_bytes().to_vec());
-                            continue;
-                        }
-                        Err(
-                            Error::DnsRecordNotFound(_)
-                            | Error::InvalidRecordType
-                            | Error::ParseError,
-                        ) => {
-                            return output
-                                .with_result(SpfResult::PermError)
-                                .with_report(&spf_record)
-                        }
-                        Err(_) => {
-                            return output
-                                .with_result(SpfResult::TempError)
-                                .with_report(&spf_record)
-                        }
-                    }
-                }
-
                 break;
             }
         }
 
         // Evaluate explain
-        if let (Some(macro_string), Some(SpfResult::Fail { .. })) = (&spf_record.exp, &result) {
-            if let Ok(macro_string) = self
-                .txt_lookup::<Macro>(macro_string.eval(&vars, &domain, true).to_string())
-                .await
-            {
+        if let (Some(macro_string), Some(SpfResult::Fail)) = (&spf_record.exp, &result) {
+            let target_name = macro_string.eval(vars, &domain, true).to_string();
+            let result = self.txt_lookup::<Macro>(target_name.as_str()).await;
+            self.track_expiry(expiry, SpfLookup::Txt(&target_name));
+            if let Ok(macro_string) = result {
                 return output
                     .with_result(SpfResult::Fail)
-                    .with_explanation(macro_string.eval(&vars, &domain, false).to_string())
+                    .with_explanation(macro_string.eval(vars, &domain, false).to_string())
                     .with_report(&spf_record);
             }
         }
 
+        // No mechanism matched and there is no "all" or "redirect" to fall
+        // back on, the default result is "neutral" (RFC 7208, Section 4.7)
         output
             .with_result(result.unwrap_or(SpfResult::Neutral))
             .with_report(&spf_record)
     }
 
+    /// Fetches the SPF record published by `domain`. Records exceeding the
+    /// configured size limits are rejected as malformed, wherever they were
+    /// obtained from.
+    async fn spf_record_lookup(&self, domain: &str, opts: &SpfOptions) -> crate::Result<Arc<Spf>> {
+        self.txt_lookup::<Spf>(domain).await.and_then(|spf_record| {
+            if spf_record.is_within_limits(opts.max_terms, opts.max_record_length) {
+                Ok(spf_record)
+            } else {
+                Err(Error::ParseError)
+            }
+        })
+    }
+
+    /// Lowers the expiration of a cached SPF result to that of a cached
+    /// DNS answer consulted during the evaluation.
+    fn track_expiry(&self, expiry: &mut Option<Instant>, lookup: SpfLookup<'_>) {
+        if let Some(expiry) = expiry {
+            let valid_until = match lookup {
+                SpfLookup::Txt(name) => self.cache_txt.valid_until(name.into_fqdn().as_ref()),
+                SpfLookup::Mx(name) => self.cache_mx.valid_until(name.into_fqdn().as_ref()),
+                SpfLookup::Ip(name, IpAddr::V4(_)) | SpfLookup::Ipv4(name) => {
+                    self.cache_ipv4.valid_until(name.into_fqdn().as_ref())
+                }
+                SpfLookup::Ip(name, IpAddr::V6(_)) => {
+                    self.cache_ipv6.valid_until(name.into_fqdn().as_ref())
+                }
+                SpfLookup::Ptr(ip) => self.cache_ptr.valid_until(&ip),
+            };
+            if let Some(valid_until) = valid_until {
+                *expiry = (*expiry).min(valid_until);
+            }
+        }
+    }
+
     async fn ip_matches(
         &self,
         target_name: &str,
@@ -405,6 +745,21 @@ impl Resolver {
     }
 }
 
+enum SpfLookup<'x> {
+    Txt(&'x str),
+    Mx(&'x str),
+    Ip(&'x str, IpAddr),
+    Ipv4(&'x str),
+    Ptr(IpAddr),
+}
+
+/// Records a trace event, building it only if a trace was requested.
+fn trace_event(trace: &mut Option<&mut Vec<SpfTraceEvent>>, event: impl FnOnce() -> SpfTraceEvent) {
+    if let Some(trace) = trace {
+        trace.push(event());
+    }
+}
+
 trait IpMask {
     fn matches_ipv4_mask(&self, addr: &Ipv4Addr, mask: u32) -> bool;
     fn matches_ipv6_mask(&self, addr: &Ipv6Addr, mask: u128) -> bool;
@@ -541,9 +896,14 @@ mod test {
     };
 
     use crate::{
-        common::parse::TxtRecordParser,
-        spf::{Macro, Spf},
-        Resolver, SpfResult, MX,
+        common::{
+            address::split_address,
+            clock::Clock,
+            parse::TxtRecordParser,
+            resolver::{test::FrozenClock, DnsAnswers},
+        },
+        spf::{Macro, Spf, SpfOptions, SpfTraceEvent},
+        Error, Resolver, SpfResult, MX,
     };
 
     #[tokio::test]
@@ -680,4 +1040,572 @@ mod test {
             }
         }
     }
+
+    #[tokio::test]
+    async fn spf_verify_identities() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        let ip = "192.168.0.1".parse::<IpAddr>().unwrap();
+        resolver.txt_add(
+            "mail.example.org.",
+            Spf::parse(b"v=spf1 ip4:192.168.0.1 -all"),
+            valid_until,
+        );
+        resolver.txt_add(
+            "example.org.",
+            Spf::parse(b"v=spf1 ip4:192.168.0.0/24 -all"),
+            valid_until,
+        );
+
+        let output = resolver
+            .verify_spf(
+                ip,
+                "mail.example.org",
+                "localdomain.org",
+                "John.Doe+list@example.org",
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        assert_eq!(output.domain(), "example.org");
+        assert_eq!(output.mail_from(), "John.Doe+list@example.org");
+        assert_eq!(output.helo(), "mail.example.org");
+
+        let output = resolver
+            .verify_spf_helo(ip, "mail.example.org", "localdomain.org")
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        assert_eq!(output.mail_from(), "postmaster@mail.example.org");
+        assert_eq!(output.helo(), "mail.example.org");
+
+        let output = resolver
+            .verify_spf_sender(ip, "mail.example.org", "localdomain.org", "")
+            .await;
+        assert_eq!(output.mail_from(), "");
+        assert_eq!(output.helo(), "mail.example.org");
+    }
+
+    #[tokio::test]
+    async fn spf_verify_cache() {
+        let clock = FrozenClock::new(1_000_000);
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_spf_cache(16, Duration::from_secs(60));
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        let now = clock.instant();
+        resolver.txt_add(
+            "cached.org.",
+            Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+            now + Duration::from_secs(30),
+        );
+        resolver.txt_add(
+            "macro.org.",
+            Spf::parse(b"v=spf1 exists:%{l}.users.macro.org -all").unwrap(),
+            now + Duration::from_secs(300),
+        );
+        resolver.ipv4_add(
+            "jdoe.users.macro.org.",
+            vec![Ipv4Addr::LOCALHOST],
+            now + Duration::from_secs(300),
+        );
+
+        let output = resolver
+            .verify_spf_sender(ip, "mx.example.com", "host.example.com", "a@cached.org")
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+
+        // Repeated evaluations for the same IP and domain are served from the cache
+        resolver.txt_add(
+            "cached.org.",
+            Spf::parse(b"v=spf1 -all").unwrap(),
+            now + Duration::from_secs(3600),
+        );
+        let output = resolver
+            .verify_spf_sender(ip, "mx.example.org", "host.example.com", "b@Cached.org")
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        assert_eq!(output.mail_from(), "b@Cached.org");
+        assert_eq!(output.helo(), "mx.example.org");
+        let output = resolver
+            .verify_spf_sender(
+                "10.0.0.2".parse().unwrap(),
+                "mx.example.com",
+                "host.example.com",
+                "a@cached.org",
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Fail);
+
+        // Cached results expire with the DNS records they were derived from
+        clock.advance(31);
+        let output = resolver
+            .verify_spf_sender(ip, "mx.example.com", "host.example.com", "a@cached.org")
+            .await;
+        assert_eq!(output.result(), SpfResult::Fail);
+
+        // Policies expanding sender macros are not cached
+        for (sender, expected_result) in [
+            ("jdoe@macro.org", SpfResult::Pass),
+            ("other@macro.org", SpfResult::Fail),
+            ("jdoe@macro.org", SpfResult::Pass),
+        ] {
+            let output = resolver
+                .verify_spf_sender(ip, "mx.example.com", "host.example.com", sender)
+                .await;
+            assert_eq!(output.result(), expected_result, "{sender}");
+        }
+
+        // Disabled cache
+        let resolver = resolver.with_spf_cache(0, Duration::from_secs(60));
+        resolver.txt_add(
+            "cached.org.",
+            Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+            clock.instant() + Duration::from_secs(3600),
+        );
+        let output = resolver
+            .verify_spf_sender(ip, "mx.example.com", "host.example.com", "a@cached.org")
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        resolver.txt_add(
+            "cached.org.",
+            Spf::parse(b"v=spf1 -all").unwrap(),
+            clock.instant() + Duration::from_secs(3600),
+        );
+        let output = resolver
+            .verify_spf_sender(ip, "mx.example.com", "host.example.com", "a@cached.org")
+            .await;
+        assert_eq!(output.result(), SpfResult::Fail);
+    }
+
+    #[tokio::test]
+    async fn spf_verify_offline() {
+        let mut answers = DnsAnswers::new()
+            .with_txt("mail.example.org", "v=spf1 include:example.org -all")
+            .with_txt("example.org", "v=spf1 include:_spf.example.org mx -all")
+            .with_txt(
+                "_spf.example.org",
+                "v=spf1 ip4:198.51.100.0/24 a:v6.example.org ~all",
+            )
+            .with_mx("example.org", 10, "mx.example.org")
+            .with_a("mx.example.org", "203.0.113.5".parse().unwrap())
+            .with_aaaa("v6.example.org", "2001:db8::1".parse().unwrap())
+            .with_txt("deep.example.org", "v=spf1 include:l0.example.org -all");
+        for level in 0..10 {
+            answers = answers.with_txt(
+                format!("l{level}.example.org"),
+                format!("v=spf1 include:l{}.example.org -all", level + 1),
+            );
+        }
+
+        for (ip, mail_from, expected) in [
+            ("198.51.100.7", "jdoe@example.org", SpfResult::Pass),
+            ("203.0.113.5", "jdoe@example.org", SpfResult::Pass),
+            ("2001:db8::1", "jdoe@example.org", SpfResult::Pass),
+            ("192.0.2.1", "jdoe@example.org", SpfResult::Fail),
+            ("198.51.100.7", "jdoe@unknown.org", SpfResult::None),
+            (
+                "198.51.100.7",
+                "jdoe@deep.example.org",
+                SpfResult::PermError,
+            ),
+        ] {
+            let output = Resolver::verify_spf_offline(
+                ip.parse().unwrap(),
+                "mail.example.org",
+                "localdomain.org",
+                mail_from,
+                &answers,
+            )
+            .await;
+            assert_eq!(output.result(), expected, "{ip} {mail_from}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_default_neutral() {
+        let resolver = Resolver::new_system_conf().unwrap().with_dns_answers(
+            DnsAnswers::new()
+                .with_txt("example.org", "v=spf1 ip4:198.51.100.0/24")
+                .with_txt("include.example.org", "v=spf1 include:example.org")
+                .with_txt("fail.example.org", "v=spf1 include:example.org -all"),
+        );
+
+        for (ip, mail_from, expected) in [
+            ("198.51.100.7", "jdoe@example.org", SpfResult::Pass),
+            ("192.0.2.1", "jdoe@example.org", SpfResult::Neutral),
+            ("192.0.2.1", "jdoe@include.example.org", SpfResult::Neutral),
+            ("192.0.2.1", "jdoe@fail.example.org", SpfResult::Fail),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    ip.parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    mail_from,
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{ip} {mail_from}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_include_results() {
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        let resolver = Resolver::new_system_conf().unwrap();
+        for (name, record) in [
+            ("pass.org.", "v=spf1 ip4:192.0.2.1 -all"),
+            ("fail.org.", "v=spf1 -all"),
+            ("softfail.org.", "v=spf1 ~all"),
+            ("neutral.org.", "v=spf1 ?all"),
+            ("nomatch.org.", "v=spf1 ip4:198.51.100.1"),
+            ("redirect.org.", "v=spf1 redirect=pass.org"),
+            ("permerror.org.", "v=spf1 include:missing.org -all"),
+        ] {
+            resolver.txt_add(name, Spf::parse(record.as_bytes()), valid_until);
+        }
+
+        // An include only matches when the included record passes, records
+        // that are missing or invalid are a permerror, and DNS errors are a
+        // temperror (RFC 7208, Section 5.2)
+        for (target, expected) in [
+            ("pass.org", SpfResult::Pass),
+            ("fail.org", SpfResult::SoftFail),
+            ("softfail.org", SpfResult::SoftFail),
+            ("neutral.org", SpfResult::SoftFail),
+            ("nomatch.org", SpfResult::SoftFail),
+            ("redirect.org", SpfResult::Pass),
+            ("missing.org", SpfResult::PermError),
+            ("_parse_error.example.org", SpfResult::PermError),
+            ("permerror.org", SpfResult::PermError),
+            ("_dns_error.example.org", SpfResult::TempError),
+        ] {
+            let domain = format!("include.{target}");
+            resolver.txt_add(
+                format!("{domain}."),
+                Spf::parse(format!("v=spf1 include:{target} ~all").as_bytes()),
+                valid_until,
+            );
+            let output = resolver
+                .verify_spf_sender(
+                    "192.0.2.1".parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    &format!("jdoe@{domain}"),
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{target}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_record_selection() {
+        let resolver = Resolver::new_system_conf().unwrap().with_dns_answers(
+            DnsAnswers::new()
+                .with_txt("verification.org", "google-site-verification=abc123")
+                .with_txt(
+                    "verification.org",
+                    "v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQ",
+                )
+                .with_txt("midstring.org", "site-verification v=spf1 -all")
+                .with_txt("empty.org", "")
+                .with_txt("spf10.org", "v=spf10 -all")
+                .with_txt("mixed.org", "google-site-verification=abc123")
+                .with_txt("mixed.org", "V=SPF1 -all")
+                .with_txt("mixed.org", "v=DMARC1; p=reject")
+                .with_txt("trailing.org", "v=spf1")
+                .with_txt("invalid.org", "v=spf1 -all:example.org")
+                .with_txt("invalid.org", "google-site-verification=abc123"),
+        );
+
+        for (domain, expected) in [
+            ("verification.org", SpfResult::None),
+            ("midstring.org", SpfResult::None),
+            ("empty.org", SpfResult::None),
+            ("spf10.org", SpfResult::None),
+            ("mixed.org", SpfResult::Fail),
+            ("trailing.org", SpfResult::Neutral),
+            ("invalid.org", SpfResult::PermError),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    "192.0.2.1".parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    &format!("jdoe@{domain}"),
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{domain}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_record_limits() {
+        let many_terms = format!(
+            "v=spf1 {} -all",
+            (0..200)
+                .map(|n| format!("ip4:10.0.{}.{}", n / 100, n % 100))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let answers = DnsAnswers::new()
+            .with_txt("many.org", many_terms.as_str())
+            .with_txt("include.org", "v=spf1 include:many.org -all")
+            .with_txt("redirect.org", "v=spf1 redirect=many.org")
+            .with_txt("small.org", "v=spf1 ip4:192.0.2.1 -all");
+
+        let resolver = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(answers);
+        for (opts, domain, expected) in [
+            (SpfOptions::default(), "many.org", SpfResult::PermError),
+            (SpfOptions::default(), "include.org", SpfResult::PermError),
+            (SpfOptions::default(), "redirect.org", SpfResult::PermError),
+            (SpfOptions::default(), "small.org", SpfResult::Pass),
+            (
+                SpfOptions::new().record_limits(256, 4096),
+                "many.org",
+                SpfResult::Fail,
+            ),
+            (
+                SpfOptions::new().record_limits(256, 16),
+                "small.org",
+                SpfResult::PermError,
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender_with_opts(
+                    "192.0.2.1".parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    &format!("jdoe@{domain}"),
+                    &opts,
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{domain}");
+        }
+
+        // Limits also apply to records that are already cached
+        let resolver = Resolver::new_system_conf().unwrap();
+        resolver.txt_add(
+            "small.org.",
+            Spf::parse(b"v=spf1 ip4:192.0.2.1 -all").unwrap(),
+            Instant::now() + Duration::new(3200, 0),
+        );
+        for (opts, expected) in [
+            (SpfOptions::default(), SpfResult::Pass),
+            (
+                SpfOptions::new().record_limits(256, 16),
+                SpfResult::PermError,
+            ),
+        ] {
+            let output = resolver
+                .verify_spf_sender_with_opts(
+                    "192.0.2.1".parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    "jdoe@small.org",
+                    &opts,
+                )
+                .await;
+            assert_eq!(output.result(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_zone_fixture() {
+        let mut zone_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        zone_file.push("resources");
+        zone_file.push("dns");
+        zone_file.push("spf-include-chain.zone");
+        let resolver = Resolver::new_system_conf().unwrap().with_dns_answers(
+            DnsAnswers::new()
+                .with_zone(&fs::read_to_string(zone_file).unwrap())
+                .unwrap(),
+        );
+
+        for (ip, domain, expected) in [
+            ("192.0.2.25", "example.org", SpfResult::Pass),
+            ("198.51.100.10", "example.org", SpfResult::Pass),
+            ("2001:db8::10", "example.org", SpfResult::Pass),
+            ("203.0.113.2", "example.org", SpfResult::Pass),
+            ("2001:db8:1::25", "example.org", SpfResult::Pass),
+            ("198.51.100.11", "example.org", SpfResult::Fail),
+            ("203.0.113.2", "esp.example.net", SpfResult::Pass),
+            ("192.0.2.25", "esp.example.net", SpfResult::Fail),
+            ("192.0.2.25", "loop.example.org", SpfResult::PermError),
+            ("192.0.2.25", "missing.example.org", SpfResult::PermError),
+        ] {
+            let output = resolver
+                .verify_spf_sender(
+                    ip.parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    &format!("jdoe@{domain}"),
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{ip} {domain}");
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_verify_helo_fallback() {
+        let answers = DnsAnswers::new()
+            .with_txt("mail.example.org", "v=spf1 ip4:192.0.2.1 -all")
+            .with_txt("example.org", "v=spf1 -all");
+
+        for (fallback, ip, sender, expected, expected_helo) in [
+            (
+                false,
+                "192.0.2.1",
+                "jdoe@norecord.org",
+                SpfResult::None,
+                None,
+            ),
+            (
+                true,
+                "192.0.2.1",
+                "jdoe@norecord.org",
+                SpfResult::None,
+                Some(SpfResult::Pass),
+            ),
+            (
+                true,
+                "192.0.2.2",
+                "jdoe@norecord.org",
+                SpfResult::None,
+                Some(SpfResult::Fail),
+            ),
+            (true, "192.0.2.1", "jdoe@example.org", SpfResult::Fail, None),
+            (true, "192.0.2.1", "", SpfResult::Pass, None),
+        ] {
+            let output = Resolver::new_system_conf()
+                .unwrap()
+                .with_dns_answers(answers.clone())
+                .verify_spf_sender_with_opts(
+                    ip.parse().unwrap(),
+                    "mail.example.org",
+                    "localdomain.org",
+                    sender,
+                    &SpfOptions::new().helo_fallback(fallback),
+                )
+                .await;
+            assert_eq!(output.result(), expected, "{fallback} {ip} {sender}");
+            assert_eq!(
+                output.helo_result(),
+                expected_helo,
+                "{fallback} {ip} {sender}"
+            );
+            assert_eq!(
+                output.domain(),
+                split_address(sender).map_or("mail.example.org", |(_, d)| d)
+            );
+        }
+
+        // The HELO identity is not checked twice
+        let (output, trace) = Resolver::new_system_conf()
+            .unwrap()
+            .with_dns_answers(answers)
+            .verify_spf_with_trace(
+                "192.0.2.1".parse().unwrap(),
+                "mail.example.org",
+                "localdomain.org",
+                "jdoe@norecord.org",
+                &SpfOptions::new().helo_fallback(true),
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::None);
+        assert_eq!(output.helo_result(), Some(SpfResult::Pass));
+        assert_eq!(
+            trace
+                .iter()
+                .filter(|event| matches!(event, SpfTraceEvent::Record { domain, .. } if domain == "mail.example.org"))
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn spf_verify_trace() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        for (domain, record) in [
+            ("mx.example.org.", "v=spf1 a -all"),
+            (
+                "example.org.",
+                "v=spf1 ip4:10.0.0.0/24 include:_spf.example.org -all",
+            ),
+            ("_spf.example.org.", "v=spf1 ip4:192.168.0.0/16 ~all"),
+        ] {
+            resolver.txt_add(domain, Spf::parse(record.as_bytes()).unwrap(), valid_until);
+        }
+        resolver.ipv4_add(
+            "mx.example.org.",
+            vec![Ipv4Addr::new(192, 168, 1, 1)],
+            valid_until,
+        );
+
+        let directive =
+            |domain: &str, record: &str, pos: usize, matched: bool| SpfTraceEvent::Directive {
+                domain: domain.to_string(),
+                directive: Spf::parse(record.as_bytes()).unwrap().directives[pos].clone(),
+                matched,
+            };
+        let record = |domain: &str| SpfTraceEvent::Record {
+            domain: domain.to_string(),
+            error: None,
+        };
+
+        let (output, trace) = resolver
+            .verify_spf_with_trace(
+                "192.168.1.1".parse().unwrap(),
+                "mx.example.org",
+                "host.example.com",
+                "jdoe@example.org",
+                &SpfOptions::default(),
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::Pass);
+        assert_eq!(
+            trace,
+            [
+                record("mx.example.org"),
+                directive("mx.example.org", "v=spf1 a -all", 0, true),
+                record("example.org"),
+                directive("example.org", "v=spf1 ip4:10.0.0.0/24", 0, false),
+                record("_spf.example.org"),
+                directive("_spf.example.org", "v=spf1 ip4:192.168.0.0/16", 0, true),
+                directive("example.org", "v=spf1 include:_spf.example.org", 0, true),
+            ]
+        );
+        assert_eq!(
+            output,
+            resolver
+                .verify_spf(
+                    "192.168.1.1".parse().unwrap(),
+                    "mx.example.org",
+                    "host.example.com",
+                    "jdoe@example.org",
+                )
+                .await
+        );
+
+        // Failed lookups are recorded
+        let (output, trace) = resolver
+            .verify_spf_with_trace(
+                "192.168.1.1".parse().unwrap(),
+                "mx.example.org",
+                "host.example.com",
+                "jdoe@unknown.example.org",
+                &SpfOptions::default(),
+            )
+            .await;
+        assert_eq!(output.result(), SpfResult::None);
+        assert!(matches!(
+            trace.last(),
+            Some(SpfTraceEvent::Record {
+                domain,
+                error: Some(Error::DnsRecordNotFound(_)),
+            }) if domain == "unknown.example.org"
+        ));
+    }
 }